@@ -1,5 +1,11 @@
 pub mod client;
 pub mod config;
+pub mod error;
+pub mod injection;
 pub mod models;
+pub mod prompts;
 pub mod providers;
+pub mod redact;
+pub mod tools;
 pub mod traits;
+pub mod usage;
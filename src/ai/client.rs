@@ -1,17 +1,51 @@
-use crate::ai::config::{GeminiConfig, OllamaConfig, OpenAiConfig};
+use crate::ai::config::{
+    AnthropicConfig, GeminiConfig, GenerationParams, OllamaConfig, OpenAiConfig, ProviderProfile,
+};
+use crate::ai::error::AiError;
+use crate::ai::models::{ChatMessage, ChatReply, TokenCount, ToolSpec, Usage};
 use crate::ai::providers::{
-    gemini::GeminiProvider, ollama::OllamaProvider, openai::OpenAiProvider,
+    anthropic::AnthropicProvider, gemini::GeminiProvider, ollama::OllamaProvider,
+    openai::OpenAiProvider,
 };
+use crate::ai::redact::{self, Redactor};
 use crate::ai::traits::AiProviderTrait;
+use crate::ai::usage::{self, CallContext};
 use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Attempts per provider for errors that may go away: rate limits, 5xx and timeouts.
+const MAX_ATTEMPTS: u32 = 3;
+/// Delay before the first retry, doubled for every further attempt.
+const BASE_DELAY: Duration = Duration::from_secs(1);
+/// Longest `Retry-After` honoured before moving on to the next provider.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+type Provider = Box<dyn AiProviderTrait + Send + Sync>;
+
+/// A request that can be sent to any provider of the chain.
+#[derive(Clone, Copy)]
+enum Request<'a> {
+    Ask(&'a str),
+    Chat {
+        messages: &'a [ChatMessage],
+        tools: &'a [ToolSpec],
+    },
+    Stream {
+        messages: &'a [ChatMessage],
+        tools: &'a [ToolSpec],
+        on_delta: &'a (dyn for<'b> Fn(&'b str) + Send + Sync),
+    },
+}
 
 /// The main client for interacting with AI providers.
 ///
-/// This client abstracts the underlying provider implementation (Ollama, OpenAI, Gemini)
+/// This client abstracts the underlying provider implementation (Ollama, OpenAI, Gemini, Anthropic)
 /// allowing the rest of the application to interact with AI in a uniform way.
+/// Failed requests are retried with backoff, then passed on to the configured fallbacks.
 pub struct AiClient {
-    /// The specific AI provider implementation.
-    provider: tokio::sync::RwLock<Box<dyn AiProviderTrait + Send + Sync>>,
+    /// The active provider followed by its fallbacks, in order, with their names.
+    providers: tokio::sync::RwLock<Vec<(String, Provider)>>,
     /// Database pool for saving/loading configuration.
     pool: crate::db::DbPool,
 }
@@ -31,37 +65,42 @@ impl AiClient {
             global_conf.provider
         };
 
-        let provider: Box<dyn AiProviderTrait + Send + Sync> =
-            match provider_str.to_lowercase().as_str() {
-                "openai" => Box::new(OpenAiProvider::new(OpenAiConfig::load(&pool).await)),
-                "gemini" => Box::new(GeminiProvider::new(GeminiConfig::load(&pool).await)),
-                _ => Box::new(OllamaProvider::new(OllamaConfig::load(&pool).await)),
-            };
+        let (name, provider) = match build_provider(&pool, &provider_str).await {
+            Ok(p) => (provider_str, p),
+            Err(e) => {
+                eprintln!("{}, falling back to Ollama", e);
+                let ollama: Provider =
+                    Box::new(OllamaProvider::new(OllamaConfig::load(&pool).await));
+                ("ollama".to_string(), ollama)
+            }
+        };
+        let providers = build_chain(&pool, name, provider, &global_conf.fallbacks).await;
 
         AiClient {
-            provider: tokio::sync::RwLock::new(provider),
+            providers: tokio::sync::RwLock::new(providers),
             pool,
         }
     }
 
-    /// Switches the active AI provider.
+    /// Switches the active AI provider (a built-in provider or a profile name).
     pub async fn set_provider(&self, name: &str) -> Result<String, String> {
-        let new_provider: Box<dyn AiProviderTrait + Send + Sync> =
-            match name.to_lowercase().as_str() {
-                "openai" => Box::new(OpenAiProvider::new(OpenAiConfig::load(&self.pool).await)),
-                "gemini" => Box::new(GeminiProvider::new(GeminiConfig::load(&self.pool).await)),
-                "ollama" => Box::new(OllamaProvider::new(OllamaConfig::load(&self.pool).await)),
-                _ => return Err(format!("Unknown provider: {}", name)),
-            };
+        use crate::ai::config::GlobalConfig;
 
-        let mut guard = self.provider.write().await;
-        *guard = new_provider;
+        let new_provider = build_provider(&self.pool, name).await?;
+        let mut config = GlobalConfig::load(&self.pool).await;
+        let chain = build_chain(
+            &self.pool,
+            name.to_string(),
+            new_provider,
+            &config.fallbacks,
+        )
+        .await;
+
+        let mut guard = self.providers.write().await;
+        *guard = chain;
 
         // Persist
-        use crate::ai::config::GlobalConfig;
-        let config = GlobalConfig {
-            provider: name.to_string(),
-        };
+        config.provider = name.to_string();
 
         if let Err(e) = config.save(&self.pool).await {
             return Ok(format!(
@@ -88,43 +127,397 @@ impl AiClient {
         self.set_provider(&provider_str).await.map(|_| ())
     }
 
+    /// Sends `request` to the active provider, retrying errors that may go away,
+    /// then to each fallback in turn.
+    ///
+    /// Returns the reply with a notice when a fallback answered. Once part of a reply
+    /// has been streamed, a failure is returned as is rather than retried.
+    ///
+    /// Each provider gets its own generation parameters for the purpose of `context`,
+    /// and the request with its secrets redacted unless it is marked local only.
+    /// The usage of the successful call is recorded for `context`. While a daily spend
+    /// cap is set, models without a known price are skipped, and once it is reached only
+    /// providers whose model is free are tried.
+    async fn with_fallback(
+        &self,
+        request: Request<'_>,
+        context: &CallContext,
+    ) -> Result<(ChatReply, Option<String>), AiError> {
+        let guard = self.providers.read().await;
+        let streamed = AtomicBool::new(false);
+        let mut failures: Vec<String> = Vec::new();
+        let mut first_error: Option<AiError> = None;
+        let capped = usage::daily_cap(&self.pool).await.is_some();
+        let cap_reached = usage::cap_reached(&self.pool).await;
+        let redactor = Redactor::load(&self.pool).await;
+
+        for (name, provider) in guard.iter() {
+            if streamed.load(Ordering::Relaxed) {
+                break;
+            }
+            if capped {
+                let price = usage::provider_price(&self.pool, name, provider.model()).await;
+                let error = match (price, cap_reached) {
+                    (None, _) => Some(AiError::BudgetExceeded(format!(
+                        "No price known for {}, so the daily cap can't be enforced. Set one with /usage price {} <in> <out>",
+                        provider.model(),
+                        provider.model()
+                    ))),
+                    (Some((input, output)), Some((spent, cap))) if input > 0.0 || output > 0.0 => {
+                        Some(AiError::BudgetExceeded(format!(
+                            "Daily AI spend cap of ${:.2} reached (${:.2} spent today)",
+                            cap, spent
+                        )))
+                    }
+                    _ => None,
+                };
+                if let Some(error) = error {
+                    failures.push(format!("{} skipped: {}", name, error));
+                    first_error.get_or_insert(error);
+                    continue;
+                }
+            }
+            let params = GenerationParams::load(&self.pool, name, context.purpose).await;
+            let (question, messages, redacted) = if redact::is_local(&self.pool, name).await {
+                (None, None, 0)
+            } else {
+                redact_request(&redactor, &request)
+            };
+            let sent = match (request, &question, &messages) {
+                (Request::Ask(_), Some(question), _) => Request::Ask(question),
+                (Request::Chat { tools, .. }, _, Some(messages)) => {
+                    Request::Chat { messages, tools }
+                }
+                (
+                    Request::Stream {
+                        tools, on_delta, ..
+                    },
+                    _,
+                    Some(messages),
+                ) => Request::Stream {
+                    messages,
+                    tools,
+                    on_delta,
+                },
+                (request, _, _) => request,
+            };
+            let mut attempt = 0;
+            let result = loop {
+                let result = match &sent {
+                    Request::Ask(question) => {
+                        provider
+                            .ask(question, &params)
+                            .await
+                            .map(|content| ChatReply {
+                                content,
+                                ..ChatReply::default()
+                            })
+                    }
+                    Request::Chat { messages, tools } => {
+                        provider.chat_with_tools(messages, tools, &params).await
+                    }
+                    Request::Stream {
+                        messages,
+                        tools,
+                        on_delta,
+                    } => {
+                        let on_delta = |text: &str| {
+                            streamed.store(true, Ordering::Relaxed);
+                            on_delta(text);
+                        };
+                        provider
+                            .chat_stream(messages, tools, &params, &on_delta)
+                            .await
+                    }
+                };
+                // Waits the provider asks for that are too long are left to the fallbacks
+                let delay = match &result {
+                    Err(e) if e.is_transient() => match e.retry_after() {
+                        Some(wait) if wait > MAX_RETRY_AFTER => None,
+                        Some(wait) => Some(wait),
+                        None => Some(backoff(attempt)),
+                    },
+                    _ => None,
+                };
+                match delay {
+                    Some(delay)
+                        if attempt + 1 < MAX_ATTEMPTS && !streamed.load(Ordering::Relaxed) =>
+                    {
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    }
+                    _ => break result,
+                }
+            };
+
+            match result {
+                Ok(mut reply) => {
+                    reply.redacted = redacted;
+                    self.record_usage(context, name, provider.as_ref(), &sent, &reply)
+                        .await;
+                    let notice = (!failures.is_empty()).then(|| {
+                        format!(
+                            "Answered by {} — {}",
+                            provider.get_info(),
+                            failures.join("; ")
+                        )
+                    });
+                    return Ok((reply, notice));
+                }
+                Err(e) => {
+                    failures.push(format!("{} failed: {}", name, e));
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        // The active provider's error tells the user what to fix; fallbacks are noted
+        match first_error {
+            Some(error) if failures.len() > 1 => Err(error.with_note(&failures[1..].join("; "))),
+            Some(error) => Err(error),
+            None => Err(AiError::Other("No AI provider configured".to_string())),
+        }
+    }
+
+    /// Stores the tokens of a call, estimating them when the provider didn't report any.
+    async fn record_usage(
+        &self,
+        context: &CallContext,
+        name: &str,
+        provider: &(dyn AiProviderTrait + Send + Sync),
+        request: &Request<'_>,
+        reply: &ChatReply,
+    ) {
+        let (tokens, estimated) = match reply.usage {
+            Some(tokens) => (tokens, false),
+            None => {
+                let prompt = match request {
+                    Request::Ask(question) => question.to_string(),
+                    Request::Chat { messages, .. } | Request::Stream { messages, .. } => messages
+                        .iter()
+                        .map(|m| m.content.as_str())
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                };
+                let count = |text: String| async move {
+                    match provider.count_tokens(&text).await {
+                        Ok(count) => count.tokens as u64,
+                        Err(_) => (text.len() / 4) as u64,
+                    }
+                };
+                let tokens = Usage {
+                    prompt_tokens: count(prompt).await,
+                    completion_tokens: count(reply.content.clone()).await,
+                };
+                (tokens, true)
+            }
+        };
+        usage::record(
+            &self.pool,
+            context,
+            name,
+            provider.model(),
+            tokens,
+            estimated,
+        )
+        .await;
+    }
+
     /// Asks the AI a question.
-    pub async fn ask(&self, question: &str) -> Result<String, String> {
-        let guard = self.provider.read().await;
-        guard.ask(question).await
+    pub async fn ask(&self, question: &str, context: &CallContext) -> Result<String, AiError> {
+        self.with_fallback(Request::Ask(question), context)
+            .await
+            .map(|(reply, _)| reply.content)
     }
 
-    /// Asks the AI a question with additional context.
-    pub async fn ask_with_context(&self, question: &str, context: &str) -> Result<String, String> {
-        let prompt = format!("Context:\n{}\n\nQuestion: {}", context, question);
-        let guard = self.provider.read().await;
-        guard.ask(&prompt).await
+    /// Asks the AI a question with additional context, returning the whole reply.
+    pub async fn ask_with_context(
+        &self,
+        question: &str,
+        extra: &str,
+        context: &CallContext,
+    ) -> Result<ChatReply, AiError> {
+        // Nothing is run from the answer, so suspicious context only gets its warning
+        let (extra, _) = crate::ai::injection::wrap("the context", extra);
+        let prompt = format!("Context:\n{}\n\nQuestion: {}", extra, question);
+        self.with_fallback(Request::Ask(&prompt), context)
+            .await
+            .map(|(reply, _)| reply)
     }
 
-    /// Conversations with history.
-    pub async fn chat(
+    /// Conversations with history where the model may call the given tools.
+    ///
+    /// The second value tells which provider answered when it wasn't the active one.
+    pub async fn chat_with_tools(
         &self,
-        messages: &[crate::ai::models::ChatMessage],
-    ) -> Result<String, String> {
-        let guard = self.provider.read().await;
-        guard.chat(messages).await
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+        context: &CallContext,
+    ) -> Result<(ChatReply, Option<String>), AiError> {
+        self.with_fallback(Request::Chat { messages, tools }, context)
+            .await
+    }
+
+    /// Like `chat_with_tools`, streaming the text to `on_delta` as it arrives.
+    pub async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+        on_delta: &(dyn for<'a> Fn(&'a str) + Send + Sync),
+        context: &CallContext,
+    ) -> Result<(ChatReply, Option<String>), AiError> {
+        self.with_fallback(
+            Request::Stream {
+                messages,
+                tools,
+                on_delta,
+            },
+            context,
+        )
+        .await
     }
 
     /// Lists the available models for the current provider.
-    pub async fn list_models(&self) -> Result<Vec<String>, String> {
-        let guard = self.provider.read().await;
-        guard.list_models().await
+    pub async fn list_models(&self) -> Result<Vec<String>, AiError> {
+        let guard = self.providers.read().await;
+        guard[0].1.list_models().await
+    }
+
+    /// Returns the token count of the given text, and whether it is exact.
+    ///
+    /// Some providers count remotely, so the text is redacted like any request.
+    pub async fn count_tokens(&self, text: &str) -> Result<TokenCount, AiError> {
+        let guard = self.providers.read().await;
+        let (name, provider) = &guard[0];
+        if redact::is_local(&self.pool, name).await {
+            return provider.count_tokens(text).await;
+        }
+        let (text, _) = Redactor::load(&self.pool).await.redact(text);
+        provider.count_tokens(&text).await
     }
 
-    /// Returns the estimated token count for the given text.
-    pub async fn count_tokens(&self, text: &str) -> Result<usize, String> {
-        let guard = self.provider.read().await;
-        guard.count_tokens(text).await
+    /// Returns the context window of the current model, in tokens.
+    pub async fn context_window(&self) -> usize {
+        let guard = self.providers.read().await;
+        guard[0].1.context_window()
+    }
+
+    /// Returns the name of the active provider (a built-in provider or a profile name).
+    pub async fn provider_name(&self) -> String {
+        let guard = self.providers.read().await;
+        guard[0].0.clone()
     }
 
     /// Returns information about the current AI provider and configuration.
     pub async fn get_provider_info(&self) -> String {
-        let guard = self.provider.read().await;
-        guard.get_info()
+        let guard = self.providers.read().await;
+        guard[0].1.get_info()
+    }
+}
+
+/// Redacts the text of a request: the question, or the messages of a chat.
+///
+/// The count covers the messages after the model's last reply only, so secrets
+/// already reported in earlier turns of a conversation aren't counted again.
+fn redact_request(
+    redactor: &Redactor,
+    request: &Request<'_>,
+) -> (Option<String>, Option<Vec<ChatMessage>>, usize) {
+    match request {
+        Request::Ask(question) => {
+            let (question, count) = redactor.redact(question);
+            (Some(question), None, count)
+        }
+        Request::Chat { messages, .. } | Request::Stream { messages, .. } => {
+            let new_from = messages
+                .iter()
+                .rposition(|m| m.role == "assistant")
+                .map_or(0, |i| i + 1);
+            let mut count = 0;
+            let messages = messages
+                .iter()
+                .enumerate()
+                .map(|(i, message)| {
+                    let (content, redacted) = redactor.redact(&message.content);
+                    if i >= new_from {
+                        count += redacted;
+                    }
+                    ChatMessage::new(message.role.clone(), content)
+                })
+                .collect();
+            (None, Some(messages), count)
+        }
+    }
+}
+
+/// Puts the active provider in front of the fallbacks that can be built.
+async fn build_chain(
+    pool: &crate::db::DbPool,
+    name: String,
+    provider: Provider,
+    fallbacks: &[String],
+) -> Vec<(String, Provider)> {
+    let mut chain = vec![(name, provider)];
+    for fallback in fallbacks {
+        if chain.iter().any(|(n, _)| n == fallback) {
+            continue;
+        }
+        match build_provider(pool, fallback).await {
+            Ok(p) => chain.push((fallback.clone(), p)),
+            Err(e) => eprintln!("Skipping fallback provider: {}", e),
+        }
     }
+    chain
+}
+
+/// Exponential backoff with up to 50% random jitter, so clients don't retry in lockstep.
+fn backoff(attempt: u32) -> Duration {
+    let delay = BASE_DELAY * 2u32.pow(attempt);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+    delay + delay.mul_f64(f64::from(nanos % 1000) / 2000.0)
+}
+
+/// Creates the provider for a built-in name or a named profile.
+async fn build_provider(pool: &crate::db::DbPool, name: &str) -> Result<Provider, String> {
+    let provider: Provider = match name.to_lowercase().as_str() {
+        "openai" => Box::new(OpenAiProvider::new(OpenAiConfig::load(pool).await)),
+        "gemini" => Box::new(GeminiProvider::new(GeminiConfig::load(pool).await)),
+        "ollama" => Box::new(OllamaProvider::new(OllamaConfig::load(pool).await)),
+        "anthropic" => Box::new(AnthropicProvider::new(AnthropicConfig::load(pool).await)),
+        _ => {
+            let profile = ProviderProfile::load(pool, name)
+                .await
+                .ok_or_else(|| format!("Unknown provider: {}", name))?;
+            match profile.kind.as_str() {
+                "openai" => Box::new(OpenAiProvider::with_headers(
+                    OpenAiConfig {
+                        api_key: profile.api_key,
+                        model: profile.model,
+                        base_url: profile.base_url,
+                    },
+                    &profile.headers,
+                )),
+                "ollama" => Box::new(OllamaProvider::with_headers(
+                    OllamaConfig {
+                        base_url: profile.base_url,
+                        model: profile.model,
+                    },
+                    &profile.headers,
+                )),
+                "anthropic" => Box::new(AnthropicProvider::with_headers(
+                    AnthropicConfig {
+                        api_key: profile.api_key,
+                        model: profile.model,
+                        base_url: profile.base_url,
+                    },
+                    &profile.headers,
+                )),
+                other => return Err(format!("Profile '{}' has an unknown type: {}", name, other)),
+            }
+        }
+    };
+    Ok(provider)
 }
@@ -26,10 +26,21 @@ pub struct GeminiConfig {
     pub base_url: String,
 }
 
+/// Configuration settings for the Anthropic provider.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AnthropicConfig {
+    pub api_key: String,
+    pub model: String,
+    pub base_url: String,
+}
+
 /// Global settings to track current provider.
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct GlobalConfig {
     pub provider: String,
+    /// Providers tried in order when the current one fails.
+    #[serde(default)]
+    pub fallbacks: Vec<String>,
 }
 
 impl GlobalConfig {
@@ -42,7 +53,23 @@ impl GlobalConfig {
         .unwrap_or(None);
 
         if let Some((provider,)) = row {
-            GlobalConfig { provider }
+            let fallbacks = sqlx::query_as::<_, (String,)>(
+                "SELECT value FROM ai_configs WHERE provider = 'global' AND key = 'fallbacks'",
+            )
+            .fetch_optional(pool)
+            .await
+            .unwrap_or(None)
+            .map(|r| {
+                r.0.split(',')
+                    .filter(|name| !name.is_empty())
+                    .map(|name| name.to_string())
+                    .collect()
+            })
+            .unwrap_or_default();
+            GlobalConfig {
+                provider,
+                fallbacks,
+            }
         } else {
             // Fallback to file/default
             Self::load_from_file()
@@ -51,15 +78,15 @@ impl GlobalConfig {
 
     fn load_from_file() -> Self {
         let path = "config/ai/settings.json";
+        let default = GlobalConfig {
+            provider: "ollama".to_string(),
+            fallbacks: Vec::new(),
+        };
         if Path::new(path).exists() {
             let content = fs::read_to_string(path).unwrap_or_default();
-            serde_json::from_str(&content).unwrap_or(GlobalConfig {
-                provider: "ollama".to_string(),
-            })
+            serde_json::from_str(&content).unwrap_or(default)
         } else {
-            GlobalConfig {
-                provider: "ollama".to_string(),
-            }
+            default
         }
     }
 
@@ -68,6 +95,10 @@ impl GlobalConfig {
             .bind(&self.provider)
             .execute(pool)
             .await?;
+        sqlx::query("INSERT OR REPLACE INTO ai_configs (provider, key, value) VALUES ('global', 'fallbacks', ?)")
+            .bind(self.fallbacks.join(","))
+            .execute(pool)
+            .await?;
         Ok(())
     }
 }
@@ -250,3 +281,359 @@ impl GeminiConfig {
         Ok(())
     }
 }
+
+impl AnthropicConfig {
+    pub async fn load(pool: &Pool<Sqlite>) -> Self {
+        let key = sqlx::query_as::<_, (String,)>(
+            "SELECT value FROM ai_configs WHERE provider = 'anthropic' AND key = 'api_key'",
+        )
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None)
+        .map(|r| r.0)
+        .unwrap_or_default();
+
+        if key.is_empty() {
+            return Self::load_from_file();
+        }
+
+        let model = sqlx::query_as::<_, (String,)>(
+            "SELECT value FROM ai_configs WHERE provider = 'anthropic' AND key = 'model'",
+        )
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None)
+        .map(|r| r.0)
+        .unwrap_or_else(|| "claude-sonnet-4-5".to_string());
+
+        let base_url = sqlx::query_as::<_, (String,)>(
+            "SELECT value FROM ai_configs WHERE provider = 'anthropic' AND key = 'base_url'",
+        )
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None)
+        .map(|r| r.0)
+        .unwrap_or_else(|| "https://api.anthropic.com/v1".to_string());
+
+        AnthropicConfig {
+            api_key: key,
+            model,
+            base_url,
+        }
+    }
+
+    fn load_from_file() -> Self {
+        let default = AnthropicConfig {
+            api_key: "".to_string(),
+            model: "claude-sonnet-4-5".to_string(),
+            base_url: "https://api.anthropic.com/v1".to_string(),
+        };
+        let path = "config/ai/anthropic.json";
+        if Path::new(path).exists() {
+            let content = fs::read_to_string(path).expect("Failed to read anthropic.json");
+            serde_json::from_str(&content).unwrap_or(default)
+        } else {
+            default
+        }
+    }
+
+    pub async fn save(&self, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT OR REPLACE INTO ai_configs (provider, key, value) VALUES ('anthropic', 'api_key', ?)")
+            .bind(&self.api_key).execute(pool).await?;
+        sqlx::query("INSERT OR REPLACE INTO ai_configs (provider, key, value) VALUES ('anthropic', 'model', ?)")
+            .bind(&self.model).execute(pool).await?;
+        sqlx::query("INSERT OR REPLACE INTO ai_configs (provider, key, value) VALUES ('anthropic', 'base_url', ?)")
+            .bind(&self.base_url).execute(pool).await?;
+        Ok(())
+    }
+}
+
+/// Names reserved for the built-in providers.
+pub const BUILTIN_PROVIDERS: [&str; 4] = ["ollama", "openai", "gemini", "anthropic"];
+
+/// A named provider profile, e.g. a local inference server.
+///
+/// Stored in `ai_configs` under the provider `profile:<name>`.
+#[derive(Debug, Clone)]
+pub struct ProviderProfile {
+    pub name: String,
+    /// Backing implementation: `openai` (any OpenAI-compatible API), `ollama` or `anthropic`.
+    pub kind: String,
+    pub base_url: String,
+    pub api_key: String,
+    pub model: String,
+    /// Extra HTTP headers sent with every request.
+    pub headers: Vec<(String, String)>,
+}
+
+impl ProviderProfile {
+    /// Loads a profile by name.
+    pub async fn load(pool: &Pool<Sqlite>, name: &str) -> Option<Self> {
+        let rows: Vec<(String, String)> =
+            sqlx::query_as("SELECT key, value FROM ai_configs WHERE provider = ?")
+                .bind(format!("profile:{}", name))
+                .fetch_all(pool)
+                .await
+                .unwrap_or_default();
+        if rows.is_empty() {
+            return None;
+        }
+
+        let get = |key: &str| {
+            rows.iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.clone())
+                .unwrap_or_default()
+        };
+        let headers: Vec<(String, String)> =
+            serde_json::from_str(&get("headers")).unwrap_or_default();
+
+        Some(ProviderProfile {
+            name: name.to_string(),
+            kind: get("type"),
+            base_url: get("base_url"),
+            api_key: get("api_key"),
+            model: get("model"),
+            headers,
+        })
+    }
+
+    /// Lists the names of all profiles.
+    pub async fn list(pool: &Pool<Sqlite>) -> Result<Vec<String>, sqlx::Error> {
+        let rows: Vec<(String,)> = sqlx::query_as(
+            "SELECT DISTINCT provider FROM ai_configs WHERE provider LIKE 'profile:%' ORDER BY provider",
+        )
+        .fetch_all(pool)
+        .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|(p,)| p.strip_prefix("profile:").map(str::to_string))
+            .collect())
+    }
+
+    pub async fn save(&self, pool: &Pool<Sqlite>) -> Result<(), sqlx::Error> {
+        let provider = format!("profile:{}", self.name);
+        let headers = serde_json::to_string(&self.headers).unwrap_or_default();
+        for (key, value) in [
+            ("type", self.kind.as_str()),
+            ("base_url", self.base_url.as_str()),
+            ("api_key", self.api_key.as_str()),
+            ("model", self.model.as_str()),
+            ("headers", headers.as_str()),
+        ] {
+            sqlx::query(
+                "INSERT OR REPLACE INTO ai_configs (provider, key, value) VALUES (?, ?, ?)",
+            )
+            .bind(&provider)
+            .bind(key)
+            .bind(value)
+            .execute(pool)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Deletes a profile. Returns `false` if it didn't exist.
+    pub async fn delete(pool: &Pool<Sqlite>, name: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM ai_configs WHERE provider = ?")
+            .bind(format!("profile:{}", name))
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// One-line description; the API key is never shown.
+    pub fn describe(&self) -> String {
+        let key = if self.api_key.is_empty() {
+            "no key"
+        } else {
+            "key set"
+        };
+        format!(
+            "{} ({}, {}, model {}, {}, {} extra headers)",
+            self.name,
+            self.kind,
+            self.base_url,
+            self.model,
+            key,
+            self.headers.len()
+        )
+    }
+}
+
+/// The generation parameters that can be set, in display order.
+pub const GENERATION_PARAMS: [&str; 6] = [
+    "temperature",
+    "max_tokens",
+    "top_p",
+    "stop",
+    "num_ctx",
+    "keep_alive",
+];
+
+/// Kinds of request that may override the parameters of their provider.
+pub const PURPOSES: [&str; 7] = [
+    "chat",
+    "summary",
+    "analysis",
+    "digest",
+    "investigate",
+    "report",
+    "howto",
+];
+
+/// Sampling and runtime options sent with each request; unset ones are left to the API.
+///
+/// Stored in `ai_configs` under the provider (or `profile:<name>`) as `param:<name>`,
+/// and as `param:<purpose>:<name>` for the values that apply to one kind of request.
+#[derive(Debug, Clone, Default)]
+pub struct GenerationParams {
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<u32>,
+    pub top_p: Option<f64>,
+    pub stop: Vec<String>,
+    /// Ollama only: context window to load the model with.
+    pub num_ctx: Option<u32>,
+    /// Ollama only: how long the model stays loaded after a request, e.g. `10m`.
+    pub keep_alive: Option<String>,
+}
+
+impl GenerationParams {
+    /// Loads the parameters of `provider`, with those of `purpose` taking precedence.
+    pub async fn load(pool: &Pool<Sqlite>, provider: &str, purpose: Option<&str>) -> Self {
+        let rows: Vec<(String, String)> = sqlx::query_as(
+            "SELECT key, value FROM ai_configs WHERE provider = ? AND key LIKE 'param:%'",
+        )
+        .bind(config_provider(provider))
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default();
+
+        let mut params = GenerationParams::default();
+        for scope in [None, purpose] {
+            for (key, value) in &rows {
+                let key = &key["param:".len()..];
+                let name = match (scope, key.split_once(':')) {
+                    (None, None) => key,
+                    (Some(purpose), Some((p, name))) if p == purpose => name,
+                    _ => continue,
+                };
+                // Values are checked when saved; one edited by hand is ignored
+                if let Err(e) = params.set(name, value) {
+                    eprintln!("Ignoring {} for {}: {}", name, provider, e);
+                }
+            }
+        }
+        params
+    }
+
+    /// Sets one parameter from its text form, checking its range.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let number = |max: f64| match value.parse::<f64>() {
+            Ok(n) if (0.0..=max).contains(&n) => Ok(n),
+            _ => Err(format!("{} must be a number between 0 and {}", name, max)),
+        };
+        let count = || match value.parse::<u32>() {
+            Ok(n) if n > 0 => Ok(n),
+            _ => Err(format!("{} must be a positive whole number", name)),
+        };
+        match name {
+            "temperature" => self.temperature = Some(number(2.0)?),
+            "top_p" => self.top_p = Some(number(1.0)?),
+            "max_tokens" => self.max_tokens = Some(count()?),
+            "num_ctx" => self.num_ctx = Some(count()?),
+            "stop" => {
+                self.stop = serde_json::from_str(value)
+                    .map_err(|_| "stop must be a list of sequences".to_string())?
+            }
+            "keep_alive" => {
+                let valid = value.parse::<i64>().is_ok()
+                    || value
+                        .strip_suffix(['s', 'm', 'h'])
+                        .is_some_and(|n| n.parse::<u32>().is_ok());
+                if !valid {
+                    return Err("keep_alive must be a duration such as 30s, 10m or 1h, or a number of seconds (-1 keeps the model loaded)".to_string());
+                }
+                self.keep_alive = Some(value.to_string());
+            }
+            _ => {
+                return Err(format!(
+                    "Unknown parameter: {}. Use one of: {}",
+                    name,
+                    GENERATION_PARAMS.join(", ")
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Stores one parameter of `provider`, for `purpose` only when set. `None` removes it.
+    ///
+    /// `stop` is given as its list of sequences encoded as JSON.
+    pub async fn save(
+        pool: &Pool<Sqlite>,
+        provider: &str,
+        purpose: Option<&str>,
+        name: &str,
+        value: Option<&str>,
+    ) -> Result<(), String> {
+        if !GENERATION_PARAMS.contains(&name) {
+            return Err(format!(
+                "Unknown parameter: {}. Use one of: {}",
+                name,
+                GENERATION_PARAMS.join(", ")
+            ));
+        }
+        let key = match purpose {
+            Some(purpose) => format!("param:{}:{}", purpose, name),
+            None => format!("param:{}", name),
+        };
+        let query = match value {
+            Some(value) => {
+                GenerationParams::default().set(name, value)?;
+                sqlx::query(
+                    "INSERT OR REPLACE INTO ai_configs (provider, key, value) VALUES (?, ?, ?)",
+                )
+                .bind(config_provider(provider))
+                .bind(key)
+                .bind(value)
+            }
+            None => sqlx::query("DELETE FROM ai_configs WHERE provider = ? AND key = ?")
+                .bind(config_provider(provider))
+                .bind(key),
+        };
+        query.execute(pool).await.map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// The stored values of `provider`, as `(purpose, name, value)` sorted by purpose.
+    pub async fn list(
+        pool: &Pool<Sqlite>,
+        provider: &str,
+    ) -> Result<Vec<(Option<String>, String, String)>, sqlx::Error> {
+        let rows: Vec<(String, String)> = sqlx::query_as(
+            "SELECT key, value FROM ai_configs WHERE provider = ? AND key LIKE 'param:%' ORDER BY key",
+        )
+        .bind(config_provider(provider))
+        .fetch_all(pool)
+        .await?;
+        let mut values: Vec<(Option<String>, String, String)> = rows
+            .into_iter()
+            .map(|(key, value)| match key["param:".len()..].split_once(':') {
+                Some((purpose, name)) => (Some(purpose.to_string()), name.to_string(), value),
+                None => (None, key["param:".len()..].to_string(), value),
+            })
+            .collect();
+        values.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(values)
+    }
+}
+
+/// The `ai_configs` provider column of a built-in provider or a profile.
+fn config_provider(name: &str) -> String {
+    if BUILTIN_PROVIDERS.contains(&name) {
+        name.to_string()
+    } else {
+        format!("profile:{}", name)
+    }
+}
@@ -0,0 +1,244 @@
+use reqwest::StatusCode;
+use serde_json::Value;
+use std::fmt;
+use std::time::Duration;
+
+/// An error from an AI provider, classified so callers can tell the user what to do.
+#[derive(Debug, Clone)]
+pub enum AiError {
+    /// The API key is missing, invalid or lacks permission.
+    Auth(String),
+    /// Too many requests or an exhausted quota. `retry_after` is set when the provider says when to retry.
+    RateLimited {
+        message: String,
+        retry_after: Option<Duration>,
+    },
+    /// The prompt does not fit in the model's context window.
+    ContextTooLong(String),
+    /// The configured model doesn't exist or isn't available to this key.
+    ModelNotFound(String),
+    /// The provider refused to answer for safety reasons.
+    SafetyBlocked(String),
+    /// The daily spend cap is reached, or the model has no price under a cap, so the call is not made.
+    BudgetExceeded(String),
+    /// The provider could not be reached or the request timed out.
+    Network(String),
+    /// The response could not be understood.
+    Parse(String),
+    /// Any other error reported by the API, with its HTTP status.
+    Api { status: u16, message: String },
+    /// A failure without a better category, e.g. an empty reply.
+    Other(String),
+}
+
+impl AiError {
+    /// Builds the error for a non-2xx response, reading the provider's error JSON.
+    pub async fn from_response(res: reqwest::Response) -> Self {
+        let status = res.status();
+        let retry_after = res
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let body = res.text().await.unwrap_or_default();
+        Self::from_status(status, retry_after, &body)
+    }
+
+    /// Classifies an error response from its status and body.
+    ///
+    /// OpenAI, Anthropic and Gemini send `{"error": {"message", ...}}` with a code in
+    /// `code`, `type` or `status` respectively; Ollama sends `{"error": "<message>"}`.
+    pub fn from_status(status: StatusCode, retry_after: Option<Duration>, body: &str) -> Self {
+        let json: Value = serde_json::from_str(body).unwrap_or_default();
+        let error = &json["error"];
+        let detail = error["message"]
+            .as_str()
+            .or(error.as_str())
+            .unwrap_or(body)
+            .trim();
+        let message = if detail.is_empty() {
+            status.to_string()
+        } else {
+            format!("{} - {}", status, detail)
+        };
+        let code: String = [&error["code"], &error["type"], &error["status"]]
+            .iter()
+            .filter_map(|v| v.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let lower = detail.to_lowercase();
+
+        // Gemini reports a bad key as 400 INVALID_ARGUMENT with an API_KEY_INVALID reason
+        if status == StatusCode::UNAUTHORIZED
+            || status == StatusCode::FORBIDDEN
+            || code.contains("invalid_api_key")
+            || code.contains("authentication_error")
+            || body.contains("API_KEY_INVALID")
+        {
+            return AiError::Auth(message);
+        }
+        if status == StatusCode::TOO_MANY_REQUESTS
+            || code.contains("rate_limit")
+            || code.contains("RESOURCE_EXHAUSTED")
+        {
+            return AiError::RateLimited {
+                message,
+                retry_after: retry_after.or_else(|| gemini_retry_delay(error)),
+            };
+        }
+        if code.contains("context_length_exceeded")
+            || lower.contains("context length")
+            || lower.contains("context window")
+            || lower.contains("prompt is too long")
+            || lower.contains("maximum number of tokens")
+            || lower.contains("too many tokens")
+        {
+            return AiError::ContextTooLong(message);
+        }
+        if code.contains("content_filter") || code.contains("content_policy") {
+            return AiError::SafetyBlocked(message);
+        }
+        if status == StatusCode::NOT_FOUND
+            || code.contains("model_not_found")
+            || (lower.contains("model") && lower.contains("not found"))
+        {
+            return AiError::ModelNotFound(message);
+        }
+        AiError::Api {
+            status: status.as_u16(),
+            message,
+        }
+    }
+
+    /// Whether repeating the request may succeed: network errors, rate limits and 5xx.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            AiError::Network(_) | AiError::RateLimited { .. } => true,
+            AiError::Api { status, .. } => *status >= 500,
+            _ => false,
+        }
+    }
+
+    /// How long the provider asked to wait before retrying, if it said so.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            AiError::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+
+    /// Appends a note to the message, e.g. the failures of fallback providers.
+    pub fn with_note(mut self, note: &str) -> Self {
+        let message = match &mut self {
+            AiError::Auth(m)
+            | AiError::ContextTooLong(m)
+            | AiError::ModelNotFound(m)
+            | AiError::SafetyBlocked(m)
+            | AiError::BudgetExceeded(m)
+            | AiError::Network(m)
+            | AiError::Parse(m)
+            | AiError::Other(m)
+            | AiError::RateLimited { message: m, .. }
+            | AiError::Api { message: m, .. } => m,
+        };
+        message.push_str("; ");
+        message.push_str(note);
+        self
+    }
+
+    /// What the user can do about the error, if anything.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            AiError::Auth(_) => Some(
+                "API key invalid — use /config_key to set it (or /provider add for a profile)."
+                    .to_string(),
+            ),
+            AiError::RateLimited { retry_after, .. } => Some(format!(
+                "Rate limited by the provider — try again {}, or add a fallback with /provider fallback.",
+                match retry_after {
+                    Some(d) => format!("in {}s", d.as_secs().max(1)),
+                    None => "shortly".to_string(),
+                }
+            )),
+            AiError::ContextTooLong(_) => Some(
+                "The conversation is too long for this model — check /context, or /end the session and start a new one."
+                    .to_string(),
+            ),
+            AiError::ModelNotFound(_) => {
+                Some("Model not found — pick an available one with /models.".to_string())
+            }
+            AiError::SafetyBlocked(_) => Some(
+                "The provider blocked this answer for safety reasons — try rephrasing the request."
+                    .to_string(),
+            ),
+            AiError::BudgetExceeded(_) => Some(
+                "Blocked by the daily AI spend cap — see /usage, raise it with /usage cap, price the model with /usage price, or add a local fallback with /provider fallback."
+                    .to_string(),
+            ),
+            AiError::Network(_) => Some(
+                "Could not reach the provider — check that it is running and reachable (see /ai_info)."
+                    .to_string(),
+            ),
+            AiError::Parse(_) => Some(
+                "The provider's response could not be read — the API or model may not be compatible."
+                    .to_string(),
+            ),
+            AiError::Api { .. } | AiError::Other(_) => None,
+        }
+    }
+
+    /// The message shown in the chat: the hint first, then the provider's details.
+    pub fn user_message(&self) -> String {
+        match self.hint() {
+            Some(hint) => format!("❌ {}\n{}", hint, self),
+            None => format!("AI Error: {}", self),
+        }
+    }
+}
+
+impl fmt::Display for AiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AiError::Auth(m)
+            | AiError::ContextTooLong(m)
+            | AiError::ModelNotFound(m)
+            | AiError::SafetyBlocked(m)
+            | AiError::BudgetExceeded(m)
+            | AiError::Network(m)
+            | AiError::Other(m)
+            | AiError::RateLimited { message: m, .. }
+            | AiError::Api { message: m, .. } => write!(f, "{}", m),
+            AiError::Parse(m) => write!(f, "Parse error: {}", m),
+        }
+    }
+}
+
+impl From<reqwest::Error> for AiError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_decode() {
+            AiError::Parse(e.to_string())
+        } else if e.is_timeout() {
+            AiError::Network(format!("Request timed out: {}", e))
+        } else {
+            AiError::Network(format!("Request failed: {}", e))
+        }
+    }
+}
+
+impl From<serde_json::Error> for AiError {
+    fn from(e: serde_json::Error) -> Self {
+        AiError::Parse(e.to_string())
+    }
+}
+
+/// Reads the delay from a Gemini `RetryInfo` detail, e.g. `"retryDelay": "31s"`.
+fn gemini_retry_delay(error: &Value) -> Option<Duration> {
+    error["details"]
+        .as_array()?
+        .iter()
+        .filter_map(|d| d["retryDelay"].as_str())
+        .filter_map(|d| d.trim_end_matches('s').parse::<f64>().ok())
+        // Negative or non-finite delays are ignored rather than trusted
+        .find_map(|secs| Duration::try_from_secs_f64(secs).ok())
+}
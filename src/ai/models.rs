@@ -14,3 +14,167 @@ impl ChatMessage {
         }
     }
 }
+
+/// A tool the model may call, described with a JSON Schema for its arguments.
+#[derive(Debug, Clone)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+impl ToolSpec {
+    /// The tool in the OpenAI `tools` format, which Ollama uses as well.
+    pub fn to_openai_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "function",
+            "function": {
+                "name": self.name,
+                "description": self.description,
+                "parameters": self.parameters
+            }
+        })
+    }
+}
+
+/// A tool invocation requested by the model.
+#[derive(Debug, Clone)]
+pub struct ToolCall {
+    pub name: String,
+    pub arguments: serde_json::Value,
+}
+
+/// Tokens consumed by one call.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+impl Usage {
+    /// Reads two token counts from a provider's usage object, if present.
+    pub fn from_json(usage: &serde_json::Value, prompt: &str, completion: &str) -> Option<Self> {
+        Some(Usage {
+            prompt_tokens: usage[prompt].as_u64()?,
+            completion_tokens: usage[completion].as_u64().unwrap_or(0),
+        })
+    }
+}
+
+/// A number of tokens, and whether it comes from the model's own tokenizer.
+#[derive(Debug, Clone, Copy)]
+pub struct TokenCount {
+    pub tokens: usize,
+    /// `false` when the count is an approximation with another tokenizer.
+    pub exact: bool,
+}
+
+impl TokenCount {
+    /// Approximates the count with `cl100k_base`, for models whose tokenizer isn't available.
+    pub fn estimate(text: &str) -> Self {
+        TokenCount {
+            tokens: tiktoken_rs::cl100k_base_singleton()
+                .encode_with_special_tokens(text)
+                .len(),
+            exact: false,
+        }
+    }
+}
+
+/// A model reply: free text plus any tool calls it requested.
+#[derive(Debug, Clone, Default)]
+pub struct ChatReply {
+    pub content: String,
+    pub tool_calls: Vec<ToolCall>,
+    /// Tokens reported by the provider; `None` when it didn't say.
+    pub usage: Option<Usage>,
+    /// Secrets removed from the new messages of the request before it was sent.
+    pub redacted: usize,
+}
+
+impl ChatReply {
+    /// Builds a reply from a model using the plain-text protocol, where each
+    /// `RUN: <command>` line requests a `run_command` call.
+    pub fn from_text(response: &str) -> Self {
+        let mut content = Vec::new();
+        let mut tool_calls = Vec::new();
+
+        for line in response.lines() {
+            match line.find("RUN:") {
+                Some(idx) => {
+                    let before = line[..idx].trim();
+                    if !before.is_empty() {
+                        content.push(before);
+                    }
+                    let command = strip_markup(&line[idx + 4..]);
+                    if !command.is_empty() {
+                        tool_calls.push(ToolCall {
+                            name: "run_command".to_string(),
+                            arguments: serde_json::json!({ "command": command }),
+                        });
+                    }
+                }
+                None => content.push(line),
+            }
+        }
+
+        ChatReply {
+            content: content.join("\n").trim().to_string(),
+            tool_calls,
+            ..ChatReply::default()
+        }
+    }
+}
+
+/// Removes the formatting tags, backticks and HTML entities models wrap commands in.
+fn strip_markup(text: &str) -> String {
+    let mut out = text.replace('`', "");
+    for tag in ["code", "b", "i", "pre"] {
+        out = out
+            .replace(&format!("<{}>", tag), "")
+            .replace(&format!("</{}>", tag), "");
+    }
+    out.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+/// Context window, in tokens, of a model identified by name.
+///
+/// Names are matched by prefix so dated or quantised variants (`gpt-4o-2024-08-06`,
+/// `llama3.1:8b`) share the limit of their family. Unknown models get a conservative 8k.
+pub fn context_window(model: &str) -> usize {
+    const LIMITS: [(&str, usize); 20] = [
+        ("gpt-4.1", 1_000_000),
+        ("gpt-4o", 128_000),
+        ("gpt-4-turbo", 128_000),
+        ("gpt-4", 8_192),
+        ("gpt-3.5", 16_385),
+        ("gpt-5", 400_000),
+        ("o1", 200_000),
+        ("o3", 200_000),
+        ("o4", 200_000),
+        ("gemini-1.5", 1_000_000),
+        ("gemini-2", 1_000_000),
+        ("gemini-pro", 32_768),
+        ("claude", 200_000),
+        ("llama3.1", 128_000),
+        ("llama3.2", 128_000),
+        ("llama3.3", 128_000),
+        ("llama3", 8_192),
+        ("qwen2.5", 32_768),
+        ("mistral", 32_768),
+        ("phi3", 4_096),
+    ];
+
+    let model = model.to_lowercase();
+    let name = model.rsplit('/').next().unwrap_or(&model);
+    LIMITS
+        .iter()
+        .find(|(prefix, _)| name.starts_with(prefix))
+        .map(|(_, limit)| *limit)
+        .unwrap_or(8_192)
+}
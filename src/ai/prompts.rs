@@ -0,0 +1,246 @@
+use crate::core::discovery::DiscoveryReport;
+use crate::db::{get_setting, set_setting, DbPool};
+use std::collections::HashMap;
+
+/// Settings scope of the template overrides, keyed by `name` or `name@provider`.
+const OVERRIDE_SCOPE: &str = "prompt";
+/// Settings scope of the last discovery summary of each server, by alias.
+const DISCOVERY_SCOPE: &str = "discovery";
+/// Settings scope of the OS name found by the last discovery, by alias.
+const OS_SCOPE: &str = "server_os";
+/// Settings scope of the notes the user keeps about each server, by alias.
+const NOTES_SCOPE: &str = "server_notes";
+
+/// A named prompt with its built-in text.
+pub struct Template {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Variables the template is rendered with, without braces.
+    pub variables: &'static [&'static str],
+    pub default: &'static str,
+}
+
+/// Variables available to every template about a server.
+const SERVER_VARIABLES: [&str; 4] = ["alias", "os", "discovery", "notes"];
+
+/// The registry of prompt templates. Variables are written `{{name}}`.
+pub const TEMPLATES: [Template; 7] = [
+    Template {
+        name: "server_assistant",
+        description: "System prompt of a server session",
+        variables: &SERVER_VARIABLES,
+        default: include_str!("../../templates/prompts/server_assistant.html"),
+    },
+    Template {
+        name: "ssh_reminder",
+        description: "Reminder appended to the last user message of a session",
+        variables: &SERVER_VARIABLES,
+        default: include_str!("../../templates/prompts/ssh_reminder.txt"),
+    },
+    Template {
+        name: "summarise_history",
+        description: "Summary of older messages when a session outgrows the context window",
+        variables: &["alias", "os", "discovery", "notes", "transcript"],
+        default: include_str!("../../templates/prompts/summarise_history.txt"),
+    },
+    Template {
+        name: "discover_analysis",
+        description: "Analysis of a /discover report (sent along with the report)",
+        variables: &SERVER_VARIABLES,
+        default: include_str!("../../templates/prompts/discover_analysis.txt"),
+    },
+    Template {
+        name: "investigate",
+        description: "System prompt of an /investigate run",
+        variables: &["alias", "os", "discovery", "notes", "goal", "max_steps"],
+        default: include_str!("../../templates/prompts/investigate.txt"),
+    },
+    Template {
+        name: "incident_report",
+        description: "Post-mortem of the last session, written by /report",
+        variables: &[
+            "alias",
+            "os",
+            "discovery",
+            "notes",
+            "started",
+            "ended",
+            "timeline",
+        ],
+        default: include_str!("../../templates/prompts/incident_report.txt"),
+    },
+    Template {
+        name: "howto",
+        description: "Command proposal with explanation and risk rating, asked by /howto",
+        variables: &["alias", "os", "discovery", "notes", "task"],
+        default: include_str!("../../templates/prompts/howto.txt"),
+    },
+];
+
+/// Looks up a template by name.
+pub fn template(name: &str) -> Option<&'static Template> {
+    TEMPLATES.iter().find(|t| t.name == name)
+}
+
+/// Values substituted into a template.
+#[derive(Debug, Clone, Default)]
+pub struct Vars(HashMap<&'static str, String>);
+
+impl Vars {
+    /// Sets a variable, replacing any previous value.
+    pub fn set(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.0.insert(name, value.into());
+        self
+    }
+
+    /// The variables known about a server: its alias, OS, last discovery and notes.
+    pub async fn server(pool: &DbPool, alias: &str) -> Self {
+        let known = |value: Option<String>, missing: &str| {
+            value
+                .filter(|v| !v.trim().is_empty())
+                .unwrap_or_else(|| missing.to_string())
+        };
+        Vars::default()
+            .set("alias", alias)
+            .set(
+                "os",
+                known(get_setting(pool, OS_SCOPE, alias).await, "unknown"),
+            )
+            .set(
+                "discovery",
+                known(
+                    get_setting(pool, DISCOVERY_SCOPE, alias).await,
+                    "none yet (run /discover)",
+                ),
+            )
+            .set(
+                "notes",
+                known(get_setting(pool, NOTES_SCOPE, alias).await, "none"),
+            )
+    }
+}
+
+/// Replaces each `{{name}}` with its value. Unknown variables are left as they are.
+pub fn fill(text: &str, vars: &Vars) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after.find("}}") {
+            Some(end) => {
+                let name = after[..end].trim();
+                match vars.0.get(name) {
+                    Some(value) => out.push_str(value),
+                    None => out.push_str(&rest[start..start + 2 + end + 2]),
+                }
+                rest = &after[end + 2..];
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The text of template `name` for `provider`: its provider variant, else its
+/// override, else the built-in text.
+pub async fn text(pool: &DbPool, name: &str, provider: &str) -> String {
+    if let Some(text) = get_setting(pool, OVERRIDE_SCOPE, &format!("{}@{}", name, provider)).await {
+        if !text.is_empty() {
+            return text;
+        }
+    }
+    match get_setting(pool, OVERRIDE_SCOPE, name).await {
+        Some(text) if !text.is_empty() => text,
+        _ => template(name)
+            .map(|t| t.default)
+            .unwrap_or_default()
+            .to_string(),
+    }
+}
+
+/// Renders template `name` for `provider` with `vars`.
+pub async fn render(pool: &DbPool, name: &str, provider: &str, vars: &Vars) -> String {
+    fill(&text(pool, name, provider).await, vars)
+}
+
+/// Stores an override of template `name`, for one provider only when `provider` is set.
+///
+/// An empty `text` removes the override (the settings table has no delete helper,
+/// and an empty value reads as unset).
+pub async fn set_override(
+    pool: &DbPool,
+    name: &str,
+    provider: Option<&str>,
+    text: &str,
+) -> Result<(), sqlx::Error> {
+    let key = match provider {
+        Some(provider) => format!("{}@{}", name, provider),
+        None => name.to_string(),
+    };
+    set_setting(pool, OVERRIDE_SCOPE, &key, text).await
+}
+
+/// The keys of the stored overrides of template `name`: `name` and `name@provider`.
+pub async fn overrides(pool: &DbPool, name: &str) -> Result<Vec<String>, sqlx::Error> {
+    let rows: Vec<(String,)> = sqlx::query_as(
+        "SELECT key FROM settings WHERE scope = ? AND (key = ? OR key LIKE ?) AND value != '' ORDER BY key",
+    )
+    .bind(OVERRIDE_SCOPE)
+    .bind(name)
+    .bind(format!("{}@%", name))
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|r| r.0).collect())
+}
+
+/// Stores the OS and a short summary of a discovery, for the `os` and `discovery` variables.
+pub async fn save_discovery(
+    pool: &DbPool,
+    alias: &str,
+    report: &DiscoveryReport,
+) -> Result<(), sqlx::Error> {
+    let info = &report.system_info;
+    let health = &report.health;
+    let mut summary = format!(
+        "{} (kernel {}), up {}; CPU load {}, memory {}, disk {}",
+        info.os_release,
+        info.kernel_version,
+        info.uptime,
+        report.resources.cpu_usage,
+        report.resources.memory_usage,
+        report.resources.disk_usage
+    );
+    if !health.failed_units.is_empty() {
+        summary.push_str(&format!(
+            "; failed units: {}",
+            health.failed_units.join(", ")
+        ));
+    }
+    if let Some(updates) = health.pending_updates {
+        summary.push_str(&format!("; {} pending updates", updates));
+    }
+    if health.reboot_required {
+        summary.push_str("; reboot required");
+    }
+    summary.push_str(&format!(" (as of {})", report.timestamp));
+
+    set_setting(pool, OS_SCOPE, alias, &info.os_release).await?;
+    set_setting(pool, DISCOVERY_SCOPE, alias, &summary).await
+}
+
+/// The notes kept about a server, if any.
+pub async fn notes(pool: &DbPool, alias: &str) -> Option<String> {
+    get_setting(pool, NOTES_SCOPE, alias)
+        .await
+        .filter(|n| !n.is_empty())
+}
+
+/// Replaces the notes kept about a server; empty `notes` clears them.
+pub async fn set_notes(pool: &DbPool, alias: &str, notes: &str) -> Result<(), sqlx::Error> {
+    set_setting(pool, NOTES_SCOPE, alias, notes).await
+}
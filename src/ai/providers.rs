@@ -1,3 +1,119 @@
+pub mod anthropic;
 pub mod gemini;
 pub mod ollama;
 pub mod openai;
+
+use crate::ai::error::AiError;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+/// Time allowed to establish a connection to a provider.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time allowed for a whole request, including a streamed reply.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(180);
+/// How long a fetched model list is reused before asking the provider again.
+const MODELS_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// A model list with the time it was fetched.
+type CachedModels = (Instant, Vec<String>);
+
+/// Model lists fetched from providers, by endpoint and key.
+///
+/// Providers are rebuilt for every command, so the cache lives here rather than in them.
+static MODELS: LazyLock<Mutex<HashMap<u64, CachedModels>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Returns the models of the account behind `base_url` and `api_key`.
+///
+/// A list fetched less than `MODELS_TTL` ago is reused. When the provider can't be
+/// reached (or answers with a transient error), a non-empty `fallback` is returned
+/// instead so `/models` still works offline; other errors, such as an invalid key,
+/// are returned.
+pub(crate) async fn cached_models(
+    base_url: &str,
+    api_key: &str,
+    fetch: impl std::future::Future<Output = Result<Vec<String>, AiError>>,
+    fallback: &[&str],
+) -> Result<Vec<String>, AiError> {
+    // The key is only hashed, never kept
+    let mut hasher = DefaultHasher::new();
+    (base_url, api_key).hash(&mut hasher);
+    let key = hasher.finish();
+
+    if let Some((fetched, models)) = MODELS.lock().unwrap().get(&key) {
+        if fetched.elapsed() < MODELS_TTL {
+            return Ok(models.clone());
+        }
+    }
+    match fetch.await {
+        Ok(models) if !models.is_empty() => {
+            MODELS
+                .lock()
+                .unwrap()
+                .insert(key, (Instant::now(), models.clone()));
+            Ok(models)
+        }
+        Ok(_) => Ok(fallback.iter().map(|m| m.to_string()).collect()),
+        Err(e) if e.is_transient() && !fallback.is_empty() => {
+            eprintln!("Failed to list models, using the built-in list: {}", e);
+            Ok(fallback.iter().map(|m| m.to_string()).collect())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Reads a streaming HTTP response line by line (SSE or NDJSON).
+///
+/// Stops early when `on_line` returns `Ok(false)`.
+pub(crate) async fn read_lines(
+    mut res: reqwest::Response,
+    mut on_line: impl FnMut(&str) -> Result<bool, AiError>,
+) -> Result<(), AiError> {
+    let mut buffer: Vec<u8> = Vec::new();
+    while let Some(chunk) = res
+        .chunk()
+        .await
+        .map_err(|e| AiError::Network(format!("Stream interrupted: {}", e)))?
+    {
+        buffer.extend_from_slice(&chunk);
+        while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim();
+            if !line.is_empty() && !on_line(line)? {
+                return Ok(());
+            }
+        }
+    }
+
+    let rest = String::from_utf8_lossy(&buffer);
+    if !rest.trim().is_empty() {
+        on_line(rest.trim())?;
+    }
+    Ok(())
+}
+
+/// Builds an HTTP client with the provider timeouts, sending `headers` with every request.
+///
+/// Invalid header names or values are skipped.
+pub(crate) fn client_with_headers(headers: &[(String, String)]) -> reqwest::Client {
+    use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            map.insert(name, value);
+        }
+    }
+    reqwest::Client::builder()
+        .default_headers(map)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default()
+}
@@ -0,0 +1,488 @@
+use crate::ai::config::{AnthropicConfig, GenerationParams};
+use crate::ai::error::AiError;
+use crate::ai::models::{ChatMessage, ChatReply, TokenCount, ToolCall, ToolSpec, Usage};
+use crate::ai::traits::AiProviderTrait;
+use async_trait::async_trait;
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+/// Version of the Messages API this provider speaks.
+const API_VERSION: &str = "2023-06-01";
+/// The Messages API requires an explicit output limit; used unless `max_tokens` is set.
+const MAX_TOKENS: u32 = 4096;
+
+/// A provider implementation for the Anthropic Messages API (Claude models).
+pub struct AnthropicProvider {
+    client: Client,
+    config: AnthropicConfig,
+}
+
+impl AnthropicProvider {
+    /// Creates a new `AnthropicProvider` with the given configuration.
+    pub fn new(config: AnthropicConfig) -> Self {
+        Self {
+            client: crate::ai::providers::client_with_headers(&[]),
+            config,
+        }
+    }
+
+    /// Creates a provider that sends extra HTTP headers with every request.
+    pub fn with_headers(config: AnthropicConfig, headers: &[(String, String)]) -> Self {
+        Self {
+            client: crate::ai::providers::client_with_headers(headers),
+            config,
+        }
+    }
+
+    /// Builds a Messages API request body.
+    fn body(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+        params: &GenerationParams,
+        stream: bool,
+    ) -> Value {
+        let (system, messages) = to_messages(messages);
+        let mut body = json!({
+            "model": self.config.model,
+            "max_tokens": params.max_tokens.unwrap_or(MAX_TOKENS),
+            "messages": messages
+        });
+        if let Some(temperature) = params.temperature {
+            // The API accepts 0 to 1
+            body["temperature"] = json!(temperature.min(1.0));
+        }
+        if let Some(top_p) = params.top_p {
+            body["top_p"] = json!(top_p);
+        }
+        if !params.stop.is_empty() {
+            body["stop_sequences"] = json!(params.stop);
+        }
+        if let Some(system) = system {
+            body["system"] = json!(system);
+        }
+        if !tools.is_empty() {
+            body["tools"] = tools
+                .iter()
+                .map(|t| {
+                    json!({
+                        "name": t.name,
+                        "description": t.description,
+                        "input_schema": t.parameters
+                    })
+                })
+                .collect();
+        }
+        if stream {
+            body["stream"] = json!(true);
+        }
+        body
+    }
+
+    /// Sends a request to `/messages`, returning the response once it succeeded.
+    async fn send(&self, body: &Value) -> Result<reqwest::Response, AiError> {
+        let url = format!("{}/messages", self.config.base_url.trim_end_matches('/'));
+        let res = self
+            .client
+            .post(&url)
+            .header("x-api-key", &self.config.api_key)
+            .header("anthropic-version", API_VERSION)
+            .json(body)
+            .send()
+            .await?;
+
+        if !res.status().is_success() {
+            return Err(AiError::from_response(res).await);
+        }
+        Ok(res)
+    }
+
+    /// Sends a non-streaming request and parses text and `tool_use` blocks.
+    async fn create(&self, body: Value) -> Result<ChatReply, AiError> {
+        let res = self.send(&body).await?;
+        let json: Value = res.json().await?;
+
+        let mut reply = ChatReply {
+            usage: Usage::from_json(&json["usage"], "input_tokens", "output_tokens"),
+            ..ChatReply::default()
+        };
+        for block in json["content"].as_array().into_iter().flatten() {
+            match block["type"].as_str() {
+                Some("text") => reply
+                    .content
+                    .push_str(block["text"].as_str().unwrap_or_default()),
+                Some("tool_use") => reply.tool_calls.push(ToolCall {
+                    name: block["name"].as_str().unwrap_or_default().to_string(),
+                    arguments: block["input"].clone(),
+                }),
+                _ => {}
+            }
+        }
+
+        if reply.content.is_empty() && reply.tool_calls.is_empty() {
+            return Err(match json["stop_reason"].as_str() {
+                Some("refusal") => {
+                    AiError::SafetyBlocked("Claude declined to answer (refusal)".to_string())
+                }
+                Some(reason) => {
+                    AiError::Other(format!("No content in response (stop reason: {})", reason))
+                }
+                None => AiError::Other("No content in response".to_string()),
+            });
+        }
+        Ok(reply)
+    }
+}
+
+/// Splits out the system prompt and converts the rest into alternating messages.
+///
+/// The Messages API only accepts `user` and `assistant` roles, starting with `user`,
+/// so consecutive messages with the same role are merged.
+fn to_messages(messages: &[ChatMessage]) -> (Option<String>, Vec<Value>) {
+    let system: Vec<&str> = messages
+        .iter()
+        .filter(|m| m.role == "system")
+        .map(|m| m.content.as_str())
+        .collect();
+    let system = if system.is_empty() {
+        None
+    } else {
+        Some(system.join("\n\n"))
+    };
+
+    let mut turns: Vec<(&str, Vec<&str>)> = Vec::new();
+    for message in messages.iter().filter(|m| m.role != "system") {
+        let role = if message.role == "assistant" {
+            "assistant"
+        } else {
+            "user"
+        };
+        match turns.last_mut() {
+            Some((last_role, texts)) if *last_role == role => texts.push(&message.content),
+            _ => turns.push((role, vec![&message.content])),
+        }
+    }
+    if turns.first().map(|(role, _)| *role) == Some("assistant") {
+        turns.insert(0, ("user", vec!["(conversation start)"]));
+    }
+
+    let messages = turns
+        .into_iter()
+        .map(|(role, texts)| json!({ "role": role, "content": texts.join("\n\n") }))
+        .collect();
+    (system, messages)
+}
+
+#[async_trait]
+impl AiProviderTrait for AnthropicProvider {
+    async fn ask(&self, question: &str, params: &GenerationParams) -> Result<String, AiError> {
+        let messages = [ChatMessage::new("user", question)];
+        self.create(self.body(&messages, &[], params, false))
+            .await
+            .map(|r| r.content)
+    }
+
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        params: &GenerationParams,
+    ) -> Result<String, AiError> {
+        self.create(self.body(messages, &[], params, false))
+            .await
+            .map(|r| r.content)
+    }
+
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+        params: &GenerationParams,
+    ) -> Result<ChatReply, AiError> {
+        self.create(self.body(messages, tools, params, false)).await
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+        params: &GenerationParams,
+        on_delta: &(dyn for<'a> Fn(&'a str) + Send + Sync),
+    ) -> Result<ChatReply, AiError> {
+        let res = self.send(&self.body(messages, tools, params, true)).await?;
+
+        // Content blocks are indexed; tool inputs arrive as fragments of JSON
+        let mut content = String::new();
+        let mut calls: Vec<(usize, String, String)> = Vec::new();
+        let mut usage = Usage::default();
+        crate::ai::providers::read_lines(res, |line| {
+            let data = match line.strip_prefix("data:") {
+                Some(data) => data.trim(),
+                None => return Ok(true),
+            };
+            let event: Value = serde_json::from_str(data)?;
+            let index = event["index"].as_u64().unwrap_or(0) as usize;
+
+            match event["type"].as_str() {
+                // Input tokens come first, the output count with the final message delta
+                Some("message_start") => {
+                    let u = &event["message"]["usage"];
+                    usage.prompt_tokens = u["input_tokens"].as_u64().unwrap_or(0);
+                }
+                Some("message_delta") => {
+                    usage.completion_tokens = event["usage"]["output_tokens"].as_u64().unwrap_or(0);
+                }
+                Some("content_block_start") if event["content_block"]["type"] == "tool_use" => {
+                    let name = event["content_block"]["name"].as_str().unwrap_or_default();
+                    calls.push((index, name.to_string(), String::new()));
+                }
+                Some("content_block_delta") => {
+                    let delta = &event["delta"];
+                    if let Some(text) = delta["text"].as_str() {
+                        content.push_str(text);
+                        on_delta(text);
+                    }
+                    if let Some(json) = delta["partial_json"].as_str() {
+                        if let Some(call) = calls.iter_mut().find(|(i, _, _)| *i == index) {
+                            call.2.push_str(json);
+                        }
+                    }
+                }
+                Some("error") => {
+                    // Errors after the stream started come as an event, without an HTTP status
+                    let status = match event["error"]["type"].as_str() {
+                        Some("overloaded_error") => 529,
+                        Some("rate_limit_error") => 429,
+                        Some("api_error") => 500,
+                        _ => 400,
+                    };
+                    return Err(AiError::from_status(
+                        StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_REQUEST),
+                        None,
+                        data,
+                    ));
+                }
+                Some("message_stop") => return Ok(false),
+                _ => {}
+            }
+            Ok(true)
+        })
+        .await?;
+
+        let tool_calls = calls
+            .into_iter()
+            .filter_map(|(_, name, input)| {
+                Some(ToolCall {
+                    name,
+                    arguments: serde_json::from_str(if input.is_empty() { "{}" } else { &input })
+                        .ok()?,
+                })
+            })
+            .collect();
+        Ok(ChatReply {
+            content,
+            tool_calls,
+            usage: (usage.prompt_tokens > 0).then_some(usage),
+            ..ChatReply::default()
+        })
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AiError> {
+        Ok(vec![
+            "claude-sonnet-4-5".to_string(),
+            "claude-opus-4-1".to_string(),
+            "claude-haiku-4-5".to_string(),
+        ])
+    }
+
+    async fn count_tokens(&self, text: &str) -> Result<TokenCount, AiError> {
+        // Claude's tokenizer isn't public; cl100k_base gives a close estimate
+        Ok(TokenCount::estimate(text))
+    }
+
+    fn model(&self) -> &str {
+        &self.config.model
+    }
+
+    fn context_window(&self) -> usize {
+        crate::ai::models::context_window(&self.config.model)
+    }
+
+    fn get_info(&self) -> String {
+        format!("Anthropic (Model: {})", self.config.model)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    /// Answers one request with `body` and returns the request's head (lowercased)
+    /// and JSON body.
+    async fn mock_server(
+        content_type: &'static str,
+        body: String,
+    ) -> (String, JoinHandle<(String, Value)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            let (head, start, length) = loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let head = text[..end].to_lowercase();
+                    let length = head
+                        .lines()
+                        .find_map(|l| l.strip_prefix("content-length:"))
+                        .map(|v| v.trim().parse::<usize>().unwrap())
+                        .unwrap_or(0);
+                    break (head, end + 4, length);
+                }
+            };
+            while request.len() < start + length {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                content_type,
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+            let sent = serde_json::from_slice(&request[start..start + length]).unwrap();
+            (head, sent)
+        });
+        (url, handle)
+    }
+
+    fn provider(base_url: String) -> AnthropicProvider {
+        AnthropicProvider::new(AnthropicConfig {
+            api_key: "test-key".to_string(),
+            model: "claude-test".to_string(),
+            base_url,
+        })
+    }
+
+    fn conversation() -> Vec<ChatMessage> {
+        vec![
+            ChatMessage::new("system", "Be brief."),
+            ChatMessage::new("user", "Is nginx up?"),
+            ChatMessage::new("user", "On web-1."),
+            ChatMessage::new("assistant", "Let me check."),
+            ChatMessage::new("user", "Thanks."),
+        ]
+    }
+
+    fn tools() -> Vec<ToolSpec> {
+        vec![ToolSpec {
+            name: "run_command".to_string(),
+            description: "Runs a command".to_string(),
+            parameters: json!({ "type": "object", "properties": { "command": { "type": "string" } } }),
+        }]
+    }
+
+    /// Checks the headers and the parts of the body common to every request.
+    fn assert_request(head: &str, body: &Value) {
+        assert!(head.starts_with("post /v1/messages "), "{}", head);
+        assert!(head.contains("\r\nx-api-key: test-key"), "{}", head);
+        assert!(
+            head.contains("\r\nanthropic-version: 2023-06-01"),
+            "{}",
+            head
+        );
+
+        assert_eq!(body["model"], "claude-test");
+        assert_eq!(body["max_tokens"], MAX_TOKENS);
+        assert_eq!(body["system"], "Be brief.");
+        let roles: Vec<&str> = body["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["role"].as_str().unwrap())
+            .collect();
+        assert_eq!(roles, ["user", "assistant", "user"]);
+        assert_eq!(body["messages"][0]["content"], "Is nginx up?\n\nOn web-1.");
+        assert_eq!(body["tools"][0]["name"], "run_command");
+        assert_eq!(body["tools"][0]["input_schema"]["type"], "object");
+    }
+
+    #[tokio::test]
+    async fn sends_messages_and_parses_tool_use() {
+        let response = json!({
+            "content": [
+                { "type": "text", "text": "Checking nginx." },
+                { "type": "tool_use", "id": "toolu_1", "name": "run_command", "input": { "command": "systemctl status nginx" } }
+            ],
+            "stop_reason": "tool_use",
+            "usage": { "input_tokens": 42, "output_tokens": 7 }
+        });
+        let (url, server) = mock_server("application/json", response.to_string()).await;
+
+        let reply = provider(url)
+            .chat_with_tools(&conversation(), &tools(), &GenerationParams::default())
+            .await
+            .unwrap();
+        let (head, body) = server.await.unwrap();
+
+        assert_request(&head, &body);
+        assert!(body.get("stream").is_none());
+        assert_eq!(reply.content, "Checking nginx.");
+        assert_eq!(reply.tool_calls.len(), 1);
+        assert_eq!(reply.tool_calls[0].name, "run_command");
+        assert_eq!(
+            reply.tool_calls[0].arguments["command"],
+            "systemctl status nginx"
+        );
+        let usage = reply.usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (42, 7));
+    }
+
+    #[tokio::test]
+    async fn streams_text_and_tool_input() {
+        let events = [
+            json!({ "type": "message_start", "message": { "usage": { "input_tokens": 42 } } }),
+            json!({ "type": "content_block_start", "index": 0, "content_block": { "type": "text", "text": "" } }),
+            json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "text_delta", "text": "Check" } }),
+            json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "text_delta", "text": "ing." } }),
+            json!({ "type": "content_block_start", "index": 1, "content_block": { "type": "tool_use", "name": "run_command" } }),
+            json!({ "type": "content_block_delta", "index": 1, "delta": { "type": "input_json_delta", "partial_json": "{\"command\": \"up" } }),
+            json!({ "type": "content_block_delta", "index": 1, "delta": { "type": "input_json_delta", "partial_json": "time\"}" } }),
+            json!({ "type": "message_delta", "usage": { "output_tokens": 9 } }),
+            json!({ "type": "message_stop" }),
+        ];
+        let sse: String = events
+            .iter()
+            .map(|e| format!("event: {}\ndata: {}\n\n", e["type"].as_str().unwrap(), e))
+            .collect();
+        let (url, server) = mock_server("text/event-stream", sse).await;
+
+        let deltas = Mutex::new(Vec::new());
+        let reply = provider(url)
+            .chat_stream(
+                &conversation(),
+                &tools(),
+                &GenerationParams::default(),
+                &|delta| deltas.lock().unwrap().push(delta.to_string()),
+            )
+            .await
+            .unwrap();
+        let (head, body) = server.await.unwrap();
+
+        assert_request(&head, &body);
+        assert_eq!(body["stream"], true);
+        assert_eq!(*deltas.lock().unwrap(), ["Check", "ing."]);
+        assert_eq!(reply.content, "Checking.");
+        assert_eq!(reply.tool_calls.len(), 1);
+        assert_eq!(reply.tool_calls[0].arguments["command"], "uptime");
+        let usage = reply.usage.unwrap();
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (42, 9));
+    }
+}
@@ -1,4 +1,6 @@
-use crate::ai::config::GeminiConfig;
+use crate::ai::config::{GeminiConfig, GenerationParams};
+use crate::ai::error::AiError;
+use crate::ai::models::{ChatMessage, ChatReply, TokenCount, ToolCall, ToolSpec, Usage};
 use crate::ai::traits::AiProviderTrait;
 use async_trait::async_trait;
 use reqwest::Client;
@@ -16,68 +18,397 @@ impl GeminiProvider {
     /// Creates a new `GeminiProvider` with the given configuration.
     pub fn new(config: GeminiConfig) -> Self {
         Self {
-            client: Client::new(),
+            client: crate::ai::providers::client_with_headers(&[]),
             config,
         }
     }
 }
 
-#[async_trait]
-impl AiProviderTrait for GeminiProvider {
-    async fn ask(&self, question: &str) -> Result<String, String> {
+impl GeminiProvider {
+    /// Fetches the models that support `generateContent` from the `models` endpoint.
+    async fn fetch_models(&self) -> Result<Vec<String>, AiError> {
+        // Embedding, attributed QA, speech and image models also list generateContent
+        const EXCLUDED: [&str; 5] = ["embedding", "aqa", "tts", "image", "learnlm"];
+
+        let url = format!(
+            "{}?pageSize=1000&key={}",
+            self.config.base_url.trim_end_matches('/'),
+            self.config.api_key
+        );
+        let res = self.client.get(&url).send().await?;
+        if !res.status().is_success() {
+            return Err(AiError::from_response(res).await);
+        }
+        let json: Value = res.json().await?;
+
+        let models = json["models"]
+            .as_array()
+            .ok_or_else(|| AiError::Parse("Invalid response format".to_string()))?
+            .iter()
+            .filter(|m| {
+                m["supportedGenerationMethods"]
+                    .as_array()
+                    .is_some_and(|methods| methods.iter().any(|x| x == "generateContent"))
+            })
+            .filter_map(|m| m["name"].as_str())
+            .map(|name| name.trim_start_matches("models/").to_string())
+            .filter(|name| !EXCLUDED.iter().any(|e| name.contains(e)))
+            .collect();
+        Ok(models)
+    }
+
+    /// Counts tokens with the model's `countTokens` endpoint.
+    async fn count_tokens_remote(&self, text: &str) -> Result<usize, AiError> {
+        let url = format!(
+            "{}/{}:countTokens?key={}",
+            self.config.base_url, self.config.model, self.config.api_key
+        );
+        let body = json!({ "contents": [{ "role": "user", "parts": [{ "text": text }] }] });
+
+        let res = self.client.post(&url).json(&body).send().await?;
+        if !res.status().is_success() {
+            return Err(AiError::from_response(res).await);
+        }
+        let json: Value = res.json().await?;
+        json["totalTokens"]
+            .as_u64()
+            .map(|t| t as usize)
+            .ok_or_else(|| AiError::Parse("No totalTokens in countTokens response".to_string()))
+    }
+
+    /// Calls `generateContent` and returns the parts of the first candidate with the usage.
+    ///
+    /// Blocked prompts and candidates that stopped for safety or similar reasons are
+    /// turned into errors explaining why.
+    async fn generate(&self, body: Value) -> Result<(Vec<Value>, Option<Usage>), AiError> {
         let url = format!(
             "{}/{}:generateContent?key={}",
             self.config.base_url, self.config.model, self.config.api_key
         );
-        let body = json!({
+
+        let res = self.client.post(&url).json(&body).send().await?;
+
+        if !res.status().is_success() {
+            return Err(AiError::from_response(res).await);
+        }
+
+        let json: Value = res.json().await?;
+        let usage = usage(&json);
+        candidate_parts(&json).map(|parts| (parts, usage))
+    }
+}
+
+/// Reads the token counts of a (partial) `generateContent` response.
+fn usage(json: &Value) -> Option<Usage> {
+    Usage::from_json(
+        &json["usageMetadata"],
+        "promptTokenCount",
+        "candidatesTokenCount",
+    )
+}
+
+/// Extracts the parts of the first candidate, surfacing blocks and abnormal stops.
+fn candidate_parts(json: &Value) -> Result<Vec<Value>, AiError> {
+    let parts = json["candidates"][0]["content"]["parts"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    if let Some(error) = stop_error(json, !parts.is_empty()) {
+        return Err(error);
+    }
+    if parts.is_empty() {
+        return Err(AiError::Other("No content in response".to_string()));
+    }
+    Ok(parts)
+}
+
+/// Explains why Gemini blocked the prompt or stopped the first candidate early.
+///
+/// `has_content` tells whether some answer was produced, in which case hitting the
+/// token limit is not an error.
+fn stop_error(json: &Value, has_content: bool) -> Option<AiError> {
+    if let Some(reason) = json["promptFeedback"]["blockReason"].as_str() {
+        return Some(AiError::SafetyBlocked(format!(
+            "Gemini blocked the prompt ({})",
+            reason
+        )));
+    }
+
+    let candidate = &json["candidates"][0];
+    match candidate["finishReason"].as_str() {
+        None | Some("STOP") | Some("FINISH_REASON_UNSPECIFIED") => None,
+        Some("MAX_TOKENS") if has_content => None,
+        Some("MAX_TOKENS") => Some(AiError::Other(
+            "Gemini hit the output token limit before answering".to_string(),
+        )),
+        Some(reason @ ("SAFETY" | "PROHIBITED_CONTENT" | "BLOCKLIST" | "SPII")) => {
+            let categories: Vec<&str> = candidate["safetyRatings"]
+                .as_array()
+                .map(|ratings| {
+                    ratings
+                        .iter()
+                        .filter(|r| r["blocked"].as_bool().unwrap_or(false))
+                        .filter_map(|r| r["category"].as_str())
+                        .collect()
+                })
+                .unwrap_or_default();
+            let detail = if categories.is_empty() {
+                String::new()
+            } else {
+                format!(": {}", categories.join(", "))
+            };
+            Some(AiError::SafetyBlocked(format!(
+                "Gemini blocked the answer for safety reasons ({}{})",
+                reason, detail
+            )))
+        }
+        Some(reason) if !has_content => Some(AiError::Other(format!(
+            "Gemini stopped without an answer ({})",
+            reason
+        ))),
+        Some(_) => None,
+    }
+}
+
+/// Converts a chat history into Gemini `contents` and an optional `systemInstruction`.
+///
+/// Gemini only knows the `user` and `model` roles and expects them to alternate, so
+/// consecutive messages with the same role are merged into one turn.
+fn to_contents(messages: &[ChatMessage]) -> (Option<Value>, Vec<Value>) {
+    let system: Vec<&str> = messages
+        .iter()
+        .filter(|m| m.role == "system")
+        .map(|m| m.content.as_str())
+        .collect();
+    let system_instruction = if system.is_empty() {
+        None
+    } else {
+        Some(json!({ "parts": [{ "text": system.join("\n\n") }] }))
+    };
+
+    let mut turns: Vec<(&str, Vec<&str>)> = Vec::new();
+    for message in messages.iter().filter(|m| m.role != "system") {
+        let role = if message.role == "assistant" {
+            "model"
+        } else {
+            "user"
+        };
+        match turns.last_mut() {
+            Some((last_role, texts)) if *last_role == role => texts.push(&message.content),
+            _ => turns.push((role, vec![&message.content])),
+        }
+    }
+
+    let contents = turns
+        .into_iter()
+        .map(|(role, texts)| {
+            json!({
+                "role": role,
+                "parts": [{ "text": texts.join("\n\n") }]
+            })
+        })
+        .collect();
+    (system_instruction, contents)
+}
+
+/// Builds a `generateContent` request body from a chat history.
+fn chat_body(messages: &[ChatMessage], params: &GenerationParams) -> Value {
+    let (system_instruction, contents) = to_contents(messages);
+    let mut body = json!({ "contents": contents });
+    if let Some(instruction) = system_instruction {
+        body["systemInstruction"] = instruction;
+    }
+    apply_params(&mut body, params);
+    body
+}
+
+/// Sets the `generationConfig` of a request.
+fn apply_params(body: &mut Value, params: &GenerationParams) {
+    let mut config = serde_json::Map::new();
+    if let Some(temperature) = params.temperature {
+        config.insert("temperature".into(), json!(temperature));
+    }
+    if let Some(max_tokens) = params.max_tokens {
+        config.insert("maxOutputTokens".into(), json!(max_tokens));
+    }
+    if let Some(top_p) = params.top_p {
+        config.insert("topP".into(), json!(top_p));
+    }
+    if !params.stop.is_empty() {
+        config.insert("stopSequences".into(), json!(params.stop));
+    }
+    if !config.is_empty() {
+        body["generationConfig"] = Value::Object(config);
+    }
+}
+
+/// Declares the tools as Gemini `functionDeclarations`.
+fn tools_json(tools: &[ToolSpec]) -> Value {
+    let declarations: Vec<Value> = tools
+        .iter()
+        .map(|t| {
+            json!({
+                "name": t.name,
+                "description": t.description,
+                "parameters": t.parameters
+            })
+        })
+        .collect();
+    json!([{ "functionDeclarations": declarations }])
+}
+
+/// Joins the text parts of a candidate.
+fn parts_text(parts: &[Value]) -> String {
+    parts.iter().filter_map(|p| p["text"].as_str()).collect()
+}
+
+#[async_trait]
+impl AiProviderTrait for GeminiProvider {
+    async fn ask(&self, question: &str, params: &GenerationParams) -> Result<String, AiError> {
+        let mut body = json!({
             "contents": [{
+                "role": "user",
                 "parts": [{"text": question}]
             }]
         });
+        apply_params(&mut body, params);
+        let (parts, _) = self.generate(body).await?;
+        Ok(parts_text(&parts))
+    }
+
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        params: &GenerationParams,
+    ) -> Result<String, AiError> {
+        let (parts, _) = self.generate(chat_body(messages, params)).await?;
+        Ok(parts_text(&parts))
+    }
+
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+        params: &GenerationParams,
+    ) -> Result<ChatReply, AiError> {
+        let mut body = chat_body(messages, params);
+        body["tools"] = tools_json(tools);
+
+        let (parts, usage) = self.generate(body).await?;
+
+        // Text and function calls come as separate parts
+        let mut reply = ChatReply {
+            content: parts_text(&parts),
+            usage,
+            ..ChatReply::default()
+        };
+        for part in &parts {
+            if let Some(name) = part["functionCall"]["name"].as_str() {
+                reply.tool_calls.push(ToolCall {
+                    name: name.to_string(),
+                    arguments: part["functionCall"]["args"].clone(),
+                });
+            }
+        }
+        Ok(reply)
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+        params: &GenerationParams,
+        on_delta: &(dyn for<'a> Fn(&'a str) + Send + Sync),
+    ) -> Result<ChatReply, AiError> {
+        let url = format!(
+            "{}/{}:streamGenerateContent?alt=sse&key={}",
+            self.config.base_url, self.config.model, self.config.api_key
+        );
+        let mut body = chat_body(messages, params);
+        if !tools.is_empty() {
+            body["tools"] = tools_json(tools);
+        }
 
-        let res = self
-            .client
-            .post(&url)
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {}", e))?;
+        let res = self.client.post(&url).json(&body).send().await?;
 
         if !res.status().is_success() {
-            return Err(format!("API Error: {}", res.status()));
+            return Err(AiError::from_response(res).await);
         }
 
-        let json: Value = res
-            .json()
-            .await
-            .map_err(|e| format!("Parse error: {}", e))?;
-        json["candidates"][0]["content"]["parts"][0]["text"]
-            .as_str()
-            .map(|s| s.to_string())
-            .ok_or_else(|| "No content in response".to_string())
+        // Each event is a partial `generateContent` response
+        let mut reply = ChatReply::default();
+        crate::ai::providers::read_lines(res, |line| {
+            let data = match line.strip_prefix("data:") {
+                Some(data) => data.trim(),
+                None => return Ok(true),
+            };
+            let event: Value = serde_json::from_str(data)?;
+
+            for part in event["candidates"][0]["content"]["parts"]
+                .as_array()
+                .into_iter()
+                .flatten()
+            {
+                if let Some(text) = part["text"].as_str() {
+                    reply.content.push_str(text);
+                    on_delta(text);
+                }
+                if let Some(name) = part["functionCall"]["name"].as_str() {
+                    reply.tool_calls.push(ToolCall {
+                        name: name.to_string(),
+                        arguments: part["functionCall"]["args"].clone(),
+                    });
+                }
+            }
+
+            // Every event carries the counts so far
+            if let Some(u) = usage(&event) {
+                reply.usage = Some(u);
+            }
+            let has_content = !reply.content.is_empty() || !reply.tool_calls.is_empty();
+            match stop_error(&event, has_content) {
+                Some(error) => Err(error),
+                None => Ok(true),
+            }
+        })
+        .await?;
+
+        if reply.content.is_empty() && reply.tool_calls.is_empty() {
+            return Err(AiError::Other("No content in response".to_string()));
+        }
+        Ok(reply)
     }
 
-    async fn chat(&self, _messages: &[crate::ai::models::ChatMessage]) -> Result<String, String> {
-        // TODO: Implement multi-turn chat for Gemini
-        // Convert ChatMessage to Gemini content structure
-        self.ask(_messages.last().map(|m| m.content.as_str()).unwrap_or(""))
-            .await
+    async fn list_models(&self) -> Result<Vec<String>, AiError> {
+        crate::ai::providers::cached_models(
+            &self.config.base_url,
+            &self.config.api_key,
+            self.fetch_models(),
+            &["gemini-2.5-pro", "gemini-2.5-flash", "gemini-2.0-flash"],
+        )
+        .await
     }
 
-    async fn list_models(&self) -> Result<Vec<String>, String> {
-        Ok(vec![
-            "gemini-pro".to_string(),
-            "gemini-1.5-pro".to_string(),
-            "gemini-1.5-flash".to_string(),
-        ])
+    async fn count_tokens(&self, text: &str) -> Result<TokenCount, AiError> {
+        // Estimated when the endpoint can't be reached, as callers need a number
+        match self.count_tokens_remote(text).await {
+            Ok(tokens) => Ok(TokenCount {
+                tokens,
+                exact: true,
+            }),
+            Err(e) => {
+                eprintln!("Gemini countTokens failed, estimating: {}", e);
+                Ok(TokenCount::estimate(text))
+            }
+        }
     }
 
-    async fn count_tokens(&self, text: &str) -> Result<usize, String> {
-        // Using cl100k_base as estimation. Gemini has countTokens API but for local speed we use this.
-        let bpe =
-            tiktoken_rs::cl100k_base().map_err(|e| format!("Failed to load tokenizer: {}", e))?;
+    fn model(&self) -> &str {
+        &self.config.model
+    }
 
-        Ok(bpe.encode_with_special_tokens(text).len())
+    fn context_window(&self) -> usize {
+        crate::ai::models::context_window(&self.config.model)
     }
 
     fn get_info(&self) -> String {
@@ -1,4 +1,6 @@
-use crate::ai::config::OllamaConfig;
+use crate::ai::config::{GenerationParams, OllamaConfig};
+use crate::ai::error::AiError;
+use crate::ai::models::{ChatMessage, ChatReply, TokenCount, ToolCall, ToolSpec, Usage};
 use crate::ai::traits::AiProviderTrait;
 use async_trait::async_trait;
 use reqwest::Client;
@@ -16,15 +18,92 @@ impl OllamaProvider {
     /// Creates a new `OllamaProvider` with the given configuration.
     pub fn new(config: OllamaConfig) -> Self {
         Self {
-            client: Client::new(),
+            client: crate::ai::providers::client_with_headers(&[]),
             config,
         }
     }
+
+    /// Creates a provider that sends extra HTTP headers with every request.
+    pub fn with_headers(config: OllamaConfig, headers: &[(String, String)]) -> Self {
+        Self {
+            client: crate::ai::providers::client_with_headers(headers),
+            config,
+        }
+    }
+
+    /// Counts tokens with the model's own tokenizer: `/api/tokenize` where the
+    /// server has it, else the `prompt_eval_count` of an `/api/embed` call.
+    /// `None` when neither works, e.g. on older servers or chat-only models.
+    async fn count_tokens_remote(&self, text: &str) -> Option<usize> {
+        let base = self.config.base_url.trim_end_matches('/');
+        let base = base.strip_suffix("/api").unwrap_or(base);
+
+        let body = json!({ "model": self.config.model, "content": text });
+        if let Ok(res) = self
+            .client
+            .post(format!("{}/api/tokenize", base))
+            .json(&body)
+            .send()
+            .await
+        {
+            if res.status().is_success() {
+                if let Ok(json) = res.json::<Value>().await {
+                    if let Some(tokens) = json["tokens"].as_array() {
+                        return Some(tokens.len());
+                    }
+                }
+            }
+        }
+
+        let body = json!({ "model": self.config.model, "input": text, "truncate": false });
+        let res = self
+            .client
+            .post(format!("{}/api/embed", base))
+            .json(&body)
+            .send()
+            .await
+            .ok()?;
+        if !res.status().is_success() {
+            return None;
+        }
+        let json: Value = res.json().await.ok()?;
+        json["prompt_eval_count"].as_u64().map(|t| t as usize)
+    }
+}
+
+/// Sets the sampling `options` of a request, and how long the model stays loaded.
+fn apply_params(body: &mut Value, params: &GenerationParams) {
+    let mut options = serde_json::Map::new();
+    if let Some(temperature) = params.temperature {
+        options.insert("temperature".into(), json!(temperature));
+    }
+    if let Some(max_tokens) = params.max_tokens {
+        options.insert("num_predict".into(), json!(max_tokens));
+    }
+    if let Some(top_p) = params.top_p {
+        options.insert("top_p".into(), json!(top_p));
+    }
+    if !params.stop.is_empty() {
+        options.insert("stop".into(), json!(params.stop));
+    }
+    if let Some(num_ctx) = params.num_ctx {
+        options.insert("num_ctx".into(), json!(num_ctx));
+    }
+    if !options.is_empty() {
+        body["options"] = Value::Object(options);
+    }
+    if let Some(keep_alive) = &params.keep_alive {
+        // A bare number is a count of seconds, which the API wants as a number
+        body["keep_alive"] = match keep_alive.parse::<i64>() {
+            Ok(seconds) => json!(seconds),
+            Err(_) => json!(keep_alive),
+        };
+    }
 }
 
 #[async_trait]
 impl AiProviderTrait for OllamaProvider {
-    async fn ask(&self, question: &str) -> Result<String, String> {
+    async fn ask(&self, question: &str, params: &GenerationParams) -> Result<String, AiError> {
         let base = self.config.base_url.trim_end_matches('/');
         let base = if base.ends_with("/api") {
             &base[..base.len() - 4]
@@ -33,35 +112,31 @@ impl AiProviderTrait for OllamaProvider {
         };
         let url = format!("{}/api/generate", base);
 
-        let body = json!({
+        let mut body = json!({
             "model": self.config.model,
             "prompt": question,
             "stream": false
         });
+        apply_params(&mut body, params);
 
-        let res = self
-            .client
-            .post(&url)
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {}", e))?;
+        let res = self.client.post(&url).json(&body).send().await?;
 
         if !res.status().is_success() {
-            return Err(format!("API Error: {}", res.status()));
+            return Err(AiError::from_response(res).await);
         }
 
-        let json: Value = res
-            .json()
-            .await
-            .map_err(|e| format!("Parse error: {}", e))?;
+        let json: Value = res.json().await?;
         json["response"]
             .as_str()
             .map(|s| s.to_string())
-            .ok_or_else(|| "No response field".to_string())
+            .ok_or_else(|| AiError::Parse("No response field".to_string()))
     }
 
-    async fn chat(&self, messages: &[crate::ai::models::ChatMessage]) -> Result<String, String> {
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        params: &GenerationParams,
+    ) -> Result<String, AiError> {
         let base = self.config.base_url.trim_end_matches('/');
         let base = if base.ends_with("/api") {
             &base[..base.len() - 4]
@@ -70,61 +145,162 @@ impl AiProviderTrait for OllamaProvider {
         };
         let url = format!("{}/api/chat", base);
 
-        let body = json!({
+        let mut body = json!({
             "model": self.config.model,
             "messages": messages,
             "stream": false
         });
+        apply_params(&mut body, params);
 
-        let res = self
-            .client
-            .post(&url)
-            .json(&body)
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {}", e))?;
+        let res = self.client.post(&url).json(&body).send().await?;
 
         if !res.status().is_success() {
-            // Try to read error body if possible
-            let status = res.status();
-            let text = res.text().await.unwrap_or_default();
-            return Err(format!("API Error: {} - {}", status, text));
+            return Err(AiError::from_response(res).await);
         }
 
-        let json: Value = res
-            .json()
-            .await
-            .map_err(|e| format!("Parse error: {}", e))?;
+        let json: Value = res.json().await?;
         json["message"]["content"]
             .as_str()
             .map(|s| s.to_string())
-            .ok_or_else(|| "No message content in response".to_string())
+            .ok_or_else(|| AiError::Parse("No message content in response".to_string()))
     }
 
-    async fn list_models(&self) -> Result<Vec<String>, String> {
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+        params: &GenerationParams,
+    ) -> Result<ChatReply, AiError> {
+        let base = self.config.base_url.trim_end_matches('/');
+        let base = base.strip_suffix("/api").unwrap_or(base);
+        let url = format!("{}/api/chat", base);
+
+        let tools: Vec<Value> = tools.iter().map(ToolSpec::to_openai_json).collect();
+        let mut body = json!({
+            "model": self.config.model,
+            "messages": messages,
+            "tools": tools,
+            "stream": false
+        });
+        apply_params(&mut body, params);
+
+        let res = self.client.post(&url).json(&body).send().await?;
+
+        if !res.status().is_success() {
+            let error = AiError::from_response(res).await;
+            // Many local models have no tool support; use the text protocol for them
+            if error.to_string().contains("does not support tools") {
+                let reply = self.chat(messages, params).await?;
+                return Ok(ChatReply::from_text(&reply));
+            }
+            return Err(error);
+        }
+
+        let json: Value = res.json().await?;
+        let message = &json["message"];
+
+        let tool_calls = message["tool_calls"]
+            .as_array()
+            .map(|calls| {
+                calls
+                    .iter()
+                    .filter_map(|c| {
+                        Some(ToolCall {
+                            name: c["function"]["name"].as_str()?.to_string(),
+                            arguments: c["function"]["arguments"].clone(),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(ChatReply {
+            content: message["content"].as_str().unwrap_or_default().to_string(),
+            tool_calls,
+            usage: Usage::from_json(&json, "prompt_eval_count", "eval_count"),
+            ..ChatReply::default()
+        })
+    }
+
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+        params: &GenerationParams,
+        on_delta: &(dyn for<'a> Fn(&'a str) + Send + Sync),
+    ) -> Result<ChatReply, AiError> {
+        let base = self.config.base_url.trim_end_matches('/');
+        let base = base.strip_suffix("/api").unwrap_or(base);
+        let url = format!("{}/api/chat", base);
+
+        let mut body = json!({
+            "model": self.config.model,
+            "messages": messages,
+            "stream": true
+        });
+        if !tools.is_empty() {
+            body["tools"] = Value::Array(tools.iter().map(ToolSpec::to_openai_json).collect());
+        }
+        apply_params(&mut body, params);
+
+        let res = self.client.post(&url).json(&body).send().await?;
+
+        if !res.status().is_success() {
+            let error = AiError::from_response(res).await;
+            // Many local models have no tool support; use the text protocol for them
+            if !tools.is_empty() && error.to_string().contains("does not support tools") {
+                let reply = self.chat_stream(messages, &[], params, on_delta).await?;
+                return Ok(ChatReply::from_text(&reply.content));
+            }
+            return Err(error);
+        }
+
+        // One JSON object per line until `done`
+        let mut reply = ChatReply::default();
+        crate::ai::providers::read_lines(res, |line| {
+            let event: Value = serde_json::from_str(line)?;
+            if let Some(error) = event["error"].as_str() {
+                return Err(AiError::Other(format!("API Error: {}", error)));
+            }
+            let message = &event["message"];
+            if let Some(text) = message["content"].as_str().filter(|t| !t.is_empty()) {
+                reply.content.push_str(text);
+                on_delta(text);
+            }
+            for call in message["tool_calls"].as_array().into_iter().flatten() {
+                if let Some(name) = call["function"]["name"].as_str() {
+                    reply.tool_calls.push(ToolCall {
+                        name: name.to_string(),
+                        arguments: call["function"]["arguments"].clone(),
+                    });
+                }
+            }
+            let done = event["done"].as_bool().unwrap_or(false);
+            if done {
+                reply.usage = Usage::from_json(&event, "prompt_eval_count", "eval_count");
+            }
+            Ok(!done)
+        })
+        .await?;
+        Ok(reply)
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AiError> {
         // Ollama API endpoint might change based on version, but usually /api/tags
         let base = self.config.base_url.replace("/api", ""); // standard construct usually includes /api
         let url = format!("{}/api/tags", base.trim_end_matches('/'));
 
-        let res = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {}", e))?;
+        let res = self.client.get(&url).send().await?;
 
         if !res.status().is_success() {
-            return Err(format!("API Error: {}", res.status()));
+            return Err(AiError::from_response(res).await);
         }
 
-        let json: Value = res
-            .json()
-            .await
-            .map_err(|e| format!("Parse error: {}", e))?;
+        let json: Value = res.json().await?;
 
         let models = json["models"]
             .as_array()
-            .ok_or_else(|| "Invalid response format".to_string())?;
+            .ok_or_else(|| AiError::Parse("Invalid response format".to_string()))?;
 
         let names = models
             .iter()
@@ -134,13 +310,22 @@ impl AiProviderTrait for OllamaProvider {
         Ok(names)
     }
 
-    async fn count_tokens(&self, text: &str) -> Result<usize, String> {
-        // Use cl100k_base (GPT-4) as a reasonable approximation for modern LLMs
-        // since exact counting for arbitrary Ollama models is complex locally without the specific tokenizer.
-        let bpe =
-            tiktoken_rs::cl100k_base().map_err(|e| format!("Failed to load tokenizer: {}", e))?;
+    async fn count_tokens(&self, text: &str) -> Result<TokenCount, AiError> {
+        match self.count_tokens_remote(text).await {
+            Some(tokens) => Ok(TokenCount {
+                tokens,
+                exact: true,
+            }),
+            None => Ok(TokenCount::estimate(text)),
+        }
+    }
+
+    fn model(&self) -> &str {
+        &self.config.model
+    }
 
-        Ok(bpe.encode_with_special_tokens(text).len())
+    fn context_window(&self) -> usize {
+        crate::ai::models::context_window(&self.config.model)
     }
 
     fn get_info(&self) -> String {
@@ -150,3 +335,56 @@ impl AiProviderTrait for OllamaProvider {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn provider(base_url: String) -> OllamaProvider {
+        OllamaProvider::new(OllamaConfig {
+            base_url,
+            model: "llama3".to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn counts_tokens_with_the_server_tokenizer() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let n = socket.read(&mut buf).await.unwrap();
+            let body = r#"{"tokens":[1,2,3]}"#;
+            socket
+                .write_all(
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                    .as_bytes(),
+                )
+                .await
+                .unwrap();
+            String::from_utf8_lossy(&buf[..n]).to_string()
+        });
+
+        let count = provider(url).count_tokens("hello world").await.unwrap();
+        assert_eq!((count.tokens, count.exact), (3, true));
+        assert!(server.await.unwrap().starts_with("POST /api/tokenize "));
+    }
+
+    #[tokio::test]
+    async fn estimates_when_the_server_is_unreachable() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let count = provider(url).count_tokens("hello world").await.unwrap();
+        assert_eq!(count.tokens, TokenCount::estimate("hello world").tokens);
+        assert!(!count.exact);
+    }
+}
@@ -1,4 +1,6 @@
-use crate::ai::config::OpenAiConfig;
+use crate::ai::config::{GenerationParams, OpenAiConfig};
+use crate::ai::error::AiError;
+use crate::ai::models::{ChatMessage, ChatReply, TokenCount, ToolCall, ToolSpec, Usage};
 use crate::ai::traits::AiProviderTrait;
 use async_trait::async_trait;
 use reqwest::Client;
@@ -16,20 +18,114 @@ impl OpenAiProvider {
     /// Creates a new `OpenAiProvider` with the given configuration.
     pub fn new(config: OpenAiConfig) -> Self {
         Self {
-            client: Client::new(),
+            client: crate::ai::providers::client_with_headers(&[]),
             config,
         }
     }
+
+    /// Creates a provider that sends extra HTTP headers with every request.
+    pub fn with_headers(config: OpenAiConfig, headers: &[(String, String)]) -> Self {
+        Self {
+            client: crate::ai::providers::client_with_headers(headers),
+            config,
+        }
+    }
+
+    /// Adds the sampling parameters to a Chat Completions request body.
+    ///
+    /// OpenAI's reasoning models take `max_completion_tokens` and reject sampling
+    /// settings; compatible servers still expect `max_tokens`.
+    fn apply_params(&self, body: &mut Value, params: &GenerationParams) {
+        let model = self.config.model.rsplit('/').next().unwrap_or_default();
+        let reasoning = self.config.base_url.contains("api.openai.com")
+            && ["o1", "o3", "o4", "gpt-5"]
+                .iter()
+                .any(|prefix| model.starts_with(prefix));
+
+        if let Some(max_tokens) = params.max_tokens {
+            let key = if reasoning {
+                "max_completion_tokens"
+            } else {
+                "max_tokens"
+            };
+            body[key] = json!(max_tokens);
+        }
+        if !reasoning {
+            if let Some(temperature) = params.temperature {
+                body["temperature"] = json!(temperature);
+            }
+            if let Some(top_p) = params.top_p {
+                body["top_p"] = json!(top_p);
+            }
+        }
+        if !params.stop.is_empty() {
+            body["stop"] = json!(params.stop);
+        }
+    }
+
+    /// Fetches the models of the account from `GET /models`, newest first.
+    ///
+    /// On the OpenAI API itself, models that can't chat (embeddings, audio, images,
+    /// moderation, legacy completions) are left out. Other OpenAI-compatible servers
+    /// name their models freely (e.g. `qwen2.5-7b-instruct`), so they are listed as is.
+    async fn fetch_models(&self) -> Result<Vec<String>, AiError> {
+        const EXCLUDED: [&str; 13] = [
+            "embedding",
+            "whisper",
+            "tts",
+            "dall-e",
+            "davinci",
+            "babbage",
+            "moderation",
+            "audio",
+            "realtime",
+            "transcribe",
+            "image",
+            "search",
+            "instruct",
+        ];
+
+        let url = format!("{}/models", self.config.base_url.trim_end_matches('/'));
+        let res = self
+            .client
+            .get(&url)
+            .header("Authorization", format!("Bearer {}", self.config.api_key))
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            return Err(AiError::from_response(res).await);
+        }
+        let json: Value = res.json().await?;
+
+        let official = self.config.base_url.contains("api.openai.com");
+        let mut models: Vec<(i64, String)> = json["data"]
+            .as_array()
+            .ok_or_else(|| AiError::Parse("Invalid response format".to_string()))?
+            .iter()
+            .filter_map(|m| Some((m["created"].as_i64().unwrap_or(0), m["id"].as_str()?)))
+            .filter(|(_, id)| {
+                !official
+                    || (!EXCLUDED.iter().any(|e| id.contains(e))
+                        && ["gpt-", "chatgpt-", "o1", "o3", "o4"]
+                            .iter()
+                            .any(|p| id.starts_with(p)))
+            })
+            .map(|(created, id)| (created, id.to_string()))
+            .collect();
+        models.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        Ok(models.into_iter().map(|(_, id)| id).collect())
+    }
 }
 
 #[async_trait]
 impl AiProviderTrait for OpenAiProvider {
-    async fn ask(&self, question: &str) -> Result<String, String> {
+    async fn ask(&self, question: &str, params: &GenerationParams) -> Result<String, AiError> {
         let url = format!("{}/chat/completions", self.config.base_url);
-        let body = json!({
+        let mut body = json!({
             "model": self.config.model,
             "messages": [{"role": "user", "content": question}]
         });
+        self.apply_params(&mut body, params);
 
         let res = self
             .client
@@ -37,29 +133,30 @@ impl AiProviderTrait for OpenAiProvider {
             .header("Authorization", format!("Bearer {}", self.config.api_key))
             .json(&body)
             .send()
-            .await
-            .map_err(|e| format!("Request failed: {}", e))?;
+            .await?;
 
         if !res.status().is_success() {
-            return Err(format!("API Error: {}", res.status()));
+            return Err(AiError::from_response(res).await);
         }
 
-        let json: Value = res
-            .json()
-            .await
-            .map_err(|e| format!("Parse error: {}", e))?;
+        let json: Value = res.json().await?;
         json["choices"][0]["message"]["content"]
             .as_str()
             .map(|s| s.to_string())
-            .ok_or_else(|| "No content in response".to_string())
+            .ok_or_else(|| AiError::Other("No content in response".to_string()))
     }
 
-    async fn chat(&self, messages: &[crate::ai::models::ChatMessage]) -> Result<String, String> {
+    async fn chat(
+        &self,
+        messages: &[ChatMessage],
+        params: &GenerationParams,
+    ) -> Result<String, AiError> {
         let url = format!("{}/chat/completions", self.config.base_url);
-        let body = json!({
+        let mut body = json!({
             "model": self.config.model,
             "messages": messages
         });
+        self.apply_params(&mut body, params);
 
         let res = self
             .client
@@ -67,44 +164,253 @@ impl AiProviderTrait for OpenAiProvider {
             .header("Authorization", format!("Bearer {}", self.config.api_key))
             .json(&body)
             .send()
-            .await
-            .map_err(|e| format!("Request failed: {}", e))?;
+            .await?;
 
         if !res.status().is_success() {
-            return Err(format!("API Error: {}", res.status()));
+            return Err(AiError::from_response(res).await);
         }
 
-        let json: Value = res
-            .json()
-            .await
-            .map_err(|e| format!("Parse error: {}", e))?;
+        let json: Value = res.json().await?;
         json["choices"][0]["message"]["content"]
             .as_str()
             .map(|s| s.to_string())
-            .ok_or_else(|| "No content in response".to_string())
+            .ok_or_else(|| AiError::Other("No content in response".to_string()))
     }
 
-    async fn list_models(&self) -> Result<Vec<String>, String> {
-        Ok(vec![
-            "gpt-4o".to_string(),
-            "gpt-4-turbo".to_string(),
-            "gpt-3.5-turbo".to_string(),
-            "gpt-4o-mini".to_string(),
-            "gpt-5-nano".to_string(),
-            "gpt-5-nano-2025-08-07".to_string(),
-        ])
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+        params: &GenerationParams,
+    ) -> Result<ChatReply, AiError> {
+        let url = format!("{}/chat/completions", self.config.base_url);
+        let tools: Vec<Value> = tools.iter().map(ToolSpec::to_openai_json).collect();
+        let mut body = json!({
+            "model": self.config.model,
+            "messages": messages,
+            "tools": tools
+        });
+        self.apply_params(&mut body, params);
+
+        let res = self
+            .client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", self.config.api_key))
+            .json(&body)
+            .send()
+            .await?;
+
+        if !res.status().is_success() {
+            return Err(AiError::from_response(res).await);
+        }
+
+        let json: Value = res.json().await?;
+        let message = &json["choices"][0]["message"];
+
+        // Arguments arrive as a JSON-encoded string
+        let tool_calls = message["tool_calls"]
+            .as_array()
+            .map(|calls| {
+                calls
+                    .iter()
+                    .filter_map(|c| {
+                        Some(ToolCall {
+                            name: c["function"]["name"].as_str()?.to_string(),
+                            arguments: serde_json::from_str(
+                                c["function"]["arguments"].as_str().unwrap_or("{}"),
+                            )
+                            .ok()?,
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(ChatReply {
+            content: message["content"].as_str().unwrap_or_default().to_string(),
+            tool_calls,
+            usage: Usage::from_json(&json["usage"], "prompt_tokens", "completion_tokens"),
+            ..ChatReply::default()
+        })
     }
 
-    async fn count_tokens(&self, text: &str) -> Result<usize, String> {
-        // Try to get encoding for specific model, fallback to cl100k_base (gpt-4)
-        let bpe = tiktoken_rs::get_bpe_from_model(&self.config.model)
-            .or_else(|_| tiktoken_rs::cl100k_base())
-            .map_err(|e| format!("Failed to load tokenizer: {}", e))?;
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+        params: &GenerationParams,
+        on_delta: &(dyn for<'a> Fn(&'a str) + Send + Sync),
+    ) -> Result<ChatReply, AiError> {
+        let url = format!("{}/chat/completions", self.config.base_url);
+        let mut body = json!({
+            "model": self.config.model,
+            "messages": messages,
+            "stream": true,
+            "stream_options": {"include_usage": true}
+        });
+        if !tools.is_empty() {
+            body["tools"] = Value::Array(tools.iter().map(ToolSpec::to_openai_json).collect());
+        }
+        self.apply_params(&mut body, params);
+
+        let res = self
+            .client
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", self.config.api_key))
+            .json(&body)
+            .send()
+            .await?;
+
+        if !res.status().is_success() {
+            return Err(AiError::from_response(res).await);
+        }
+
+        // Tool calls arrive in fragments keyed by index: the name first, then pieces of arguments
+        let mut content = String::new();
+        let mut calls: Vec<(String, String)> = Vec::new();
+        let mut usage = None;
+        crate::ai::providers::read_lines(res, |line| {
+            let data = match line.strip_prefix("data:") {
+                Some(data) => data.trim(),
+                None => return Ok(true),
+            };
+            if data == "[DONE]" {
+                return Ok(false);
+            }
+            let event: Value = serde_json::from_str(data)?;
+            let delta = &event["choices"][0]["delta"];
+            // Sent in a last chunk without choices
+            if let Some(u) = Usage::from_json(&event["usage"], "prompt_tokens", "completion_tokens")
+            {
+                usage = Some(u);
+            }
+
+            if let Some(text) = delta["content"].as_str() {
+                content.push_str(text);
+                on_delta(text);
+            }
+            for call in delta["tool_calls"].as_array().into_iter().flatten() {
+                let index = call["index"].as_u64().unwrap_or(0) as usize;
+                if calls.len() <= index {
+                    calls.resize(index + 1, (String::new(), String::new()));
+                }
+                if let Some(name) = call["function"]["name"].as_str() {
+                    calls[index].0.push_str(name);
+                }
+                if let Some(args) = call["function"]["arguments"].as_str() {
+                    calls[index].1.push_str(args);
+                }
+            }
+            Ok(true)
+        })
+        .await?;
+
+        let tool_calls = calls
+            .into_iter()
+            .filter(|(name, _)| !name.is_empty())
+            .filter_map(|(name, args)| {
+                Some(ToolCall {
+                    name,
+                    arguments: serde_json::from_str(if args.is_empty() { "{}" } else { &args })
+                        .ok()?,
+                })
+            })
+            .collect();
+        Ok(ChatReply {
+            content,
+            tool_calls,
+            usage,
+            ..ChatReply::default()
+        })
+    }
 
-        Ok(bpe.encode_with_special_tokens(text).len())
+    async fn list_models(&self) -> Result<Vec<String>, AiError> {
+        // Local servers don't serve OpenAI's models, so they get no fallback list
+        let fallback: &[&str] = if self.config.base_url.contains("api.openai.com") {
+            &[
+                "gpt-4o",
+                "gpt-4o-mini",
+                "gpt-4.1",
+                "gpt-4.1-mini",
+                "gpt-4-turbo",
+            ]
+        } else {
+            &[]
+        };
+        crate::ai::providers::cached_models(
+            &self.config.base_url,
+            &self.config.api_key,
+            self.fetch_models(),
+            fallback,
+        )
+        .await
+    }
+
+    async fn count_tokens(&self, text: &str) -> Result<TokenCount, AiError> {
+        use tiktoken_rs::tokenizer::{get_tokenizer, Tokenizer};
+
+        // Gateways such as OpenRouter prefix the model with its vendor
+        let model = self.config.model.rsplit('/').next().unwrap_or_default();
+        let tokenizer = get_tokenizer(model);
+        let bpe = match tokenizer {
+            Some(Tokenizer::Cl100kBase) => tiktoken_rs::cl100k_base_singleton(),
+            Some(Tokenizer::O200kHarmony) => tiktoken_rs::o200k_harmony_singleton(),
+            Some(Tokenizer::P50kBase) => tiktoken_rs::p50k_base_singleton(),
+            Some(Tokenizer::P50kEdit) => tiktoken_rs::p50k_edit_singleton(),
+            Some(Tokenizer::R50kBase | Tokenizer::Gpt2) => tiktoken_rs::r50k_base_singleton(),
+            // Models newer than the tokenizer table all use o200k_base
+            Some(Tokenizer::O200kBase) | None => tiktoken_rs::o200k_base_singleton(),
+        };
+
+        Ok(TokenCount {
+            tokens: bpe.encode_with_special_tokens(text).len(),
+            exact: tokenizer.is_some(),
+        })
+    }
+
+    fn model(&self) -> &str {
+        &self.config.model
+    }
+
+    fn context_window(&self) -> usize {
+        crate::ai::models::context_window(&self.config.model)
     }
 
     fn get_info(&self) -> String {
         format!("OpenAI (Model: {})", self.config.model)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn count(model: &str, text: &str) -> TokenCount {
+        OpenAiProvider::new(OpenAiConfig {
+            api_key: String::new(),
+            model: model.to_string(),
+            base_url: String::new(),
+        })
+        .count_tokens(text)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn counts_with_the_model_tokenizer() {
+        let count = count("gpt-4o", "hello world").await;
+        assert_eq!((count.tokens, count.exact), (2, true));
+    }
+
+    #[tokio::test]
+    async fn strips_the_vendor_prefix_of_gateways() {
+        assert!(count("openai/gpt-4o-mini", "hello world").await.exact);
+    }
+
+    #[tokio::test]
+    async fn flags_counts_for_unknown_models_as_estimates() {
+        let count = count("mistral-large", "hello world").await;
+        assert!(count.tokens > 0);
+        assert!(!count.exact);
+    }
+}
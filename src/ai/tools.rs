@@ -0,0 +1,329 @@
+use crate::ai::models::{ToolCall, ToolSpec};
+use crate::core::discovery::Discovery;
+use crate::executor::ssh::SshExecutor;
+use crate::executor::validator;
+use crate::models::ManagedServer;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// Largest number of lines a tool may return from a file or the journal.
+const MAX_LINES: u32 = 200;
+/// Most bytes of output a tool reads, so endless output can't exhaust memory.
+const MAX_OUTPUT_BYTES: u64 = 1024 * 1024;
+
+/// Arguments of the `run_command` tool.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RunCommand {
+    /// Alias of the server to run on. Models using the text protocol omit it.
+    #[serde(default)]
+    pub server: Option<String>,
+    pub command: String,
+    /// Why the model wants to run the command.
+    #[serde(default)]
+    pub rationale: Option<String>,
+}
+
+/// A typed tool call the AI can make during a session.
+///
+/// Every tool except `run_command` maps to a fixed, read-only command whose
+/// arguments are validated here, so those run without asking the user.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "name", content = "arguments", rename_all = "snake_case")]
+pub enum Tool {
+    RunCommand(RunCommand),
+    ReadFile {
+        path: String,
+        #[serde(default = "default_lines")]
+        lines: u32,
+    },
+    ServiceStatus {
+        unit: String,
+    },
+    Journal {
+        unit: String,
+        #[serde(default = "default_since")]
+        since: String,
+    },
+    ListProcesses {
+        #[serde(default = "default_sort")]
+        sort: String,
+    },
+    DiskUsage {
+        #[serde(default = "default_path")]
+        path: String,
+    },
+    DiscoveryReport {},
+}
+
+fn default_lines() -> u32 {
+    50
+}
+
+fn default_since() -> String {
+    "1 hour ago".to_string()
+}
+
+fn default_sort() -> String {
+    "cpu".to_string()
+}
+
+fn default_path() -> String {
+    "/".to_string()
+}
+
+impl Tool {
+    /// Parses a tool call from the model into a typed tool.
+    pub fn from_call(call: &ToolCall) -> Result<Self, String> {
+        // Tools without parameters may come with no arguments at all
+        let arguments = match &call.arguments {
+            Value::Null => json!({}),
+            other => other.clone(),
+        };
+        let tool: Tool = serde_json::from_value(json!({
+            "name": call.name,
+            "arguments": arguments
+        }))
+        .map_err(|e| format!("Invalid call to {}: {}", call.name, e))?;
+
+        if let Tool::RunCommand(c) = &tool {
+            if c.command.trim().is_empty() {
+                return Err("run_command needs a command".to_string());
+            }
+        }
+        // Files that may hold secrets are read only once the user approves
+        if let Tool::ReadFile { path, .. } = &tool {
+            if validator::is_sensitive_path(path) {
+                return Ok(Tool::RunCommand(RunCommand {
+                    server: None,
+                    command: tool.command()?,
+                    rationale: Some(format!("Read {}, which may hold secrets", path)),
+                }));
+            }
+        }
+        Ok(tool)
+    }
+
+    /// Whether the tool only reads state and may run without approval.
+    pub fn is_read_only(&self) -> bool {
+        !matches!(self, Tool::RunCommand(_))
+    }
+
+    /// Short human readable form, e.g. `journal(nginx, 1 hour ago)`.
+    pub fn describe(&self) -> String {
+        match self {
+            Tool::RunCommand(c) => c.command.clone(),
+            Tool::ReadFile { path, lines } => format!("read_file({}, {} lines)", path, lines),
+            Tool::ServiceStatus { unit } => format!("service_status({})", unit),
+            Tool::Journal { unit, since } => format!("journal({}, {})", unit, since),
+            Tool::ListProcesses { sort } => format!("list_processes({})", sort),
+            Tool::DiskUsage { path } => format!("disk_usage({})", path),
+            Tool::DiscoveryReport {} => "discovery_report()".to_string(),
+        }
+    }
+
+    /// Builds the vetted shell command for a read-only tool.
+    fn command(&self) -> Result<String, String> {
+        match self {
+            Tool::RunCommand(c) => Ok(c.command.clone()),
+            Tool::ReadFile { path, lines } => {
+                if validator::is_device_path(path) {
+                    return Err(format!("Cannot read {}, it is a device", path));
+                }
+                // Devices and FIFOs behind other names would never finish reading
+                Ok(format!(
+                    "if [ -f {p} ]; then tail -n {n} {p}; else echo 'Not a regular file' >&2; exit 1; fi",
+                    p = quote_path(path)?,
+                    n = (*lines).clamp(1, MAX_LINES)
+                ))
+            }
+            Tool::ServiceStatus { unit } => Ok(format!(
+                "systemctl show {} --no-pager -p Id,Description,LoadState,ActiveState,SubState,MainPID,ActiveEnterTimestamp,NRestarts,Result",
+                check_unit(unit)?
+            )),
+            Tool::Journal { unit, since } => {
+                let unit = check_unit(unit)?;
+                if since.is_empty()
+                    || !since
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || " :-".contains(c))
+                {
+                    return Err(format!("Invalid time '{}'", since));
+                }
+                Ok(format!(
+                    "journalctl -u {} --since '{}' --no-pager -n {}",
+                    unit, since, MAX_LINES
+                ))
+            }
+            Tool::ListProcesses { sort } => {
+                let key = match sort.as_str() {
+                    "cpu" => "-pcpu",
+                    "mem" | "memory" => "-pmem",
+                    _ => return Err(format!("Invalid sort '{}', use cpu or mem", sort)),
+                };
+                Ok(format!(
+                    "ps -eo pid,user,pcpu,pmem,etime,comm --sort={} | head -n 16",
+                    key
+                ))
+            }
+            Tool::DiskUsage { path } => {
+                let path = quote_path(path)?;
+                Ok(format!(
+                    "df -h {p} && echo && du -xh --max-depth=1 {p} 2>/dev/null | sort -rh | head -n 15",
+                    p = path
+                ))
+            }
+            Tool::DiscoveryReport {} => Err("discovery_report has no command".to_string()),
+        }
+    }
+
+    /// Runs the tool on a server and returns the text handed back to the model.
+    ///
+    /// With a `timeout`, a command that stops responding fails after it; the
+    /// discovery report runs its fixed commands without one. Output beyond
+    /// `MAX_OUTPUT_BYTES` stops the command and is reported as an error.
+    pub fn execute(&self, server: &ManagedServer, timeout: Option<Duration>) -> String {
+        let result = match self {
+            Tool::DiscoveryReport {} => Discovery::run(server)
+                .map(|report| serde_json::to_string_pretty(&report).unwrap_or_default()),
+            Tool::ServiceStatus { .. } => self
+                .command()
+                .and_then(|cmd| {
+                    SshExecutor::execute_with_limits(server, &cmd, timeout, Some(MAX_OUTPUT_BYTES))
+                })
+                .map(|out| format_properties(&out)),
+            _ => self.command().and_then(|cmd| {
+                SshExecutor::execute_with_limits(server, &cmd, timeout, Some(MAX_OUTPUT_BYTES))
+            }),
+        };
+        match result {
+            Ok(out) if out.trim().is_empty() => "(no output)".to_string(),
+            Ok(out) => out,
+            Err(e) => format!("Error: {}", e),
+        }
+    }
+}
+
+/// Accepts absolute paths only and single-quotes them for the shell.
+fn quote_path(path: &str) -> Result<String, String> {
+    if !path.starts_with('/') || path.chars().any(|c| c.is_control() || c == '\'') {
+        return Err(format!("Invalid path '{}', use an absolute path", path));
+    }
+    Ok(format!("'{}'", path))
+}
+
+/// Accepts systemd unit names only.
+fn check_unit(unit: &str) -> Result<&str, String> {
+    if unit.is_empty()
+        || unit.starts_with('-')
+        || !unit
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "@._:-".contains(c))
+    {
+        return Err(format!("Invalid unit name '{}'", unit));
+    }
+    Ok(unit)
+}
+
+/// Turns `systemctl show` output into aligned `Key: value` lines.
+fn format_properties(output: &str) -> String {
+    output
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| format!("{}: {}", key, value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// All tools offered to the model in a session on `alias`.
+pub fn specs(alias: &str) -> Vec<ToolSpec> {
+    vec![
+        ToolSpec {
+            name: "run_command".to_string(),
+            description: "Propose a shell command to run on the server over SSH. The user must approve it before it runs; its output is sent back to you. To run several diagnostics, call it once per command in the same reply, in the order they should run: the user approves them together as a plan and you get all outputs at once. Prefer the read-only tools when they cover what you need.".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "server": {
+                        "type": "string",
+                        "description": "Alias of the server to run the command on.",
+                        "enum": [alias]
+                    },
+                    "command": {
+                        "type": "string",
+                        "description": "The shell command to run."
+                    },
+                    "rationale": {
+                        "type": "string",
+                        "description": "One sentence explaining what the command checks or changes."
+                    }
+                },
+                "required": ["server", "command", "rationale"]
+            }),
+        },
+        ToolSpec {
+            name: "read_file".to_string(),
+            description: "Read the last lines of a file. Runs immediately, except for files that may hold secrets (keys, password hashes, .env), which need the user's approval.".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string", "description": "Absolute path of the file."},
+                    "lines": {"type": "integer", "description": "Number of lines from the end (max 200)."}
+                },
+                "required": ["path"]
+            }),
+        },
+        ToolSpec {
+            name: "service_status".to_string(),
+            description: "Get the state of a systemd unit. Runs immediately.".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "unit": {"type": "string", "description": "Unit name, e.g. nginx.service."}
+                },
+                "required": ["unit"]
+            }),
+        },
+        ToolSpec {
+            name: "journal".to_string(),
+            description: "Read the journal of a systemd unit. Runs immediately.".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "unit": {"type": "string", "description": "Unit name, e.g. nginx.service."},
+                    "since": {"type": "string", "description": "Start time understood by journalctl, e.g. '1 hour ago' or '2024-01-01 10:00'."}
+                },
+                "required": ["unit"]
+            }),
+        },
+        ToolSpec {
+            name: "list_processes".to_string(),
+            description: "List the top 15 processes. Runs immediately.".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "sort": {"type": "string", "enum": ["cpu", "mem"], "description": "Sort by CPU or memory usage."}
+                }
+            }),
+        },
+        ToolSpec {
+            name: "disk_usage".to_string(),
+            description: "Show filesystem usage and the largest directories under a path. Runs immediately.".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "path": {"type": "string", "description": "Absolute path, defaults to /."}
+                }
+            }),
+        },
+        ToolSpec {
+            name: "discovery_report".to_string(),
+            description: "Collect a full report: OS, resources, running services and health checks. Runs immediately.".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {}
+            }),
+        },
+    ]
+}
@@ -1,3 +1,6 @@
+use crate::ai::config::GenerationParams;
+use crate::ai::error::AiError;
+use crate::ai::models::{ChatMessage, ChatReply, TokenCount, ToolSpec};
 use async_trait::async_trait;
 
 /// A trait defining the common interface for all AI providers.
@@ -7,16 +10,61 @@ use async_trait::async_trait;
 #[async_trait]
 pub trait AiProviderTrait: Send + Sync {
     /// Sends a prompt to the AI and returns the response.
-    async fn ask(&self, question: &str) -> Result<String, String>;
+    ///
+    /// Each method sends the set `params` in the form the API expects and ignores
+    /// those it has no equivalent for.
+    async fn ask(&self, question: &str, params: &GenerationParams) -> Result<String, AiError>;
 
     /// Sends a chat history to the AI and returns the next response.
-    async fn chat(&self, messages: &[crate::ai::models::ChatMessage]) -> Result<String, String>;
+    async fn chat(
+        &self,
+        messages: &[crate::ai::models::ChatMessage],
+        params: &GenerationParams,
+    ) -> Result<String, AiError>;
+
+    /// Sends a chat history with the tools the model may call and returns its reply.
+    ///
+    /// The default implementation falls back to the plain-text `RUN:` protocol for
+    /// providers or models without native tool calling.
+    async fn chat_with_tools(
+        &self,
+        messages: &[ChatMessage],
+        _tools: &[ToolSpec],
+        params: &GenerationParams,
+    ) -> Result<ChatReply, AiError> {
+        self.chat(messages, params)
+            .await
+            .map(|r| ChatReply::from_text(&r))
+    }
+
+    /// Like `chat_with_tools`, but hands the text to `on_delta` piece by piece as it
+    /// is generated. The returned reply holds the full text and the tool calls.
+    ///
+    /// The default implementation waits for the whole reply and sends it at once.
+    async fn chat_stream(
+        &self,
+        messages: &[ChatMessage],
+        tools: &[ToolSpec],
+        params: &GenerationParams,
+        on_delta: &(dyn for<'a> Fn(&'a str) + Send + Sync),
+    ) -> Result<ChatReply, AiError> {
+        let reply = self.chat_with_tools(messages, tools, params).await?;
+        on_delta(&reply.content);
+        Ok(reply)
+    }
 
     /// Lists the available models for this provider.
-    async fn list_models(&self) -> Result<Vec<String>, String>;
+    async fn list_models(&self) -> Result<Vec<String>, AiError>;
+
+    /// Returns the number of tokens in the given text, exact when the provider's
+    /// tokenizer is available and estimated otherwise.
+    async fn count_tokens(&self, text: &str) -> Result<TokenCount, AiError>;
+
+    /// Returns the name of the configured model.
+    fn model(&self) -> &str;
 
-    /// Returns the number of tokens in the given text using the provider's tokenizer.
-    async fn count_tokens(&self, text: &str) -> Result<usize, String>;
+    /// Returns the context window of the configured model, in tokens.
+    fn context_window(&self) -> usize;
 
     /// Returns a string describing the provider and its current configuration.
     fn get_info(&self) -> String;
@@ -0,0 +1,257 @@
+use crate::ai::config::ProviderProfile;
+use crate::ai::models::Usage;
+use crate::core::session::escape_html;
+use crate::db::{get_setting, set_setting, DbPool};
+
+/// Prices in USD per million prompt and completion tokens, matched by model prefix.
+///
+/// More specific prefixes come first. Published prices change; `/usage price`
+/// overrides an entry. Models of Ollama providers are free; other models not
+/// listed are unpriced.
+const PRICES: [(&str, f64, f64); 28] = [
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4.1-nano", 0.10, 0.40),
+    ("gpt-4.1-mini", 0.40, 1.60),
+    ("gpt-4.1", 2.00, 8.00),
+    ("gpt-4-turbo", 10.00, 30.00),
+    ("gpt-4", 30.00, 60.00),
+    ("gpt-3.5-turbo", 0.50, 1.50),
+    ("gpt-5-nano", 0.05, 0.40),
+    ("gpt-5-mini", 0.25, 2.00),
+    ("gpt-5", 1.25, 10.00),
+    ("o4-mini", 1.10, 4.40),
+    ("o3-mini", 1.10, 4.40),
+    ("o3", 2.00, 8.00),
+    ("o1-mini", 1.10, 4.40),
+    ("o1", 15.00, 60.00),
+    ("gemini-2.5-flash-lite", 0.10, 0.40),
+    ("gemini-2.5-flash", 0.30, 2.50),
+    ("gemini-2.5-pro", 1.25, 10.00),
+    ("gemini-2.0-flash", 0.10, 0.40),
+    ("gemini-1.5-flash", 0.075, 0.30),
+    ("gemini-1.5-pro", 1.25, 5.00),
+    ("gemini-pro", 0.50, 1.50),
+    ("claude-haiku-4", 1.00, 5.00),
+    ("claude-3-5-haiku", 0.80, 4.00),
+    ("claude-sonnet-4", 3.00, 15.00),
+    ("claude-3-7-sonnet", 3.00, 15.00),
+    ("claude-opus-4", 15.00, 75.00),
+];
+
+/// Who an AI call is made for, recorded with its usage.
+#[derive(Debug, Clone, Default)]
+pub struct CallContext {
+    pub chat_id: Option<i64>,
+    pub session_id: Option<String>,
+    /// Kind of request, one of `config::PURPOSES`, for its generation parameters.
+    pub purpose: Option<&'static str>,
+}
+
+impl CallContext {
+    /// A call made for a chat outside of any session.
+    pub fn chat(chat_id: i64) -> Self {
+        CallContext {
+            chat_id: Some(chat_id),
+            ..CallContext::default()
+        }
+    }
+
+    /// Sets the kind of request.
+    pub fn purpose(mut self, purpose: &'static str) -> Self {
+        self.purpose = Some(purpose);
+        self
+    }
+}
+
+/// Period covered by a usage report.
+#[derive(Debug, Clone, Copy)]
+pub enum Period {
+    Day,
+    Month,
+}
+
+/// Price of a model in USD per million prompt and completion tokens.
+///
+/// A price set with `/usage price` wins over the built-in table.
+pub async fn price(pool: &DbPool, model: &str) -> Option<(f64, f64)> {
+    if let Some(value) = get_setting(pool, "ai_price", model).await {
+        let (input, output) = value.split_once(',')?;
+        return Some((input.parse().ok()?, output.parse().ok()?));
+    }
+    let name = model.to_lowercase();
+    let name = name.rsplit('/').next().unwrap_or(&name);
+    PRICES
+        .iter()
+        .find(|(prefix, _, _)| name.starts_with(prefix))
+        .map(|(_, input, output)| (*input, *output))
+}
+
+/// Price of `model` served by `provider`: nothing for local Ollama providers, `None`
+/// when a paid provider's model has no known price.
+pub async fn provider_price(pool: &DbPool, provider: &str, model: &str) -> Option<(f64, f64)> {
+    if is_free(pool, provider).await {
+        return Some((0.0, 0.0));
+    }
+    price(pool, model).await
+}
+
+/// Whether `provider` runs models locally, either built-in Ollama or an Ollama profile.
+async fn is_free(pool: &DbPool, provider: &str) -> bool {
+    provider.eq_ignore_ascii_case("ollama")
+        || ProviderProfile::load(pool, provider)
+            .await
+            .is_some_and(|p| p.kind == "ollama")
+}
+
+/// Stores a price override for `model`.
+pub async fn set_price(
+    pool: &DbPool,
+    model: &str,
+    input: f64,
+    output: f64,
+) -> Result<(), sqlx::Error> {
+    set_setting(pool, "ai_price", model, &format!("{},{}", input, output)).await
+}
+
+/// Records the usage of one call along with its cost at current prices.
+///
+/// Calls to unpriced models are recorded at no cost and flagged by `/usage`.
+pub async fn record(
+    pool: &DbPool,
+    context: &CallContext,
+    provider: &str,
+    model: &str,
+    usage: Usage,
+    estimated: bool,
+) {
+    let cost = match provider_price(pool, provider, model).await {
+        Some((input, output)) => {
+            (usage.prompt_tokens as f64 * input + usage.completion_tokens as f64 * output)
+                / 1_000_000.0
+        }
+        None => 0.0,
+    };
+
+    if let Err(e) = sqlx::query(
+        "INSERT INTO ai_usage (chat_id, session_id, provider, model, prompt_tokens, completion_tokens, estimated, cost) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(context.chat_id)
+    .bind(&context.session_id)
+    .bind(provider)
+    .bind(model)
+    .bind(usage.prompt_tokens as i64)
+    .bind(usage.completion_tokens as i64)
+    .bind(estimated)
+    .bind(cost)
+    .execute(pool)
+    .await
+    {
+        eprintln!("Failed to record AI usage: {}", e);
+    }
+}
+
+/// Daily spend cap in USD, if one is set.
+pub async fn daily_cap(pool: &DbPool) -> Option<f64> {
+    get_setting(pool, "usage", "daily_cap")
+        .await
+        .and_then(|v| v.parse().ok())
+        .filter(|cap: &f64| *cap > 0.0)
+}
+
+/// Sets or clears (`None`) the daily spend cap.
+pub async fn set_daily_cap(pool: &DbPool, cap: Option<f64>) -> Result<(), sqlx::Error> {
+    let value = cap.map(|c| c.to_string()).unwrap_or_default();
+    set_setting(pool, "usage", "daily_cap", &value).await
+}
+
+/// Spend of the current UTC day in USD.
+pub async fn spent_today(pool: &DbPool) -> f64 {
+    sqlx::query_as::<_, (Option<f64>,)>(
+        "SELECT SUM(cost) FROM ai_usage WHERE date(timestamp) = date('now')",
+    )
+    .fetch_one(pool)
+    .await
+    .ok()
+    .and_then(|r| r.0)
+    .unwrap_or(0.0)
+}
+
+/// Returns `(spent, cap)` when today's spend has reached the daily cap.
+pub async fn cap_reached(pool: &DbPool) -> Option<(f64, f64)> {
+    let cap = daily_cap(pool).await?;
+    let spent = spent_today(pool).await;
+    (spent >= cap).then_some((spent, cap))
+}
+
+/// Builds the `/usage` report: calls, tokens and cost per provider and model.
+pub async fn report(pool: &DbPool, period: Period) -> Result<String, sqlx::Error> {
+    let (filter, label) = match period {
+        Period::Day => ("date(timestamp) = date('now')", "today"),
+        Period::Month => (
+            "strftime('%Y-%m', timestamp) = strftime('%Y-%m', 'now')",
+            "this month",
+        ),
+    };
+    let rows: Vec<(String, String, i64, i64, i64, i64, f64)> = sqlx::query_as(&format!(
+        "SELECT provider, model, COUNT(*), SUM(prompt_tokens), SUM(completion_tokens), SUM(estimated), SUM(cost) \
+         FROM ai_usage WHERE {} GROUP BY provider, model ORDER BY SUM(cost) DESC, COUNT(*) DESC",
+        filter
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    let mut text = format!("💰 <b>AI usage {} (UTC)</b>\n", label);
+    if rows.is_empty() {
+        text.push_str("No AI calls recorded.\n");
+    }
+    let (mut calls, mut tokens, mut cost, mut estimated) = (0, 0, 0.0, 0);
+    let mut unpriced = false;
+    for (provider, model, n, prompt, completion, est, spent) in &rows {
+        text.push_str(&format!(
+            "\n<b>{}</b> · {}\n{} calls · {} in / {} out tokens · ${:.4}",
+            escape_html(provider),
+            escape_html(model),
+            n,
+            prompt,
+            completion,
+            spent
+        ));
+        if provider_price(pool, provider, model).await.is_none() {
+            text.push_str(" ⚠️ no price");
+            unpriced = true;
+        }
+        calls += n;
+        tokens += prompt + completion;
+        cost += spent;
+        estimated += est;
+    }
+    if !rows.is_empty() {
+        text.push_str(&format!(
+            "\n\n<b>Total:</b> {} calls · {} tokens · ${:.4}",
+            calls, tokens, cost
+        ));
+        if estimated > 0 {
+            text.push_str(&format!(
+                "\n<i>{} calls without usage data from the provider were estimated.</i>",
+                estimated
+            ));
+        }
+        if unpriced {
+            text.push_str(
+                "\n<i>⚠️ Models without a price are counted as free and blocked under a daily cap. \
+                 Set one with /usage price &lt;model&gt; &lt;in&gt; &lt;out&gt;.</i>",
+            );
+        }
+    }
+
+    match daily_cap(pool).await {
+        Some(cap) => text.push_str(&format!(
+            "\n\nDaily cap: ${:.2} (${:.4} spent today)",
+            cap,
+            spent_today(pool).await
+        )),
+        None => text.push_str("\n\nNo daily cap. Set one with /usage cap <usd>."),
+    }
+    Ok(text)
+}
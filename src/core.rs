@@ -1,4 +1,12 @@
+pub mod agent;
+pub mod alerts;
+pub mod chart;
+pub mod digest;
 pub mod discovery;
 pub mod dispatcher;
+pub mod howto;
+pub mod maintenance;
+pub mod metrics;
+pub mod report;
 pub mod server_manager;
 pub mod session;
@@ -0,0 +1,580 @@
+use crate::ai::client::AiClient;
+use crate::ai::injection;
+use crate::ai::models::{ChatMessage, ChatReply, ToolSpec};
+use crate::ai::prompts::{self, Vars};
+use crate::ai::tools::{specs, Tool};
+use crate::ai::usage::CallContext;
+use crate::core::session::{escape_html, markdown_to_telegram_html, truncate_output};
+use crate::db::DbPool;
+use crate::executor::validator;
+use crate::models::{AgentEvent, AgentTask, CommandResponse, ManagedServer, TaskStatus};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::sync::oneshot;
+
+/// Most steps (commands and tool calls) an investigation may run.
+const MAX_STEPS: usize = 15;
+/// Investigations are wrapped up after this long, including time spent waiting for approval.
+const MAX_DURATION: Duration = Duration::from_secs(10 * 60);
+/// Longest a single step may run before the investigation moves on without it.
+pub(crate) const STEP_TIMEOUT: Duration = Duration::from_secs(120);
+/// How often a running step checks the Stop button.
+const STOP_POLL: Duration = Duration::from_secs(1);
+/// Longest step output kept for the model, in bytes.
+const MAX_OUTPUT_BYTES: usize = 6000;
+/// Name of the tool the model calls with its findings.
+const FINISH: &str = "finish";
+
+/// What the user decided about a step waiting for approval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Run,
+    Skip,
+    Stop,
+}
+
+/// Handles of a running investigation, used by the buttons of its messages.
+struct Control {
+    stop: Arc<AtomicBool>,
+    pending: Option<oneshot::Sender<Decision>>,
+}
+
+/// Running investigations, by id.
+static RUNS: LazyLock<Mutex<HashMap<u64, Control>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Passes the user's decision to investigation `id`.
+///
+/// Returns `false` if the investigation ended or isn't waiting for a decision.
+/// `Stop` is honoured at the next step even when nothing is waiting.
+pub fn decide(id: u64, decision: Decision) -> bool {
+    let mut runs = RUNS.lock().unwrap();
+    let control = match runs.get_mut(&id) {
+        Some(control) => control,
+        None => return false,
+    };
+    if decision == Decision::Stop {
+        control.stop.store(true, Ordering::Relaxed);
+    }
+    match control.pending.take() {
+        Some(tx) => tx.send(decision).is_ok(),
+        None => decision == Decision::Stop,
+    }
+}
+
+/// Findings reported by the model through the `finish` tool.
+#[derive(Debug, Deserialize)]
+struct Findings {
+    summary: String,
+    #[serde(default)]
+    findings: Vec<Finding>,
+    #[serde(default)]
+    next_steps: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Finding {
+    #[serde(default)]
+    severity: String,
+    title: String,
+    #[serde(default)]
+    evidence: String,
+}
+
+/// The tool the model calls to end the investigation with its findings.
+fn finish_spec() -> ToolSpec {
+    ToolSpec {
+        name: FINISH.to_string(),
+        description: "End the investigation and report what you found.".to_string(),
+        parameters: json!({
+            "type": "object",
+            "properties": {
+                "summary": {
+                    "type": "string",
+                    "description": "Two or three sentences answering the goal."
+                },
+                "findings": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "severity": {"type": "string", "enum": ["critical", "warning", "info", "ok"]},
+                            "title": {"type": "string", "description": "The finding in a few words."},
+                            "evidence": {"type": "string", "description": "The values or log lines that show it."}
+                        },
+                        "required": ["severity", "title"]
+                    }
+                },
+                "next_steps": {
+                    "type": "array",
+                    "items": {"type": "string"},
+                    "description": "What the user should do next, most important first."
+                }
+            },
+            "required": ["summary", "findings"]
+        }),
+    }
+}
+
+/// Starts investigating `goal` on `server` in the background.
+///
+/// The response carries the events of the investigation: progress updates,
+/// approval requests for steps that may change the server, and the final report.
+pub async fn start(
+    pool: DbPool,
+    chat_id: i64,
+    server: ManagedServer,
+    alias: String,
+    goal: String,
+) -> CommandResponse {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let stop = Arc::new(AtomicBool::new(false));
+    RUNS.lock().unwrap().insert(
+        id,
+        Control {
+            stop: stop.clone(),
+            pending: None,
+        },
+    );
+
+    let ai_client = AiClient::new(pool.clone()).await;
+    let vars = Vars::server(&pool, &alias)
+        .await
+        .set("goal", goal.clone())
+        .set("max_steps", MAX_STEPS.to_string());
+    let system = prompts::render(
+        &pool,
+        "investigate",
+        &ai_client.provider_name().await,
+        &vars,
+    )
+    .await;
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let mut investigation = Investigation {
+        id,
+        pool,
+        ai_client,
+        chat_id,
+        server,
+        alias,
+        messages: vec![
+            ChatMessage::new("system", &system),
+            ChatMessage::new("user", format!("Goal: {}", goal)),
+        ],
+        goal,
+        tasks: Vec::new(),
+        redacted: 0,
+        suspicious: None,
+        started: Instant::now(),
+        stop,
+        events: tx,
+    };
+
+    tokio::spawn(async move {
+        let report = investigation.run().await;
+        RUNS.lock().unwrap().remove(&id);
+        investigation.progress("Finished.");
+        let _ = investigation.events.send(AgentEvent::Done(report));
+    });
+
+    CommandResponse::Agent {
+        id,
+        events: Arc::new(tokio::sync::Mutex::new(rx)),
+    }
+}
+
+/// The state of one investigation.
+struct Investigation {
+    id: u64,
+    pool: DbPool,
+    ai_client: AiClient,
+    chat_id: i64,
+    server: ManagedServer,
+    alias: String,
+    goal: String,
+    messages: Vec<ChatMessage>,
+    tasks: Vec<AgentTask>,
+    /// Secrets kept from the provider so far.
+    redacted: usize,
+    /// What looked like instructions in the outputs the model has not answered yet.
+    suspicious: Option<String>,
+    started: Instant,
+    stop: Arc<AtomicBool>,
+    events: UnboundedSender<AgentEvent>,
+}
+
+impl Investigation {
+    /// Runs the agent loop until the model finishes or a budget is used up, and
+    /// returns the findings report.
+    async fn run(&mut self) -> String {
+        let mut tools = specs(&self.alias);
+        tools.push(finish_spec());
+        let context = CallContext::chat(self.chat_id).purpose("investigate");
+
+        loop {
+            if let Some(reason) = self.out_of_budget() {
+                return self.conclude(reason, &context).await;
+            }
+            self.progress("🤔 Planning the next steps…");
+
+            let reply = match self
+                .ai_client
+                .chat_with_tools(&self.messages, &tools, &context)
+                .await
+            {
+                Ok((reply, _)) => {
+                    self.redacted += reply.redacted;
+                    reply
+                }
+                Err(e) => return self.report_failure(&e.user_message()),
+            };
+            // Some models still answer with the text protocol even when offered tools
+            let reply = if reply.tool_calls.is_empty() {
+                ChatReply::from_text(&reply.content)
+            } else {
+                reply
+            };
+            // Steps proposed right after suspicious output may have been planted there
+            let injection = self.suspicious.take();
+
+            if let Some(call) = reply.tool_calls.iter().find(|c| c.name == FINISH) {
+                return self.report(&call.arguments, None);
+            }
+            if reply.tool_calls.is_empty() {
+                return self.report(&Value::String(reply.content), None);
+            }
+
+            let calls: Vec<Result<Tool, String>> =
+                reply.tool_calls.iter().map(Tool::from_call).collect();
+            let mut recorded = reply.content.clone();
+            for tool in calls.iter().flatten() {
+                let prefix = if tool.is_read_only() { "CALL" } else { "RUN" };
+                recorded.push_str(&format!("\n{}: {}", prefix, tool.describe()));
+            }
+            self.messages
+                .push(ChatMessage::new("assistant", recorded.trim()));
+
+            let mut warned = false;
+            for call in calls {
+                if self.out_of_budget().is_some() {
+                    break;
+                }
+                match call {
+                    Ok(tool) => warned |= self.step(tool, injection.as_deref()).await,
+                    Err(e) => self
+                        .messages
+                        .push(ChatMessage::new("user", format!("Tool error: {}", e))),
+                }
+            }
+            // Until shown with an approval, the flag carries over to the next steps
+            if !warned {
+                self.suspicious = self.suspicious.take().or(injection);
+            }
+        }
+    }
+
+    /// Why the investigation must stop now, if it must.
+    fn out_of_budget(&self) -> Option<&'static str> {
+        if self.stop.load(Ordering::Relaxed) {
+            Some("Stopped by the user")
+        } else if self.tasks.len() >= MAX_STEPS {
+            Some("Step budget used up")
+        } else if self.started.elapsed() >= MAX_DURATION {
+            Some("Time budget used up")
+        } else {
+            None
+        }
+    }
+
+    /// Runs one step, asking for approval first unless it only reads state.
+    ///
+    /// `injection` is what looked like instructions in the output the model read
+    /// before proposing the step. Returns whether the user was asked for approval.
+    async fn step(&mut self, tool: Tool, injection: Option<&str>) -> bool {
+        let (description, read_only) = match &tool {
+            Tool::RunCommand(c) => (
+                c.rationale
+                    .clone()
+                    .filter(|r| !r.trim().is_empty())
+                    .unwrap_or_default(),
+                validator::is_read_only(&c.command),
+            ),
+            _ => (String::new(), true),
+        };
+        self.tasks.push(AgentTask {
+            task_id: uuid::Uuid::new_v4().to_string(),
+            server_id: self.server.id.clone(),
+            command: tool.describe(),
+            description,
+            status: TaskStatus::Pending,
+        });
+        let index = self.tasks.len() - 1;
+
+        if !read_only {
+            let decision = self.approval(index, injection).await;
+            if decision != Decision::Run {
+                self.tasks[index].status = TaskStatus::Failed("skipped".to_string());
+                self.messages.push(ChatMessage::new(
+                    "user",
+                    format!(
+                        "The user did not approve `{}`. Do not propose it again; continue without it.",
+                        self.tasks[index].command
+                    ),
+                ));
+                return true;
+            }
+        }
+
+        self.tasks[index].status = TaskStatus::Executing;
+        self.progress(&format!("🔄 Running {}…", self.tasks[index].command));
+
+        // A hung step is abandoned; its SSH call ends on its own read timeout
+        let server = self.server.clone();
+        let mut handle =
+            tokio::task::spawn_blocking(move || tool.execute(&server, Some(STEP_TIMEOUT)));
+        let limit = STEP_TIMEOUT.min(MAX_DURATION.saturating_sub(self.started.elapsed()));
+        let step_started = Instant::now();
+        let output = loop {
+            match tokio::time::timeout(STOP_POLL, &mut handle).await {
+                Ok(result) => {
+                    break result.unwrap_or_else(|e| format!("Error: step failed: {}", e))
+                }
+                Err(_) if self.stop.load(Ordering::Relaxed) => {
+                    break "Error: stopped by the user".to_string()
+                }
+                Err(_) if step_started.elapsed() >= limit => {
+                    break format!("Error: timed out after {} seconds", limit.as_secs())
+                }
+                Err(_) => {}
+            }
+        };
+
+        self.tasks[index].status = match output.strip_prefix("Error: ") {
+            Some(error) => TaskStatus::Failed(error.lines().next().unwrap_or_default().to_string()),
+            None => TaskStatus::Completed,
+        };
+        self.audit(&self.tasks[index], &output).await;
+
+        let output = truncate_output(&output, MAX_OUTPUT_BYTES);
+        let (content, suspicious) = injection::wrap(&self.tasks[index].command, &output);
+        self.suspicious = self.suspicious.take().or(suspicious);
+        self.messages.push(ChatMessage::new("user", content));
+        !read_only
+    }
+
+    /// Asks the user to approve step `index` and waits for the answer, at most
+    /// until the time budget is used up.
+    async fn approval(&mut self, index: usize, injection: Option<&str>) -> Decision {
+        let (tx, rx) = oneshot::channel();
+        if let Some(control) = RUNS.lock().unwrap().get_mut(&self.id) {
+            control.pending = Some(tx);
+        }
+
+        let task = &self.tasks[index];
+        let mut html = format!(
+            "🕵️ The investigation of <b>{}</b> wants to run a command that may change the server:\n<code>{}</code>",
+            escape_html(&self.alias),
+            escape_html(&task.command)
+        );
+        if !task.description.is_empty() {
+            html.push_str(&format!("\n<i>{}</i>", escape_html(&task.description)));
+        }
+        if let Some(found) = injection {
+            html.push_str(&format!("\n\n{}", injection::warning(&escape_html(found))));
+        }
+        let _ = self.events.send(AgentEvent::Approval(html));
+        self.progress("⏸ Waiting for your approval…");
+
+        let remaining = MAX_DURATION.saturating_sub(self.started.elapsed());
+        let decision = match tokio::time::timeout(remaining, rx).await {
+            Ok(Ok(decision)) => decision,
+            _ => Decision::Skip,
+        };
+        if let Some(control) = RUNS.lock().unwrap().get_mut(&self.id) {
+            control.pending = None;
+        }
+        decision
+    }
+
+    /// Asks the model for its findings once a budget is used up.
+    async fn conclude(&mut self, reason: &str, context: &CallContext) -> String {
+        self.progress("📝 Writing the report…");
+        self.messages.push(ChatMessage::new(
+            "user",
+            format!(
+                "{}. Stop investigating and call the finish tool now with what you found so far.",
+                reason
+            ),
+        ));
+        match self
+            .ai_client
+            .chat_with_tools(&self.messages, &[finish_spec()], context)
+            .await
+        {
+            Ok((reply, _)) => {
+                self.redacted += reply.redacted;
+                match reply.tool_calls.iter().find(|c| c.name == FINISH) {
+                    Some(call) => self.report(&call.arguments, Some(reason)),
+                    None => self.report(&Value::String(reply.content), Some(reason)),
+                }
+            }
+            Err(e) => self.report_failure(&e.user_message()),
+        }
+    }
+
+    /// Records an executed step in the audit log, like the commands run from the chat.
+    async fn audit(&self, task: &AgentTask, output: &str) {
+        let _ = sqlx::query("INSERT INTO audit_logs (command, user_id, output) VALUES (?, ?, ?)")
+            .bind(format!(
+                "Investigate step {} on {} ({}): {}",
+                task.task_id, self.alias, task.server_id, task.command
+            ))
+            .bind(self.chat_id)
+            .bind(output)
+            .execute(&self.pool)
+            .await;
+    }
+
+    /// Sends the current state of the investigation with a status line.
+    fn progress(&self, status: &str) {
+        let elapsed = self.started.elapsed().as_secs();
+        let mut html = format!(
+            "🕵️ <b>Investigating {}</b>\n<b>Goal:</b> {}\nStep {}/{} · {}m {:02}s of {}m\n",
+            escape_html(&self.alias),
+            escape_html(&self.goal),
+            self.tasks.len(),
+            MAX_STEPS,
+            elapsed / 60,
+            elapsed % 60,
+            MAX_DURATION.as_secs() / 60
+        );
+        for task in &self.tasks {
+            let (icon, note) = match &task.status {
+                TaskStatus::Pending => ("⏳", String::new()),
+                TaskStatus::Executing => ("🔄", String::new()),
+                TaskStatus::Completed => ("✅", String::new()),
+                TaskStatus::Failed(reason) => ("❌", format!(" ({})", escape_html(reason))),
+            };
+            html.push_str(&format!(
+                "\n{} <code>{}</code>{}",
+                icon,
+                escape_html(&shorten(&task.command, 80)),
+                note
+            ));
+        }
+        html.push_str(&format!("\n\n<i>{}</i>", escape_html(status)));
+        let _ = self.events.send(AgentEvent::Progress(html));
+    }
+
+    /// Renders the findings, given as `finish` arguments or as plain text.
+    fn report(&self, findings: &Value, reason: Option<&str>) -> String {
+        let mut html = self.report_header(reason);
+        match serde_json::from_value::<Findings>(findings.clone()) {
+            Ok(findings) => {
+                html.push_str(&format!(
+                    "\n<b>Summary</b>\n{}\n",
+                    markdown_to_telegram_html(findings.summary.trim())
+                ));
+                if !findings.findings.is_empty() {
+                    html.push_str("\n<b>Findings</b>");
+                    for finding in &findings.findings {
+                        let icon = match finding.severity.to_lowercase().as_str() {
+                            "critical" | "high" | "error" => "🔴",
+                            "warning" | "medium" => "🟠",
+                            "ok" => "🟢",
+                            _ => "🔵",
+                        };
+                        html.push_str(&format!(
+                            "\n{} <b>{}</b>",
+                            icon,
+                            escape_html(&finding.title)
+                        ));
+                        if !finding.evidence.trim().is_empty() {
+                            html.push_str(&format!(
+                                "\n{}",
+                                markdown_to_telegram_html(finding.evidence.trim())
+                            ));
+                        }
+                    }
+                    html.push('\n');
+                }
+                if !findings.next_steps.is_empty() {
+                    html.push_str("\n<b>Next steps</b>");
+                    for (i, step) in findings.next_steps.iter().enumerate() {
+                        html.push_str(&format!("\n{}. {}", i + 1, markdown_to_telegram_html(step)));
+                    }
+                    html.push('\n');
+                }
+            }
+            // A text answer, or `finish` arguments that don't match the schema
+            Err(_) => {
+                let text = match findings {
+                    Value::String(text) => text.clone(),
+                    other => other["summary"]
+                        .as_str()
+                        .map(str::to_string)
+                        .unwrap_or_else(|| other.to_string()),
+                };
+                html.push_str(&format!("\n{}\n", markdown_to_telegram_html(text.trim())));
+            }
+        }
+        html.push_str(&self.report_footer());
+        html
+    }
+
+    /// The report when the AI could not be reached, with the steps run so far.
+    fn report_failure(&self, error: &str) -> String {
+        format!(
+            "{}\n❌ {}\n{}",
+            self.report_header(Some("The AI request failed")),
+            escape_html(error),
+            self.report_footer()
+        )
+    }
+
+    fn report_header(&self, reason: Option<&str>) -> String {
+        let mut html = format!(
+            "🕵️ <b>Investigation of {}</b>\n<b>Goal:</b> {}\n",
+            escape_html(&self.alias),
+            escape_html(&self.goal)
+        );
+        if let Some(reason) = reason {
+            html.push_str(&format!("<i>⚠️ {}.</i>\n", escape_html(reason)));
+        }
+        html
+    }
+
+    fn report_footer(&self) -> String {
+        let elapsed = self.started.elapsed().as_secs();
+        let completed = self
+            .tasks
+            .iter()
+            .filter(|t| matches!(t.status, TaskStatus::Completed))
+            .count();
+        let mut footer = format!(
+            "\n<i>{} of {} steps completed in {}m {:02}s.</i>",
+            completed,
+            self.tasks.len(),
+            elapsed / 60,
+            elapsed % 60
+        );
+        if let Some(note) = crate::ai::redact::note(self.redacted) {
+            footer.push_str(&format!("\n<i>{}.</i>", note));
+        }
+        footer
+    }
+}
+
+/// Cuts `text` to at most `max` characters, marking the cut.
+fn shorten(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
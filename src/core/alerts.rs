@@ -0,0 +1,398 @@
+use crate::core::metrics::Metric;
+use crate::db::{get_setting, set_setting, DbPool};
+use crate::executor::ssh::SshExecutor;
+use crate::models::ManagedServer;
+use serde::Serialize;
+use sqlx::Row;
+use std::time::Duration;
+
+/// Escalation level reached once the extra contacts have been notified.
+pub const MAX_LEVEL: i64 = 2;
+/// Longest the health check of one server may take before it is skipped.
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(60);
+/// Longest a single SSH read of a health check may block.
+const SSH_TIMEOUT: Duration = Duration::from_secs(20);
+
+/// A persisted alert.
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub id: i64,
+    /// Deduplication key, e.g. `web1:disk_root`.
+    pub alert_key: String,
+    pub server_alias: String,
+    pub message: String,
+    /// `firing`, `acknowledged` or `resolved`.
+    pub status: String,
+    /// 0 = admin notified, 1 = admin reminded, 2 = escalated to extra contacts.
+    pub level: i64,
+    pub fired_at: String,
+}
+
+/// Who to notify, and when, if an alert is not acknowledged.
+#[derive(Debug, Clone)]
+pub struct EscalationPolicy {
+    /// Minutes to wait for an acknowledgement before each escalation step.
+    pub ack_minutes: i64,
+    /// Additional Telegram chats notified at the last step.
+    pub chat_ids: Vec<i64>,
+    /// Outgoing webhook notified at the last step.
+    pub webhook_url: Option<String>,
+}
+
+impl EscalationPolicy {
+    pub async fn load(pool: &DbPool) -> Self {
+        let ack_minutes = get_setting(pool, "escalation", "ack_minutes")
+            .await
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(15);
+        let chat_ids = get_setting(pool, "escalation", "chat_ids")
+            .await
+            .map(|v| {
+                v.split(',')
+                    .filter_map(|id| id.trim().parse().ok())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let webhook_url = get_setting(pool, "escalation", "webhook_url")
+            .await
+            .filter(|v| !v.is_empty());
+
+        EscalationPolicy {
+            ack_minutes,
+            chat_ids,
+            webhook_url,
+        }
+    }
+
+    pub async fn save(&self, pool: &DbPool) -> Result<(), sqlx::Error> {
+        set_setting(
+            pool,
+            "escalation",
+            "ack_minutes",
+            &self.ack_minutes.to_string(),
+        )
+        .await?;
+        let chat_ids: Vec<String> = self.chat_ids.iter().map(|id| id.to_string()).collect();
+        set_setting(pool, "escalation", "chat_ids", &chat_ids.join(",")).await?;
+        set_setting(
+            pool,
+            "escalation",
+            "webhook_url",
+            self.webhook_url.as_deref().unwrap_or_default(),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Returns a human readable description of the policy.
+    pub fn describe(&self) -> String {
+        let chats = if self.chat_ids.is_empty() {
+            "none".to_string()
+        } else {
+            self.chat_ids
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        format!(
+            "Escalation policy:\n1. Alert sent to admin\n2. Reminder after {m} min without ack\n3. After another {m} min: chats [{}], webhook: {}",
+            chats,
+            self.webhook_url.as_deref().unwrap_or("none"),
+            m = self.ack_minutes
+        )
+    }
+}
+
+/// Thresholds and cadence of the periodic health check that fires alerts.
+#[derive(Debug, Clone)]
+pub struct AlertRules {
+    /// Minutes between health checks (0 disables them).
+    pub interval_minutes: i64,
+    /// Root partition usage (%) at which an alert fires.
+    pub disk_threshold: f64,
+}
+
+impl AlertRules {
+    pub async fn load(pool: &DbPool) -> Self {
+        AlertRules {
+            interval_minutes: get_setting(pool, "alerts", "interval_minutes")
+                .await
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(5),
+            disk_threshold: get_setting(pool, "alerts", "disk_threshold")
+                .await
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(90.0),
+        }
+    }
+
+    /// Runs the checks on a server and returns the problems found as `(key, message)`.
+    ///
+    /// Blocks on SSH: run it with `spawn_blocking`, within [`CHECK_TIMEOUT`].
+    pub fn check(&self, alias: &str, server: &ManagedServer) -> Vec<(String, String)> {
+        let mut problems = Vec::new();
+
+        let disk = match SshExecutor::execute_with_limits(
+            server,
+            "df -h / | tail -n 1 | awk '{print $3 \" / \" $2 \" (\" $5 \")\"}'",
+            Some(SSH_TIMEOUT),
+            None,
+        ) {
+            Ok(out) => out,
+            Err(e) => {
+                let first_line = e.lines().next().unwrap_or_default().to_string();
+                problems.push((
+                    unreachable_key(alias),
+                    format!("{} is unreachable: {}", alias, first_line),
+                ));
+                return problems;
+            }
+        };
+
+        if let Some(percent) = Metric::Disk.extract("", "", disk.trim()) {
+            if percent >= self.disk_threshold {
+                problems.push((
+                    format!("{}:disk_root", alias),
+                    format!("{}: root disk at {:.0}% ({})", alias, percent, disk.trim()),
+                ));
+            }
+        }
+
+        let failed = SshExecutor::execute_with_limits(
+            server,
+            "systemctl list-units --state=failed --no-legend --no-pager --plain | awk '{print $1}'",
+            Some(SSH_TIMEOUT),
+            None,
+        )
+        .unwrap_or_default();
+        for unit in failed.lines().map(str::trim).filter(|u| !u.is_empty()) {
+            problems.push((
+                format!("{}:unit:{}", alias, unit),
+                format!("{}: unit {} has failed", alias, unit),
+            ));
+        }
+
+        problems
+    }
+}
+
+/// Key of the alert fired when `alias` cannot be reached.
+fn unreachable_key(alias: &str) -> String {
+    format!("{}:unreachable", alias)
+}
+
+/// The `open` alerts of `server_alias` whose key is not in `still_failing`.
+///
+/// An unreachable server could not be checked, so its other alerts stay open.
+fn cleared(open: Vec<Alert>, server_alias: &str, still_failing: &[String]) -> Vec<Alert> {
+    if still_failing.contains(&unreachable_key(server_alias)) {
+        return Vec::new();
+    }
+    open.into_iter()
+        .filter(|a| !still_failing.contains(&a.alert_key))
+        .collect()
+}
+
+/// Manages alert state stored in SQLite.
+#[derive(Clone)]
+pub struct AlertManager {
+    pool: DbPool,
+}
+
+impl AlertManager {
+    /// Creates a new `AlertManager` with the given database pool.
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    /// Opens an alert unless one with the same key is already open.
+    ///
+    /// Returns the new alert, which the caller should notify about.
+    pub async fn fire(
+        &self,
+        alert_key: &str,
+        server_alias: &str,
+        message: &str,
+    ) -> Result<Option<Alert>, sqlx::Error> {
+        let open: Option<(i64,)> = sqlx::query_as(
+            "SELECT id FROM alerts WHERE alert_key = ? AND status != 'resolved' LIMIT 1",
+        )
+        .bind(alert_key)
+        .fetch_optional(&self.pool)
+        .await?;
+        if open.is_some() {
+            return Ok(None);
+        }
+
+        let id =
+            sqlx::query("INSERT INTO alerts (alert_key, server_alias, message) VALUES (?, ?, ?)")
+                .bind(alert_key)
+                .bind(server_alias)
+                .bind(message)
+                .execute(&self.pool)
+                .await?
+                .last_insert_rowid();
+        self.get(id).await
+    }
+
+    /// Resolves open alerts of `server_alias` whose key is not in `still_failing`.
+    pub async fn resolve_cleared(
+        &self,
+        server_alias: &str,
+        still_failing: &[String],
+    ) -> Result<Vec<Alert>, sqlx::Error> {
+        let open = self
+            .query_alerts(
+                "status != 'resolved' AND server_alias = ?",
+                Some(server_alias),
+            )
+            .await?;
+        let cleared = cleared(open, server_alias, still_failing);
+
+        for alert in &cleared {
+            sqlx::query(
+                "UPDATE alerts SET status = 'resolved', resolved_at = CURRENT_TIMESTAMP WHERE id = ?",
+            )
+            .bind(alert.id)
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok(cleared)
+    }
+
+    /// Acknowledges a firing alert, stopping its escalation.
+    pub async fn acknowledge(&self, id: i64) -> Result<Option<Alert>, sqlx::Error> {
+        let updated = sqlx::query(
+            "UPDATE alerts SET status = 'acknowledged', acknowledged_at = CURRENT_TIMESTAMP \
+             WHERE id = ? AND status = 'firing'",
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        if updated == 0 {
+            return Ok(None);
+        }
+        self.get(id).await
+    }
+
+    /// Lists alerts that are firing or acknowledged.
+    pub async fn list_open(&self) -> Result<Vec<Alert>, sqlx::Error> {
+        self.query_alerts("status != 'resolved'", None).await
+    }
+
+    /// Returns firing alerts unacknowledged for `ack_minutes` since their last notification,
+    /// bumping their escalation level.
+    ///
+    /// Alerts of servers in `skip` (e.g. under maintenance) keep their level.
+    pub async fn take_escalations(
+        &self,
+        ack_minutes: i64,
+        skip: &[String],
+    ) -> Result<Vec<Alert>, sqlx::Error> {
+        let due = self
+            .query_alerts(
+                &format!(
+                    "status = 'firing' AND level < {} AND last_notified_at <= datetime('now', ?)",
+                    MAX_LEVEL
+                ),
+                Some(&format!("-{} minutes", ack_minutes)),
+            )
+            .await?;
+
+        let mut escalated = Vec::new();
+        for mut alert in due {
+            if skip.contains(&alert.server_alias) {
+                continue;
+            }
+            sqlx::query(
+                "UPDATE alerts SET level = level + 1, last_notified_at = CURRENT_TIMESTAMP WHERE id = ?",
+            )
+            .bind(alert.id)
+            .execute(&self.pool)
+            .await?;
+            alert.level += 1;
+            escalated.push(alert);
+        }
+        Ok(escalated)
+    }
+
+    async fn get(&self, id: i64) -> Result<Option<Alert>, sqlx::Error> {
+        Ok(self
+            .query_alerts("id = ?", Some(&id.to_string()))
+            .await?
+            .pop())
+    }
+
+    async fn query_alerts(
+        &self,
+        filter: &str,
+        param: Option<&str>,
+    ) -> Result<Vec<Alert>, sqlx::Error> {
+        let sql = format!(
+            "SELECT id, alert_key, server_alias, message, status, level, fired_at FROM alerts \
+             WHERE {} ORDER BY id",
+            filter
+        );
+        let mut query = sqlx::query(&sql);
+        if let Some(param) = param {
+            query = query.bind(param);
+        }
+        let rows = query.fetch_all(&self.pool).await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| Alert {
+                id: row.get("id"),
+                alert_key: row.get("alert_key"),
+                server_alias: row.get("server_alias"),
+                message: row.get("message"),
+                status: row.get("status"),
+                level: row.get("level"),
+                fired_at: row.get("fired_at"),
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cleared, Alert};
+
+    fn alert(id: i64, key: &str) -> Alert {
+        Alert {
+            id,
+            alert_key: key.to_string(),
+            server_alias: "web1".to_string(),
+            message: String::new(),
+            status: "firing".to_string(),
+            level: 0,
+            fired_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn resolves_alerts_no_longer_failing() {
+        let open = vec![
+            alert(1, "web1:disk_root"),
+            alert(2, "web1:unit:nginx.service"),
+        ];
+        let still_failing = ["web1:unit:nginx.service".to_string()];
+        let ids: Vec<i64> = cleared(open, "web1", &still_failing)
+            .iter()
+            .map(|a| a.id)
+            .collect();
+        assert_eq!(ids, [1]);
+    }
+
+    #[test]
+    fn keeps_alerts_of_unreachable_servers() {
+        let open = vec![
+            alert(1, "web1:disk_root"),
+            alert(2, "web1:unit:nginx.service"),
+        ];
+        let still_failing = ["web1:unreachable".to_string()];
+        assert!(cleared(open, "web1", &still_failing).is_empty());
+    }
+}
@@ -0,0 +1,245 @@
+use crate::core::metrics::Sample;
+use chrono::NaiveDateTime;
+
+/// Series colours and the matching emoji used to build a legend in the caption,
+/// since the chart itself is rendered without text.
+pub const PALETTE: [([u8; 3], &str); 6] = [
+    ([33, 113, 181], "🟦"),
+    ([215, 48, 39], "🟥"),
+    ([35, 139, 69], "🟩"),
+    ([241, 105, 19], "🟧"),
+    ([117, 107, 177], "🟪"),
+    ([140, 81, 10], "🟫"),
+];
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 400;
+const MARGIN: i64 = 30;
+const GRID_LINES: i64 = 4;
+
+/// A named series to draw on the chart.
+pub struct Series {
+    pub name: String,
+    pub samples: Vec<Sample>,
+}
+
+/// A simple RGB raster that the chart is drawn onto before PNG encoding.
+struct Canvas {
+    width: i64,
+    height: i64,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32, background: [u8; 3]) -> Self {
+        let pixels = background
+            .iter()
+            .copied()
+            .cycle()
+            .take((width * height * 3) as usize)
+            .collect();
+        Self {
+            width: width as i64,
+            height: height as i64,
+            pixels,
+        }
+    }
+
+    fn set(&mut self, x: i64, y: i64, color: [u8; 3]) {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return;
+        }
+        let idx = ((y * self.width + x) * 3) as usize;
+        self.pixels[idx..idx + 3].copy_from_slice(&color);
+    }
+
+    fn dot(&mut self, x: i64, y: i64, radius: i64, color: [u8; 3]) {
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                if dx * dx + dy * dy <= radius * radius {
+                    self.set(x + dx, y + dy, color);
+                }
+            }
+        }
+    }
+
+    /// Draws a line using Bresenham's algorithm with the given thickness.
+    fn line(&mut self, from: (i64, i64), to: (i64, i64), thickness: i64, color: [u8; 3]) {
+        let (mut x0, mut y0) = from;
+        let (x1, y1) = to;
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+
+        loop {
+            self.dot(x0, y0, thickness / 2, color);
+            if x0 == x1 && y0 == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x0 += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y0 += sy;
+            }
+        }
+    }
+
+    fn encode_png(&self) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut out, self.width as u32, self.height as u32);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder
+                .write_header()
+                .map_err(|e| format!("PNG header error: {}", e))?;
+            writer
+                .write_image_data(&self.pixels)
+                .map_err(|e| format!("PNG encoding error: {}", e))?;
+        }
+        Ok(out)
+    }
+}
+
+/// Renders a line chart of one or more series into a PNG image.
+///
+/// The X axis spans `start..end` and the Y axis spans `0..y_max`. Colours are
+/// assigned from [`PALETTE`] in series order.
+pub fn render_line_chart(
+    series: &[Series],
+    start: NaiveDateTime,
+    end: NaiveDateTime,
+    y_max: f64,
+) -> Result<Vec<u8>, String> {
+    let mut canvas = Canvas::new(WIDTH, HEIGHT, [255, 255, 255]);
+    let left = MARGIN;
+    let right = WIDTH as i64 - MARGIN;
+    let top = MARGIN;
+    let bottom = HEIGHT as i64 - MARGIN;
+
+    // Grid and axes
+    for i in 0..=GRID_LINES {
+        let y = top + (bottom - top) * i / GRID_LINES;
+        canvas.line((left, y), (right, y), 1, [225, 225, 225]);
+    }
+    canvas.line((left, top), (left, bottom), 2, [90, 90, 90]);
+    canvas.line((left, bottom), (right, bottom), 2, [90, 90, 90]);
+
+    let span = (end - start).num_seconds().max(1) as f64;
+    let y_max = if y_max > 0.0 { y_max } else { 1.0 };
+    let to_point = |sample: &Sample| {
+        let x_ratio = (sample.timestamp - start).num_seconds() as f64 / span;
+        let y_ratio = (sample.value / y_max).clamp(0.0, 1.0);
+        (
+            left + (x_ratio.clamp(0.0, 1.0) * (right - left) as f64) as i64,
+            bottom - (y_ratio * (bottom - top) as f64) as i64,
+        )
+    };
+
+    for (idx, s) in series.iter().enumerate() {
+        let color = PALETTE[idx % PALETTE.len()].0;
+        let points: Vec<(i64, i64)> = s.samples.iter().map(to_point).collect();
+        for pair in points.windows(2) {
+            canvas.line(pair[0], pair[1], 3, color);
+        }
+        for (x, y) in &points {
+            canvas.dot(*x, *y, 3, color);
+        }
+    }
+
+    canvas.encode_png()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_line_chart, Canvas, Series, HEIGHT, PALETTE, WIDTH};
+    use crate::core::metrics::Sample;
+    use chrono::{Duration, NaiveDate};
+
+    const WHITE: [u8; 3] = [255, 255, 255];
+    const BLACK: [u8; 3] = [0, 0, 0];
+
+    fn pixel(canvas: &Canvas, x: i64, y: i64) -> [u8; 3] {
+        let idx = ((y * canvas.width + x) * 3) as usize;
+        [
+            canvas.pixels[idx],
+            canvas.pixels[idx + 1],
+            canvas.pixels[idx + 2],
+        ]
+    }
+
+    /// The coordinates of the non-background pixels.
+    fn drawn(canvas: &Canvas) -> Vec<(i64, i64)> {
+        (0..canvas.height)
+            .flat_map(|y| (0..canvas.width).map(move |x| (x, y)))
+            .filter(|(x, y)| pixel(canvas, *x, *y) != WHITE)
+            .collect()
+    }
+
+    #[test]
+    fn draws_lines_between_both_ends() {
+        let mut canvas = Canvas::new(8, 8, WHITE);
+        canvas.line((1, 2), (5, 2), 1, BLACK);
+        assert_eq!(drawn(&canvas), (1..=5).map(|x| (x, 2)).collect::<Vec<_>>());
+
+        let mut canvas = Canvas::new(8, 8, WHITE);
+        canvas.line((4, 4), (1, 1), 1, BLACK);
+        assert_eq!(drawn(&canvas), (1..=4).map(|i| (i, i)).collect::<Vec<_>>());
+
+        // Steep lines get one pixel per row
+        let mut canvas = Canvas::new(8, 8, WHITE);
+        canvas.line((2, 0), (3, 6), 1, BLACK);
+        let points = drawn(&canvas);
+        assert_eq!(points.len(), 7);
+        assert!(points.contains(&(2, 0)) && points.contains(&(3, 6)));
+        assert!((0..7).all(|y| points.iter().any(|p| p.1 == y)));
+    }
+
+    #[test]
+    fn clips_drawing_to_the_canvas() {
+        let mut canvas = Canvas::new(4, 4, WHITE);
+        canvas.line((-3, 1), (10, 1), 1, BLACK);
+        canvas.dot(0, 0, 2, BLACK);
+        assert_eq!(pixel(&canvas, 3, 1), BLACK);
+        assert_eq!(pixel(&canvas, 0, 0), BLACK);
+        assert_eq!(pixel(&canvas, 3, 3), WHITE);
+    }
+
+    #[test]
+    fn renders_series_as_png() {
+        let start = NaiveDate::from_ymd_opt(2026, 10, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        let series = [Series {
+            name: "web".to_string(),
+            samples: (0..3)
+                .map(|h| Sample {
+                    timestamp: start + Duration::hours(h),
+                    value: 50.0,
+                })
+                .collect(),
+        }];
+
+        let data = render_line_chart(&series, start, start + Duration::hours(2), 100.0).unwrap();
+        let decoder = png::Decoder::new(data.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!((info.width, info.height), (WIDTH, HEIGHT));
+
+        // Half way up the plot area, in the series' colour
+        let at = |x: u32, y: u32| {
+            let idx = ((y * WIDTH + x) * 3) as usize;
+            [pixels[idx], pixels[idx + 1], pixels[idx + 2]]
+        };
+        assert_eq!(at(WIDTH / 2, HEIGHT / 2), PALETTE[0].0);
+        assert_eq!(at(WIDTH / 2, HEIGHT / 4), WHITE);
+    }
+}
@@ -0,0 +1,640 @@
+use crate::ai::client::AiClient;
+use crate::ai::usage::CallContext;
+use crate::core::discovery::{Discovery, DiscoveryReport};
+use crate::core::maintenance::MaintenanceManager;
+use crate::core::metrics::{record_stats, Metric};
+use crate::core::server_manager::ServerManager;
+use crate::core::session::{escape_html, markdown_to_telegram_html};
+use crate::db::{get_setting, set_setting, DbPool};
+use crate::executor::ssh::SshExecutor;
+use crate::models::ManagedServer;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+/// How many entries to show in the "worst disks" / "highest load" rankings.
+const TOP_N: usize = 3;
+/// Longest a single SSH read of the reachability probe may block.
+const PROBE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+/// Longest the probe and discovery of one server may take before it is skipped.
+const SERVER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(180);
+
+/// Schedule and delivery settings for the fleet digest.
+#[derive(Debug, Clone)]
+pub struct DigestConfig {
+    /// `off`, `daily` or `weekly`.
+    pub schedule: String,
+    /// Local delivery time (`HH:MM`).
+    pub time: NaiveTime,
+    /// Day of the week for weekly digests.
+    pub weekday: Weekday,
+    /// IANA timezone used for `time`, `weekday` and quiet hours.
+    pub timezone: Tz,
+    /// Optional `(start, end)` local interval during which nothing is posted.
+    pub quiet_hours: Option<(NaiveTime, NaiveTime)>,
+    /// Whether to append a prose summary written by the active AI provider.
+    pub ai_summary: bool,
+}
+
+impl DigestConfig {
+    pub async fn load(pool: &DbPool) -> Self {
+        let schedule = get_setting(pool, "digest", "schedule")
+            .await
+            .unwrap_or_else(|| "off".to_string());
+        let time = get_setting(pool, "digest", "time")
+            .await
+            .and_then(|t| parse_time(&t))
+            .unwrap_or_else(|| NaiveTime::from_hms_opt(8, 0, 0).unwrap());
+        let weekday = get_setting(pool, "digest", "weekday")
+            .await
+            .and_then(|d| d.parse().ok())
+            .unwrap_or(Weekday::Mon);
+        let timezone = get_setting(pool, "digest", "timezone")
+            .await
+            .and_then(|tz| tz.parse().ok())
+            .unwrap_or(Tz::UTC);
+        let quiet_hours = get_setting(pool, "digest", "quiet_hours")
+            .await
+            .and_then(|q| parse_quiet_hours(&q));
+        let ai_summary = get_setting(pool, "digest", "ai_summary")
+            .await
+            .map(|v| v == "on")
+            .unwrap_or(true);
+
+        DigestConfig {
+            schedule,
+            time,
+            weekday,
+            timezone,
+            quiet_hours,
+            ai_summary,
+        }
+    }
+
+    pub async fn save(&self, pool: &DbPool) -> Result<(), sqlx::Error> {
+        set_setting(pool, "digest", "schedule", &self.schedule).await?;
+        set_setting(
+            pool,
+            "digest",
+            "time",
+            &self.time.format("%H:%M").to_string(),
+        )
+        .await?;
+        set_setting(pool, "digest", "weekday", &self.weekday.to_string()).await?;
+        set_setting(pool, "digest", "timezone", self.timezone.name()).await?;
+        let quiet = match self.quiet_hours {
+            Some((start, end)) => format!("{}-{}", start.format("%H:%M"), end.format("%H:%M")),
+            None => "off".to_string(),
+        };
+        set_setting(pool, "digest", "quiet_hours", &quiet).await?;
+        let ai = if self.ai_summary { "on" } else { "off" };
+        set_setting(pool, "digest", "ai_summary", ai).await?;
+        Ok(())
+    }
+
+    /// Returns `true` if the given local time falls inside the quiet hours.
+    pub fn in_quiet_hours(&self, local: NaiveTime) -> bool {
+        match self.quiet_hours {
+            Some((start, end)) if start <= end => local >= start && local < end,
+            // Interval wrapping midnight, e.g. 22:00-07:00
+            Some((start, end)) => local >= start || local < end,
+            None => false,
+        }
+    }
+
+    /// Decides whether a digest should be posted now, returning the local date it
+    /// was scheduled for.
+    ///
+    /// `last_run` is the scheduled date of the previous digest. A digest whose time
+    /// falls inside quiet hours is deferred until they end, which may be the next day.
+    pub fn due(&self, now: DateTime<Utc>, last_run: Option<NaiveDate>) -> Option<NaiveDate> {
+        let local = now.with_timezone(&self.timezone).naive_local();
+        if self.in_quiet_hours(local.time()) {
+            return None;
+        }
+        let today = local.date();
+        // Yesterday's digest is still pending if quiet hours pushed it past midnight
+        [today - Duration::days(1), today]
+            .into_iter()
+            .filter(|date| match self.schedule.as_str() {
+                "daily" => true,
+                "weekly" => date.weekday() == self.weekday,
+                _ => false,
+            })
+            .filter(|date| last_run < Some(*date))
+            .find(|date| {
+                let send_at = self.send_at(*date);
+                send_at <= local && send_at.date() == today
+            })
+    }
+
+    /// When the digest scheduled for `date` is posted: at `time`, or when the quiet
+    /// hours around it end.
+    fn send_at(&self, date: NaiveDate) -> NaiveDateTime {
+        match self.quiet_hours {
+            Some((_, end)) if self.in_quiet_hours(self.time) => {
+                // Only an interval wrapping midnight ends on the next day
+                let day = if end > self.time {
+                    date
+                } else {
+                    date + Duration::days(1)
+                };
+                day.and_time(end)
+            }
+            _ => date.and_time(self.time),
+        }
+    }
+
+    /// Returns a human readable description of the settings.
+    pub fn describe(&self) -> String {
+        let when = match self.schedule.as_str() {
+            "daily" => format!("daily at {}", self.time.format("%H:%M")),
+            "weekly" => format!("every {} at {}", self.weekday, self.time.format("%H:%M")),
+            _ => "disabled".to_string(),
+        };
+        let quiet = match self.quiet_hours {
+            Some((start, end)) => format!("{}-{}", start.format("%H:%M"), end.format("%H:%M")),
+            None => "none".to_string(),
+        };
+        format!(
+            "Digest: {} ({})\nQuiet hours: {}\nAI summary: {}",
+            when,
+            self.timezone.name(),
+            quiet,
+            if self.ai_summary { "on" } else { "off" }
+        )
+    }
+}
+
+/// Parses `HH:MM`.
+pub fn parse_time(text: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(text.trim(), "%H:%M").ok()
+}
+
+/// Parses `HH:MM-HH:MM`. Returns `None` for `off` or malformed input.
+pub fn parse_quiet_hours(text: &str) -> Option<(NaiveTime, NaiveTime)> {
+    let (start, end) = text.split_once('-')?;
+    Some((parse_time(start)?, parse_time(end)?))
+}
+
+/// The health summary of a single server, as stored in the `digests` table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerHealth {
+    pub alias: String,
+    pub reachable: bool,
+    pub load_1m: Option<f64>,
+    pub memory_percent: Option<f64>,
+    pub disk_percent: Option<f64>,
+    pub failed_units: Vec<String>,
+    pub pending_updates: Option<u32>,
+    pub reboot_required: bool,
+}
+
+/// Runs discovery on every server and collects a health summary.
+///
+/// Servers listed in `skip` (e.g. under maintenance) are not contacted.
+pub async fn collect(pool: &DbPool, skip: &[String]) -> Result<Vec<ServerHealth>, sqlx::Error> {
+    let manager = ServerManager::new(pool.clone());
+    let mut fleet = Vec::new();
+
+    for (alias, server) in manager.list_servers().await? {
+        if skip.contains(&alias) {
+            continue;
+        }
+        let server_id = server.id.clone();
+        let Some((health, report)) = check_server(alias, server).await else {
+            continue;
+        };
+        if let Some(report) = report {
+            let _ = record_stats(pool, &server_id, &report).await;
+        }
+        fleet.push(health);
+    }
+
+    Ok(fleet)
+}
+
+/// Probes a server and runs discovery on it off the async runtime, within [`SERVER_TIMEOUT`].
+///
+/// Returns the health summary along with the discovery report it was built from,
+/// or `None` when discovery failed or took too long.
+async fn check_server(
+    alias: String,
+    server: ManagedServer,
+) -> Option<(ServerHealth, Option<DiscoveryReport>)> {
+    // A hung server is skipped; its SSH calls end on their own read timeout
+    let handle = tokio::task::spawn_blocking(move || {
+        // Probe first so unreachable servers don't cost one timeout per discovery command
+        SshExecutor::execute_with_limits(&server, "true", Some(PROBE_TIMEOUT), None)
+            .map(|_| Discovery::run(&server))
+    });
+    let report = match tokio::time::timeout(SERVER_TIMEOUT, handle).await {
+        Ok(Ok(Ok(Ok(report)))) => report,
+        Ok(Ok(Err(_))) => {
+            return Some((
+                ServerHealth {
+                    alias,
+                    reachable: false,
+                    load_1m: None,
+                    memory_percent: None,
+                    disk_percent: None,
+                    failed_units: Vec::new(),
+                    pending_updates: None,
+                    reboot_required: false,
+                },
+                None,
+            ))
+        }
+        Ok(Ok(Ok(Err(e)))) => {
+            eprintln!("Digest: discovery failed on {}: {}", alias, e);
+            return None;
+        }
+        Ok(Err(e)) => {
+            eprintln!("Digest: discovery failed on {}: {}", alias, e);
+            return None;
+        }
+        Err(_) => {
+            eprintln!("Digest: discovery timed out on {}", alias);
+            return None;
+        }
+    };
+
+    let res = &report.resources;
+    let health = ServerHealth {
+        alias,
+        reachable: true,
+        load_1m: Metric::Cpu.extract(&res.cpu_usage, "", ""),
+        memory_percent: Metric::Memory.extract("", &res.memory_usage, ""),
+        disk_percent: Metric::Disk.extract("", "", &res.disk_usage),
+        failed_units: report.health.failed_units.clone(),
+        pending_updates: report.health.pending_updates,
+        reboot_required: report.health.reboot_required,
+    };
+    Some((health, Some(report)))
+}
+
+/// Loads the snapshot of the most recent digest, if any.
+async fn load_previous(pool: &DbPool) -> Option<(String, Vec<ServerHealth>)> {
+    let row: Option<(String, String)> =
+        sqlx::query_as("SELECT timestamp, snapshot FROM digests ORDER BY id DESC LIMIT 1")
+            .fetch_optional(pool)
+            .await
+            .unwrap_or(None);
+    let (timestamp, snapshot) = row?;
+    serde_json::from_str(&snapshot)
+        .ok()
+        .map(|fleet| (timestamp, fleet))
+}
+
+/// Formats the difference between two optional values, e.g. ` (+4.0)`.
+fn delta(current: Option<f64>, previous: Option<f64>) -> String {
+    match (current, previous) {
+        (Some(c), Some(p)) if (c - p).abs() >= 0.05 => format!(" ({:+.1})", c - p),
+        _ => String::new(),
+    }
+}
+
+/// Renders the digest as Telegram HTML, comparing against the previous snapshot.
+///
+/// Servers in `muted` are under maintenance and only listed by name.
+pub fn render(
+    fleet: &[ServerHealth],
+    previous: Option<&(String, Vec<ServerHealth>)>,
+    muted: &[String],
+) -> String {
+    let prev = |alias: &str| previous.and_then(|(_, p)| p.iter().find(|s| s.alias == alias));
+    let reachable: Vec<&ServerHealth> = fleet.iter().filter(|s| s.reachable).collect();
+
+    let mut out = format!(
+        "<b>📋 Fleet digest</b> — {}\nServers: {} ({} unreachable, {} under maintenance)\n",
+        Utc::now().format("%Y-%m-%d %H:%M UTC"),
+        fleet.len() + muted.len(),
+        fleet.len() - reachable.len(),
+        muted.len()
+    );
+    if let Some((timestamp, _)) = previous {
+        out.push_str(&format!("Compared with digest of {} UTC\n", timestamp));
+    }
+
+    let mut disks: Vec<&&ServerHealth> = reachable
+        .iter()
+        .filter(|s| s.disk_percent.is_some())
+        .collect();
+    disks.sort_by(|a, b| {
+        let (a, b) = (
+            a.disk_percent.unwrap_or_default(),
+            b.disk_percent.unwrap_or_default(),
+        );
+        b.total_cmp(&a)
+    });
+    if !disks.is_empty() {
+        out.push_str("\n<b>💾 Worst disks</b>\n");
+        for s in disks.iter().take(TOP_N) {
+            out.push_str(&format!(
+                "• {}: {:.0}%{}\n",
+                escape_html(&s.alias),
+                s.disk_percent.unwrap_or_default(),
+                delta(s.disk_percent, prev(&s.alias).and_then(|p| p.disk_percent))
+            ));
+        }
+    }
+
+    let mut loads: Vec<&&ServerHealth> = reachable.iter().filter(|s| s.load_1m.is_some()).collect();
+    loads.sort_by(|a, b| {
+        let (a, b) = (a.load_1m.unwrap_or_default(), b.load_1m.unwrap_or_default());
+        b.total_cmp(&a)
+    });
+    if !loads.is_empty() {
+        out.push_str("\n<b>🔥 Highest load</b>\n");
+        for s in loads.iter().take(TOP_N) {
+            out.push_str(&format!(
+                "• {}: {:.2}{}\n",
+                escape_html(&s.alias),
+                s.load_1m.unwrap_or_default(),
+                delta(s.load_1m, prev(&s.alias).and_then(|p| p.load_1m))
+            ));
+        }
+    }
+
+    let failed: Vec<&&ServerHealth> = reachable
+        .iter()
+        .filter(|s| !s.failed_units.is_empty())
+        .collect();
+    if !failed.is_empty() {
+        out.push_str("\n<b>❌ Failed units</b>\n");
+        for s in failed {
+            let units: Vec<String> = s
+                .failed_units
+                .iter()
+                .map(|unit| {
+                    let is_new = prev(&s.alias)
+                        .map(|p| !p.failed_units.contains(unit))
+                        .unwrap_or(false);
+                    if is_new {
+                        format!("{} (new)", escape_html(unit))
+                    } else {
+                        escape_html(unit)
+                    }
+                })
+                .collect();
+            out.push_str(&format!(
+                "• {}: {}\n",
+                escape_html(&s.alias),
+                units.join(", ")
+            ));
+        }
+    }
+
+    let updates: Vec<&&ServerHealth> = reachable
+        .iter()
+        .filter(|s| s.pending_updates.unwrap_or(0) > 0)
+        .collect();
+    if !updates.is_empty() {
+        out.push_str("\n<b>📦 Pending updates</b>\n");
+        for s in updates {
+            let current = s.pending_updates.map(f64::from);
+            let previous = prev(&s.alias)
+                .and_then(|p| p.pending_updates)
+                .map(f64::from);
+            out.push_str(&format!(
+                "• {}: {}{}\n",
+                escape_html(&s.alias),
+                s.pending_updates.unwrap_or(0),
+                delta(current, previous)
+            ));
+        }
+    }
+
+    let reboot: Vec<String> = reachable
+        .iter()
+        .filter(|s| s.reboot_required)
+        .map(|s| escape_html(&s.alias))
+        .collect();
+    if !reboot.is_empty() {
+        out.push_str(&format!(
+            "\n<b>🔄 Reboot required</b>\n{}\n",
+            reboot.join(", ")
+        ));
+    }
+
+    let unreachable: Vec<String> = fleet
+        .iter()
+        .filter(|s| !s.reachable)
+        .map(|s| escape_html(&s.alias))
+        .collect();
+    if !unreachable.is_empty() {
+        out.push_str(&format!(
+            "\n<b>⚠️ Unreachable</b>\n{}\n",
+            unreachable.join(", ")
+        ));
+    }
+
+    if !muted.is_empty() {
+        let muted: Vec<String> = muted.iter().map(|a| escape_html(a)).collect();
+        out.push_str(&format!(
+            "\n<b>🔧 Under maintenance</b>\n{}\n",
+            muted.join(", ")
+        ));
+    }
+
+    // Things that got better since last time
+    if let Some((_, previous)) = previous {
+        let resolved: Vec<String> = previous
+            .iter()
+            .flat_map(|p| {
+                let current = fleet.iter().find(|s| s.alias == p.alias && s.reachable);
+                p.failed_units
+                    .iter()
+                    .filter(move |unit| {
+                        current
+                            .map(|c| !c.failed_units.contains(unit))
+                            .unwrap_or(false)
+                    })
+                    .map(move |unit| format!("{} on {}", escape_html(unit), escape_html(&p.alias)))
+            })
+            .collect();
+        if !resolved.is_empty() {
+            out.push_str(&format!(
+                "\n<b>✅ Resolved since last digest</b>\n{}\n",
+                resolved.join(", ")
+            ));
+        }
+    }
+
+    out
+}
+
+/// Collects fleet health, renders the digest and stores the snapshot for the next comparison.
+///
+/// When `with_ai` is set, the active AI provider is asked for a short prose summary
+/// which is appended to the report (failures are noted but don't abort the digest).
+pub async fn run(pool: &DbPool, ai_client: &AiClient, with_ai: bool) -> Result<String, String> {
+    let muted: Vec<String> = MaintenanceManager::new(pool.clone())
+        .list_active()
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|w| w.alias)
+        .collect();
+    let fleet = collect(pool, &muted)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+    let previous = load_previous(pool).await;
+    let mut report = render(&fleet, previous.as_ref(), &muted);
+
+    let snapshot = serde_json::to_string(&fleet).unwrap_or_default();
+    if let Err(e) = sqlx::query("INSERT INTO digests (snapshot) VALUES (?)")
+        .bind(&snapshot)
+        .execute(pool)
+        .await
+    {
+        eprintln!("Failed to save digest snapshot: {}", e);
+    }
+
+    if with_ai && !fleet.is_empty() {
+        let previous_json = previous
+            .map(|(_, p)| serde_json::to_string(&p).unwrap_or_default())
+            .unwrap_or_else(|| "none".to_string());
+        let question = format!(
+            "You are summarising a server fleet health digest for a sysadmin. \
+             In 3-5 sentences, say what needs attention first and what changed since the previous digest. \
+             Do not repeat every number.\n\nCurrent:\n{}\n\nPrevious:\n{}",
+            snapshot, previous_json
+        );
+        match ai_client
+            .ask(&question, &CallContext::default().purpose("digest"))
+            .await
+        {
+            Ok(summary) => {
+                report.push_str("\n<b>🤖 Summary</b>\n");
+                report.push_str(&markdown_to_telegram_html(summary.trim()));
+            }
+            Err(e) => report.push_str(&format!(
+                "\n<i>AI summary unavailable: {}</i>",
+                escape_html(&e.to_string())
+            )),
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_server, render, DigestConfig, ServerHealth};
+    use crate::models::ManagedServer;
+    use chrono::{NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+    use chrono_tz::Tz;
+
+    fn config(time: (u32, u32), quiet: Option<((u32, u32), (u32, u32))>) -> DigestConfig {
+        let hm = |(h, m): (u32, u32)| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+        DigestConfig {
+            schedule: "daily".to_string(),
+            time: hm(time),
+            weekday: Weekday::Mon,
+            timezone: Tz::UTC,
+            quiet_hours: quiet.map(|(start, end)| (hm(start), hm(end))),
+            ai_summary: false,
+        }
+    }
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 10, d).unwrap()
+    }
+
+    fn at(d: u32, h: u32, m: u32) -> chrono::DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, d, h, m, 0).unwrap()
+    }
+
+    fn health(alias: &str, disk: f64, failed_units: &[&str]) -> ServerHealth {
+        ServerHealth {
+            alias: alias.to_string(),
+            reachable: true,
+            load_1m: Some(disk / 100.0),
+            memory_percent: None,
+            disk_percent: Some(disk),
+            failed_units: failed_units.iter().map(|u| u.to_string()).collect(),
+            pending_updates: None,
+            reboot_required: false,
+        }
+    }
+
+    #[test]
+    fn posts_at_the_configured_time() {
+        let config = config((8, 0), Some(((22, 0), (7, 0))));
+        assert_eq!(config.due(at(5, 7, 59), Some(day(4))), None);
+        assert_eq!(config.due(at(5, 8, 0), Some(day(4))), Some(day(5)));
+        assert_eq!(config.due(at(5, 8, 1), Some(day(5))), None);
+    }
+
+    #[test]
+    fn defers_to_the_end_of_quiet_hours_on_the_same_day() {
+        let config = config((6, 0), Some(((22, 0), (7, 0))));
+        assert_eq!(config.due(at(5, 6, 30), Some(day(4))), None);
+        assert_eq!(config.due(at(5, 7, 0), Some(day(4))), Some(day(5)));
+    }
+
+    #[test]
+    fn defers_past_midnight_when_quiet_hours_wrap() {
+        let config = config((23, 0), Some(((22, 0), (7, 0))));
+        assert_eq!(config.due(at(5, 23, 30), Some(day(4))), None);
+        assert_eq!(config.due(at(6, 6, 59), Some(day(4))), None);
+        assert_eq!(config.due(at(6, 7, 0), Some(day(4))), Some(day(5)));
+        // Once sent, the next one is the day after's, again at 07:00
+        assert_eq!(config.due(at(6, 12, 0), Some(day(5))), None);
+        assert_eq!(config.due(at(7, 7, 0), Some(day(5))), Some(day(6)));
+    }
+
+    #[tokio::test]
+    async fn marks_refused_servers_unreachable() {
+        let server = ManagedServer {
+            id: "1".to_string(),
+            hostname: "web1".to_string(),
+            ip_address: "127.0.0.1".to_string(),
+            port: 1,
+            ssh_user: "root".to_string(),
+            password: None,
+        };
+        let (health, report) = check_server("web1".to_string(), server).await.unwrap();
+        assert!(!health.reachable);
+        assert!(report.is_none());
+    }
+
+    #[test]
+    fn ranks_the_worst_disks_and_lists_unreachable_servers() {
+        let mut down = health("db<1>", 0.0, &[]);
+        down.reachable = false;
+        let fleet = [
+            health("web1", 40.0, &[]),
+            health("web2", 95.0, &[]),
+            health("web3", 10.0, &[]),
+            health("web4", 70.0, &[]),
+            down,
+        ];
+        let digest = render(&fleet, None, &["cache1".to_string()]);
+
+        assert!(digest.contains("Servers: 6 (1 unreachable, 1 under maintenance)"));
+        let web2 = digest.find("• web2: 95%").unwrap();
+        let web4 = digest.find("• web4: 70%").unwrap();
+        let web1 = digest.find("• web1: 40%").unwrap();
+        assert!(web2 < web4 && web4 < web1);
+        assert!(!digest.contains("• web3: 10%"));
+        assert!(digest.contains("<b>⚠️ Unreachable</b>\ndb&lt;1&gt;\n"));
+        assert!(digest.contains("<b>🔧 Under maintenance</b>\ncache1\n"));
+    }
+
+    #[test]
+    fn compares_with_the_previous_digest() {
+        let previous = (
+            "2026-10-04 08:00:00".to_string(),
+            vec![health("web1", 50.0, &["cron.service"])],
+        );
+        let fleet = [health("web1", 54.0, &["nginx.service"])];
+        let digest = render(&fleet, Some(&previous), &[]);
+
+        assert!(digest.contains("Compared with digest of 2026-10-04 08:00:00 UTC"));
+        assert!(digest.contains("• web1: 54% (+4.0)"));
+        assert!(digest.contains("• web1: nginx.service (new)"));
+        assert!(digest.contains("<b>✅ Resolved since last digest</b>\ncron.service on web1\n"));
+    }
+}
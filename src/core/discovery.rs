@@ -35,12 +35,24 @@ pub struct RunningService {
     pub status: String,
 }
 
+/// Maintenance related health signals.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HealthChecks {
+    /// Systemd units currently in the `failed` state.
+    pub failed_units: Vec<String>,
+    /// Number of packages with pending updates, if the package manager is supported.
+    pub pending_updates: Option<u32>,
+    /// Whether the OS reports that a reboot is required.
+    pub reboot_required: bool,
+}
+
 /// A comprehensive report of the server's status generated by the discovery process.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DiscoveryReport {
     pub system_info: SystemInfo,
     pub resources: Resources,
     pub services: Vec<RunningService>,
+    pub health: HealthChecks,
     pub timestamp: String,
 }
 
@@ -54,6 +66,7 @@ impl Discovery {
     /// - OS details (`/etc/os-release`, `uname`)
     /// - Resource usage (`uptime`, `free`, `df`)
     /// - Running services (`systemctl`)
+    /// - Failed units, pending package updates and reboot-required flag
     ///
     /// # Arguments
     ///
@@ -120,10 +133,48 @@ impl Discovery {
             })
             .collect();
 
+        // Gather Health Checks
+        let failed_raw = SshExecutor::execute(
+            server,
+            "systemctl list-units --state=failed --no-legend --no-pager --plain | awk '{print $1}'",
+        )
+        .unwrap_or_default();
+
+        let failed_units: Vec<String> = failed_raw
+            .lines()
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        let pending_updates = SshExecutor::execute(
+            server,
+            "if command -v apt >/dev/null; then apt list --upgradable 2>/dev/null | grep -c upgradable || true; \
+             elif command -v dnf >/dev/null; then dnf -q check-update 2>/dev/null | grep -c '^[a-zA-Z0-9]' || true; \
+             else echo unknown; fi",
+        )
+        .ok()
+        .and_then(|out| out.trim().parse::<u32>().ok());
+
+        let reboot_required = SshExecutor::execute(
+            server,
+            "if [ -f /var/run/reboot-required ]; then echo yes; \
+             elif command -v needs-restarting >/dev/null && ! needs-restarting -r >/dev/null 2>&1; then echo yes; \
+             else echo no; fi",
+        )
+        .map(|out| out.trim() == "yes")
+        .unwrap_or(false);
+
+        let health = HealthChecks {
+            failed_units,
+            pending_updates,
+            reboot_required,
+        };
+
         Ok(DiscoveryReport {
             system_info,
             resources,
             services,
+            health,
             timestamp: chrono::Local::now().to_string(),
         })
     }
@@ -1,5 +1,9 @@
 use crate::ai::client::AiClient;
+use crate::ai::config::{GlobalConfig, ProviderProfile, BUILTIN_PROVIDERS};
+use crate::ai::usage::CallContext;
+use crate::core::maintenance::{HeldCommand, MaintenanceManager};
 use crate::core::server_manager::ServerManager;
+use crate::core::session::escape_html;
 use crate::executor::ssh::SshExecutor;
 use crate::models::command::SystemCommand;
 use crate::models::CommandResponse;
@@ -46,7 +50,27 @@ pub async fn dispatch(
     if let SystemCommand::Unknown = command {
         // Skip logging unknown commands as they might just be chat noise
     } else {
-        let cmd_str = format!("{:?} (User: {})", command, chat_id);
+        let cmd_str = match &command {
+            // Keep API keys out of the audit log
+            SystemCommand::AddProviderProfile { name, .. } => {
+                format!(
+                    "AddProviderProfile {{ name: {:?}, .. }} (User: {})",
+                    name, chat_id
+                )
+            }
+            SystemCommand::SetApiKey { provider, .. } => {
+                format!(
+                    "SetApiKey {{ provider: {:?}, .. }} (User: {})",
+                    provider, chat_id
+                )
+            }
+            // The text to test likely holds the secrets themselves
+            SystemCommand::Redact {
+                action: Some(action),
+                ..
+            } if action == "test" => format!("Redact test (User: {})", chat_id),
+            _ => format!("{:?} (User: {})", command, chat_id),
+        };
         let _ = sqlx::query("INSERT INTO audit_logs (command) VALUES (?)")
             .bind(&cmd_str)
             .execute(&pool)
@@ -54,9 +78,70 @@ pub async fn dispatch(
     }
 
     match command {
-        SystemCommand::Investigate { alias: _ } => CommandResponse::Text(
-            "Use /ask <question> instead. Example: /ask investigate local".to_string(),
-        ),
+        SystemCommand::Investigate { alias, goal } => match manager.get_server(&alias).await {
+            Ok(Some(server)) => {
+                let goal = if goal.trim().is_empty() {
+                    "Check the overall health of the server and find any problems.".to_string()
+                } else {
+                    goal
+                };
+                crate::core::agent::start(pool.clone(), chat_id, server, alias, goal).await
+            }
+            Ok(None) => CommandResponse::Text(format!(
+                "Server '{}' not found. Use /add to configure it.",
+                alias
+            )),
+            Err(e) => CommandResponse::Text(format!("Database error: {}", e)),
+        },
+
+        SystemCommand::HowTo { alias, task } => match manager.get_server(&alias).await {
+            Ok(Some(_)) => {
+                crate::core::howto::propose(&pool, &ai_client, chat_id, &alias, &task).await
+            }
+            Ok(None) => CommandResponse::Text(format!(
+                "Server '{}' not found. Use /add to configure it.",
+                alias
+            )),
+            Err(e) => CommandResponse::Text(format!("Database error: {}", e)),
+        },
+
+        SystemCommand::Report { action } => {
+            use crate::core::report;
+
+            let document = |r: report::IncidentReport| CommandResponse::Document {
+                file_name: r.file_name(),
+                caption: format!("📝 Incident report {}", r.describe()),
+                data: r.content.into_bytes(),
+            };
+            match action.as_deref() {
+                None => match report::generate(&pool, &ai_client, chat_id).await {
+                    Ok(r) => document(r),
+                    Err(e) => CommandResponse::Text(e),
+                },
+                Some("list") => match report::list(&pool, chat_id).await {
+                    Ok(reports) if reports.is_empty() => CommandResponse::Text(
+                        "No incident reports yet. Run /report after a session.".to_string(),
+                    ),
+                    Ok(reports) => {
+                        let mut msg =
+                            "Incident reports (resend one with /report <id>):\n".to_string();
+                        for r in reports {
+                            msg.push_str(&format!("{}\n", r.describe()));
+                        }
+                        CommandResponse::Text(msg)
+                    }
+                    Err(e) => CommandResponse::Text(format!("Database error: {}", e)),
+                },
+                Some(id) => match id.trim_start_matches('#').parse::<i64>() {
+                    Ok(id) => match report::get(&pool, chat_id, id).await {
+                        Ok(Some(r)) => document(r),
+                        Ok(None) => CommandResponse::Text(format!("No incident report #{}.", id)),
+                        Err(e) => CommandResponse::Text(format!("Database error: {}", e)),
+                    },
+                    Err(_) => CommandResponse::Text("Usage: /report [list|<id>]".to_string()),
+                },
+            }
+        }
 
         SystemCommand::EndSession => {
             if session_manager.end_session(chat_id).is_some() {
@@ -105,23 +190,69 @@ pub async fn dispatch(
                 if servers.is_empty() {
                     CommandResponse::Text("No servers configured.".to_string())
                 } else {
-                    let mut options = Vec::new();
+                    let maintenance = MaintenanceManager::new(pool.clone())
+                        .list_active()
+                        .await
+                        .unwrap_or_default();
+                    let mut buttons = Vec::new();
                     for (alias, _) in servers {
-                        options.push(alias);
+                        // Badge servers under maintenance
+                        let label = if maintenance.iter().any(|w| w.alias == alias) {
+                            format!("🔧 {}", alias)
+                        } else {
+                            alias.clone()
+                        };
+                        buttons.push((label, format!("menu_server:{}", alias)));
                     }
-                    CommandResponse::InteractiveList {
+                    CommandResponse::Buttons {
                         title: "Select a server to manage:".to_string(),
-                        options,
-                        callback_prefix: "menu_server:".to_string(),
+                        buttons,
                     }
                 }
             }
             Err(e) => CommandResponse::Text(format!("Failed to list servers: {}", e)),
         },
 
-        SystemCommand::Exec { alias, cmd } => {
+        SystemCommand::Exec {
+            alias,
+            cmd,
+            force,
+            stream,
+        } => {
             println!("Dispatcher: Executing '{}' on '{}'", cmd, alias);
+            let maintenance = MaintenanceManager::new(pool.clone());
+            if !force && maintenance.requires_confirmation().await {
+                if let Some(window) = maintenance.active_for(&alias).await {
+                    let id = crate::core::maintenance::hold(HeldCommand {
+                        alias: alias.clone(),
+                        cmd: cmd.clone(),
+                        stream,
+                    });
+                    let reason = window
+                        .reason
+                        .map(|r| format!(" ({})", escape_html(&r)))
+                        .unwrap_or_default();
+                    return CommandResponse::Buttons {
+                        title: format!(
+                            "🔧 <b>{}</b> is under maintenance until {} UTC{}.\nRun <code>{}</code> anyway?",
+                            escape_html(&alias),
+                            window.ends_at.format("%Y-%m-%d %H:%M"),
+                            reason,
+                            escape_html(&cmd)
+                        ),
+                        buttons: vec![
+                            ("⚠️ Run anyway".to_string(), format!("exec_force:{}:run", id)),
+                            ("❌ Cancel".to_string(), format!("exec_force:{}:cancel", id)),
+                        ],
+                    };
+                }
+            }
             match manager.get_server(&alias).await {
+                Ok(Some(server)) if stream => CommandResponse::Stream {
+                    title: format!("{} $ {}", alias, cmd),
+                    server,
+                    command: cmd,
+                },
                 Ok(Some(server)) => {
                     println!("Dispatcher: Server found. Connecting...");
                     match SshExecutor::execute(&server, &cmd) {
@@ -150,10 +281,32 @@ pub async fn dispatch(
             }
         }
 
+        SystemCommand::Tail { alias, target } => match manager.get_server(&alias).await {
+            Ok(Some(server)) => {
+                // Paths are files, anything else is a systemd unit
+                let quoted = format!("'{}'", target.replace('\'', "'\\''"));
+                let command = if target.starts_with('/') {
+                    format!("tail -n 20 -F {}", quoted)
+                } else {
+                    format!("journalctl -u {} -n 20 -f --no-pager", quoted)
+                };
+                CommandResponse::Stream {
+                    server,
+                    command,
+                    title: format!("{} · tail {}", alias, target),
+                }
+            }
+            Ok(None) => CommandResponse::Text(format!(
+                "Server '{}' not found. Use /add to configure it.",
+                alias
+            )),
+            Err(e) => CommandResponse::Text(format!("Database error: {}", e)),
+        },
+
         SystemCommand::Ask { question } => {
             // Check if we have an active session
             if session_manager.has_session(chat_id) {
-                session_manager.process_user_input(chat_id, &question).await
+                session_manager.process_user_input_streaming(chat_id, question)
             } else {
                 // Try to infer server from question or defaults
                 let servers = match manager.list_servers().await {
@@ -184,7 +337,7 @@ pub async fn dispatch(
                 if let Some(alias) = target_alias {
                     session_manager.start_session(chat_id, alias.clone()).await;
                     // Add the user's first question to the session
-                    session_manager.process_user_input(chat_id, &question).await
+                    session_manager.process_user_input_streaming(chat_id, question)
                 } else {
                     CommandResponse::Html(
                         "Please specify which server you want to ask about (e.g., <code>/ask check local</code>) or start with <code>/servers</code>.<br>I cannot answer questions about a server without knowing which one you mean.".to_string()
@@ -195,25 +348,170 @@ pub async fn dispatch(
 
         SystemCommand::SetProvider { provider } => match provider {
             Some(name) => match ai_client.set_provider(&name).await {
-                Ok(msg) => CommandResponse::Text(msg),
+                Ok(msg) => {
+                    session_manager.reload_ai_config().await;
+                    CommandResponse::Text(msg)
+                }
                 Err(e) => CommandResponse::Text(format!("Failed to set provider: {}", e)),
             },
-            None => CommandResponse::InteractiveList {
-                title: "Select AI Provider:".to_string(),
-                options: vec![
-                    "ollama".to_string(),
-                    "openai".to_string(),
-                    "gemini".to_string(),
-                ],
-                callback_prefix: "set_provider:".to_string(),
-            },
+            None => {
+                let profiles = ProviderProfile::list(&pool).await.unwrap_or_default();
+                let mut title = "Select AI Provider:".to_string();
+                let fallbacks = GlobalConfig::load(&pool).await.fallbacks;
+                if !fallbacks.is_empty() {
+                    title.push_str(&format!(
+                        "\nFallbacks: {}",
+                        escape_html(&fallbacks.join(" → "))
+                    ));
+                }
+                for name in &profiles {
+                    if let Some(profile) = ProviderProfile::load(&pool, name).await {
+                        title.push_str(&format!("\n• {}", escape_html(&profile.describe())));
+                    }
+                }
+                let mut options: Vec<String> =
+                    BUILTIN_PROVIDERS.iter().map(|p| p.to_string()).collect();
+                options.extend(profiles);
+                CommandResponse::InteractiveList {
+                    title,
+                    options,
+                    callback_prefix: "set_provider:".to_string(),
+                }
+            }
         },
+
+        SystemCommand::AddProviderProfile {
+            name,
+            kind,
+            base_url,
+            model,
+            api_key,
+            headers,
+        } => {
+            let name = name.to_lowercase();
+            let valid_name = !name.is_empty()
+                && name.len() <= 32
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid_name || BUILTIN_PROVIDERS.contains(&name.as_str()) {
+                return CommandResponse::Text(format!(
+                    "Invalid profile name '{}'. Use up to 32 letters, digits, '-' or '_', other than a built-in provider name.",
+                    name
+                ));
+            }
+            if !["openai", "ollama", "anthropic"].contains(&kind.as_str()) {
+                return CommandResponse::Text(
+                    "Profile type must be openai (any OpenAI-compatible API), ollama or anthropic."
+                        .to_string(),
+                );
+            }
+            if !base_url.starts_with("http://") && !base_url.starts_with("https://") {
+                return CommandResponse::Text(format!("Invalid base URL: {}", base_url));
+            }
+            let mut parsed_headers = Vec::new();
+            for header in &headers {
+                match header.split_once(':') {
+                    Some((k, v)) if !k.trim().is_empty() => {
+                        parsed_headers.push((k.trim().to_string(), v.trim().to_string()))
+                    }
+                    _ => {
+                        return CommandResponse::Text(format!(
+                            "Invalid header '{}', expected Name:Value",
+                            header
+                        ))
+                    }
+                }
+            }
+
+            let profile = ProviderProfile {
+                name: name.clone(),
+                kind,
+                base_url: base_url.trim_end_matches('/').to_string(),
+                api_key: api_key.unwrap_or_default(),
+                model,
+                headers: parsed_headers,
+            };
+            if let Err(e) = profile.save(&pool).await {
+                return CommandResponse::Text(format!("Failed to save config: {}", e));
+            }
+            // Pick up the changes right away if the profile is in use
+            if GlobalConfig::load(&pool).await.provider == name {
+                session_manager.reload_ai_config().await;
+            }
+            CommandResponse::Text(format!(
+                "Profile saved: {}\nUse /provider {} to switch to it.",
+                profile.describe(),
+                name
+            ))
+        }
+
+        SystemCommand::RemoveProviderProfile { name } => {
+            if GlobalConfig::load(&pool).await.provider == name {
+                return CommandResponse::Text(format!(
+                    "Profile '{}' is in use. Switch to another provider first.",
+                    name
+                ));
+            }
+            match ProviderProfile::delete(&pool, &name).await {
+                Ok(true) => {
+                    let mut config = GlobalConfig::load(&pool).await;
+                    if config.fallbacks.contains(&name) {
+                        config.fallbacks.retain(|f| *f != name);
+                        if let Err(e) = config.save(&pool).await {
+                            return CommandResponse::Text(format!("Failed to save config: {}", e));
+                        }
+                        session_manager.reload_ai_config().await;
+                    }
+                    CommandResponse::Text(format!("Profile '{}' deleted.", name))
+                }
+                Ok(false) => CommandResponse::Text(format!("Profile '{}' not found.", name)),
+                Err(e) => CommandResponse::Text(format!("Database error: {}", e)),
+            }
+        }
         SystemCommand::ConfigKey => CommandResponse::InteractiveList {
             title: "Select the AI provider to configure:".to_string(),
-            options: vec!["openai".to_string(), "gemini".to_string()],
+            options: vec![
+                "openai".to_string(),
+                "gemini".to_string(),
+                "anthropic".to_string(),
+            ],
             callback_prefix: "config_key_provider:".to_string(),
         },
 
+        SystemCommand::SetFallbacks { providers } => {
+            let mut config = GlobalConfig::load(&pool).await;
+            let mut fallbacks: Vec<String> = Vec::new();
+            for name in providers {
+                let known = BUILTIN_PROVIDERS.contains(&name.as_str())
+                    || ProviderProfile::load(&pool, &name).await.is_some();
+                if !known {
+                    return CommandResponse::Text(format!(
+                        "Unknown provider '{}'. Use /provider to list them.",
+                        name
+                    ));
+                }
+                if name != config.provider && !fallbacks.contains(&name) {
+                    fallbacks.push(name);
+                }
+            }
+            config.fallbacks = fallbacks;
+            if let Err(e) = config.save(&pool).await {
+                return CommandResponse::Text(format!("Failed to save config: {}", e));
+            }
+            session_manager.reload_ai_config().await;
+
+            if config.fallbacks.is_empty() {
+                CommandResponse::Text("Fallbacks disabled.".to_string())
+            } else {
+                CommandResponse::Text(format!(
+                    "Fallback order: {} → {}",
+                    config.provider,
+                    config.fallbacks.join(" → ")
+                ))
+            }
+        }
+
         SystemCommand::SetApiKey { provider, key } => {
             // Decode key from Base64
             use base64::prelude::*;
@@ -266,6 +564,26 @@ pub async fn dispatch(
                         Err(e) => CommandResponse::Text(format!("Failed to save config: {}", e)),
                     }
                 }
+                "anthropic" => {
+                    let mut config = crate::ai::config::AnthropicConfig::load(&pool).await;
+                    config.api_key = decoded_key;
+                    match config.save(&pool).await {
+                        Ok(_) => {
+                            // Switch to this provider automatically
+                            let switch_msg = match ai_client.set_provider("anthropic").await {
+                                Ok(m) => m,
+                                Err(e) => {
+                                    format!("Key saved, but failed to switch provider: {}", e)
+                                }
+                            };
+                            CommandResponse::Text(format!(
+                                "Anthropic API key updated successfully (saved to DB). {}",
+                                switch_msg
+                            ))
+                        }
+                        Err(e) => CommandResponse::Text(format!("Failed to save config: {}", e)),
+                    }
+                }
                 "ollama" => {
                     CommandResponse::Text("Ollama does not use API keys this way.".to_string())
                 }
@@ -291,21 +609,124 @@ pub async fn dispatch(
             }
         }
 
+        SystemCommand::ConfigAi { provider, args } => {
+            use crate::ai::config::{
+                GenerationParams, ProviderProfile, BUILTIN_PROVIDERS, GENERATION_PARAMS, PURPOSES,
+            };
+            const USAGE: &str = "Usage: /config_ai [provider [purpose] <param> <value...|off>]";
+
+            let provider = match provider {
+                Some(provider) => provider,
+                None => ai_client.provider_name().await,
+            };
+            if !BUILTIN_PROVIDERS.contains(&provider.as_str())
+                && ProviderProfile::load(&pool, &provider).await.is_none()
+            {
+                return CommandResponse::Text(format!("Unknown provider: {}", provider));
+            }
+            let (purpose, rest) = match args.split_first() {
+                Some((purpose, rest)) if PURPOSES.contains(&purpose.as_str()) => {
+                    (Some(purpose.as_str()), rest)
+                }
+                _ => (None, args.as_slice()),
+            };
+
+            match rest {
+                [] => match GenerationParams::list(&pool, &provider).await {
+                    Ok(values) => {
+                        let values: Vec<String> = values
+                            .into_iter()
+                            .filter(|(p, _, _)| purpose.is_none() || p.as_deref() == purpose)
+                            .map(|(p, name, value)| match p {
+                                Some(p) => format!("• [{}] {} = {}", p, name, value),
+                                None => format!("• {} = {}", name, value),
+                            })
+                            .collect();
+                        let values = if values.is_empty() {
+                            "No parameters set: the API defaults apply.".to_string()
+                        } else {
+                            values.join("\n")
+                        };
+                        CommandResponse::Text(format!(
+                            "⚙️ Generation parameters of {}:\n{}\n\nParameters: {}\nPurposes: {} (values for a purpose win over the provider's)\n{}",
+                            provider,
+                            values,
+                            GENERATION_PARAMS.join(", "),
+                            PURPOSES.join(", "),
+                            USAGE
+                        ))
+                    }
+                    Err(e) => CommandResponse::Text(format!("Database error: {}", e)),
+                },
+                [param, values @ ..] if !values.is_empty() => {
+                    let value = match (param.as_str(), values) {
+                        (_, [off]) if off == "off" => None,
+                        // Each word is a sequence; `\n` and `\t` stand for line breaks and tabs
+                        ("stop", sequences) => {
+                            let sequences: Vec<String> = sequences
+                                .iter()
+                                .map(|s| s.replace("\\n", "\n").replace("\\t", "\t"))
+                                .collect();
+                            Some(serde_json::to_string(&sequences).unwrap_or_default())
+                        }
+                        (_, [value]) => Some(value.clone()),
+                        _ => return CommandResponse::Text(USAGE.to_string()),
+                    };
+                    match GenerationParams::save(&pool, &provider, purpose, param, value.as_deref())
+                        .await
+                    {
+                        Ok(()) => {
+                            let target = match purpose {
+                                Some(purpose) => format!("{} ({} requests)", provider, purpose),
+                                None => provider.clone(),
+                            };
+                            CommandResponse::Text(match value {
+                                Some(_) => format!(
+                                    "✅ {} of {} set to {}.",
+                                    param,
+                                    target,
+                                    values.join(" ")
+                                ),
+                                None => format!("✅ {} of {} removed.", param, target),
+                            })
+                        }
+                        Err(e) => CommandResponse::Text(format!("Failed to save config: {}", e)),
+                    }
+                }
+                _ => CommandResponse::Text(USAGE.to_string()),
+            }
+        }
+
         SystemCommand::ListAiModels => match ai_client.list_models().await {
-            Ok(models) => {
+            Ok(mut models) => {
+                // Telegram limits the number of buttons in a message
+                const MAX_LISTED: usize = 50;
+                // and their callback data to 64 bytes; one longer button fails the message
+                const MAX_CALLBACK_BYTES: usize = 64;
+                models.retain(|m| "set_model:".len() + m.len() <= MAX_CALLBACK_BYTES);
                 if models.is_empty() {
                     CommandResponse::Text(
                         "No models found. Provider might not support listing models.".to_string(),
                     )
                 } else {
+                    let title = if models.len() > MAX_LISTED {
+                        format!(
+                            "Available AI Models (first {} of {}). Click to select:",
+                            MAX_LISTED,
+                            models.len()
+                        )
+                    } else {
+                        "Available AI Models. Click to select:".to_string()
+                    };
+                    models.truncate(MAX_LISTED);
                     CommandResponse::InteractiveList {
-                        title: "Available AI Models. Click to select:".to_string(),
+                        title,
                         options: models,
                         callback_prefix: "set_model:".to_string(),
                     }
                 }
             }
-            Err(e) => CommandResponse::Text(format!("Failed to list models: {}", e)),
+            Err(e) => CommandResponse::Text(e.user_message()),
         },
 
         SystemCommand::AiInfo => {
@@ -324,23 +745,40 @@ pub async fn dispatch(
                             println!("Dispatcher: Discovery successful. Analyzing with AI...");
 
                             // Save stats to DB
-                            let _ = sqlx::query(
-                                "INSERT INTO server_stats (server_id, cpu_load, memory_usage, disk_usage) VALUES (?, ?, ?, ?)"
+                            let _ = crate::core::metrics::record_stats(&pool, &server.id, &report)
+                                .await;
+                            if let Err(e) =
+                                crate::ai::prompts::save_discovery(&pool, &alias, &report).await
+                            {
+                                eprintln!("Failed to save discovery summary: {}", e);
+                            }
+
+                            let vars = crate::ai::prompts::Vars::server(&pool, &alias).await;
+                            let question = crate::ai::prompts::render(
+                                &pool,
+                                "discover_analysis",
+                                &ai_client.provider_name().await,
+                                &vars,
                             )
-                            .bind(&server.id)
-                            .bind(&report.resources.cpu_usage)
-                            .bind(&report.resources.memory_usage)
-                            .bind(&report.resources.disk_usage)
-                            .execute(&pool)
                             .await;
 
-                            let question = "Analyze this server report and tell me what is the status of the server. Are there any issues? What should I check next? Be concise.";
-
-                            match ai_client.ask_with_context(question, &report_json).await {
-                                Ok(analysis) => CommandResponse::Text(format!(
-                                    "Discovery Report for {}:\n\n{}\n\nAI Analysis:\n{}",
-                                    alias, report_json, analysis
-                                )),
+                            match ai_client
+                                .ask_with_context(
+                                    &question,
+                                    &report_json,
+                                    &CallContext::chat(chat_id).purpose("analysis"),
+                                )
+                                .await
+                            {
+                                Ok(analysis) => {
+                                    let note = crate::ai::redact::note(analysis.redacted)
+                                        .map(|note| format!("\n\n{}.", note))
+                                        .unwrap_or_default();
+                                    CommandResponse::Text(format!(
+                                        "Discovery Report for {}:\n\n{}\n\nAI Analysis:\n{}{}",
+                                        alias, report_json, analysis.content, note
+                                    ))
+                                }
                                 Err(e) => CommandResponse::Text(format!(
                                     "Discovery successful but AI analysis failed: {}\nReport:\n{}",
                                     e, report_json
@@ -360,11 +798,752 @@ pub async fn dispatch(
             }
         }
 
+        SystemCommand::Chart {
+            aliases,
+            metric,
+            range,
+        } => {
+            use crate::core::chart::{render_line_chart, Series, PALETTE};
+            use crate::core::metrics::{load_history, parse_range_hours, Metric, MAX_RANGE_HOURS};
+
+            let metric = match Metric::parse(&metric) {
+                Some(m) => m,
+                None => {
+                    return CommandResponse::Text(format!(
+                        "Unknown metric '{}'. Use cpu, mem or disk.",
+                        metric
+                    ))
+                }
+            };
+            let hours = match parse_range_hours(&range) {
+                Some(h) => h,
+                None => {
+                    return CommandResponse::Text(format!(
+                        "Invalid range '{}'. Use e.g. 24h or 7d, up to {}d.",
+                        range,
+                        MAX_RANGE_HOURS / 24
+                    ))
+                }
+            };
+
+            // Resolve the requested servers ("all" overlays the whole fleet)
+            let targets = if aliases.iter().any(|a| a == "all") {
+                match manager.list_servers().await {
+                    Ok(servers) => servers,
+                    Err(e) => return CommandResponse::Text(format!("Database error: {}", e)),
+                }
+            } else {
+                let mut targets = Vec::new();
+                for alias in aliases {
+                    match manager.get_server(&alias).await {
+                        Ok(Some(server)) => targets.push((alias, server)),
+                        Ok(None) => {
+                            return CommandResponse::Text(format!(
+                                "Server '{}' not found. Use /add to configure it.",
+                                alias
+                            ))
+                        }
+                        Err(e) => return CommandResponse::Text(format!("Database error: {}", e)),
+                    }
+                }
+                targets
+            };
+
+            let mut series = Vec::new();
+            for (alias, server) in targets.into_iter().take(PALETTE.len()) {
+                match load_history(&pool, &server.id, metric, hours).await {
+                    Ok(samples) if !samples.is_empty() => series.push(Series {
+                        name: alias,
+                        samples,
+                    }),
+                    Ok(_) => {}
+                    Err(e) => return CommandResponse::Text(format!("Database error: {}", e)),
+                }
+            }
+
+            if series.is_empty() {
+                return CommandResponse::Text(format!(
+                    "No {} history in the last {}. Run /discover to collect stats.",
+                    metric.label(),
+                    range
+                ));
+            }
+
+            let y_max = if metric.is_percentage() {
+                100.0
+            } else {
+                let peak = series
+                    .iter()
+                    .flat_map(|s| s.samples.iter().map(|p| p.value))
+                    .fold(0.0_f64, f64::max);
+                (peak * 1.2).max(1.0)
+            };
+
+            let end = chrono::Utc::now().naive_utc();
+            let start = chrono::Duration::try_hours(hours)
+                .and_then(|d| end.checked_sub_signed(d))
+                .unwrap_or(end);
+
+            match render_line_chart(&series, start, end, y_max) {
+                Ok(data) => {
+                    let mut caption = format!(
+                        "{} — last {} (Y axis 0–{:.1})\n",
+                        metric.label(),
+                        range,
+                        y_max
+                    );
+                    for (idx, s) in series.iter().enumerate() {
+                        let values = s.samples.iter().map(|p| p.value);
+                        let min = values.clone().fold(f64::MAX, f64::min);
+                        let max = values.clone().fold(f64::MIN, f64::max);
+                        let last = s.samples.last().map(|p| p.value).unwrap_or_default();
+                        caption.push_str(&format!(
+                            "{} {}: min {:.1} / max {:.1} / last {:.1} ({} samples)\n",
+                            PALETTE[idx].1,
+                            s.name,
+                            min,
+                            max,
+                            last,
+                            s.samples.len()
+                        ));
+                    }
+                    CommandResponse::Photo { data, caption }
+                }
+                Err(e) => CommandResponse::Text(format!("Failed to render chart: {}", e)),
+            }
+        }
+
+        SystemCommand::Digest { action, value } => {
+            use crate::core::digest::{parse_quiet_hours, parse_time, DigestConfig};
+
+            let mut config = DigestConfig::load(&pool).await;
+            let action = match action {
+                Some(a) => a.to_lowercase(),
+                None => {
+                    return match crate::core::digest::run(&pool, &ai_client, config.ai_summary)
+                        .await
+                    {
+                        Ok(report) => CommandResponse::Html(report),
+                        Err(e) => CommandResponse::Text(format!("Digest failed: {}", e)),
+                    };
+                }
+            };
+
+            match (action.as_str(), value) {
+                ("status", _) => return CommandResponse::Text(config.describe()),
+                ("daily", _) | ("off", _) => config.schedule = action.clone(),
+                ("weekly", day) => {
+                    config.schedule = "weekly".to_string();
+                    if let Some(day) = day {
+                        match day.parse() {
+                            Ok(d) => config.weekday = d,
+                            Err(_) => {
+                                return CommandResponse::Text(format!(
+                                    "Invalid weekday '{}'. Use e.g. mon, tue...",
+                                    day
+                                ))
+                            }
+                        }
+                    }
+                }
+                ("time", Some(t)) => match parse_time(&t) {
+                    Some(time) => config.time = time,
+                    None => {
+                        return CommandResponse::Text("Invalid time. Use HH:MM.".to_string())
+                    }
+                },
+                ("tz", Some(tz)) => match tz.parse() {
+                    Ok(tz) => config.timezone = tz,
+                    Err(_) => {
+                        return CommandResponse::Text(format!(
+                            "Unknown timezone '{}'. Use an IANA name like Europe/Madrid.",
+                            tz
+                        ))
+                    }
+                },
+                ("quiet", Some(q)) if q == "off" => config.quiet_hours = None,
+                ("quiet", Some(q)) => match parse_quiet_hours(&q) {
+                    Some(hours) => config.quiet_hours = Some(hours),
+                    None => {
+                        return CommandResponse::Text(
+                            "Invalid quiet hours. Use HH:MM-HH:MM or off.".to_string(),
+                        )
+                    }
+                },
+                ("ai", Some(v)) => config.ai_summary = v == "on",
+                _ => {
+                    return CommandResponse::Text(
+                        "Usage: /digest [status|daily|weekly [day]|off|time HH:MM|tz <zone>|quiet HH:MM-HH:MM|off|ai on|off]"
+                            .to_string(),
+                    )
+                }
+            }
+
+            match config.save(&pool).await {
+                Ok(_) => {
+                    CommandResponse::Text(format!("Digest settings saved.\n{}", config.describe()))
+                }
+                Err(e) => CommandResponse::Text(format!("Failed to save config: {}", e)),
+            }
+        }
+
+        SystemCommand::ListMaintenance => {
+            let maintenance = MaintenanceManager::new(pool.clone());
+            let tz = crate::core::digest::DigestConfig::load(&pool)
+                .await
+                .timezone;
+            match maintenance.list_upcoming().await {
+                Ok(windows) if windows.is_empty() => {
+                    CommandResponse::Text("No active or scheduled maintenance windows.".to_string())
+                }
+                Ok(windows) => {
+                    let mut text = format!("Maintenance windows ({}):\n", tz.name());
+                    for w in windows {
+                        text.push_str(&format!("{}\n", w.describe(&tz)));
+                    }
+                    let confirm = if maintenance.requires_confirmation().await {
+                        "on"
+                    } else {
+                        "off"
+                    };
+                    text.push_str(&format!("\nExtra command confirmation: {}", confirm));
+                    CommandResponse::Text(text)
+                }
+                Err(e) => CommandResponse::Text(format!("Database error: {}", e)),
+            }
+        }
+
+        SystemCommand::StartMaintenance {
+            aliases,
+            duration,
+            start,
+            reason,
+        } => {
+            use crate::core::maintenance::{parse_duration, parse_start, MAX_WINDOW_DAYS};
+
+            let duration = match parse_duration(&duration) {
+                Some(d) => d,
+                None => {
+                    return CommandResponse::Text(format!(
+                        "Invalid duration. Use e.g. 30m, 2h or 1d, up to {}d.",
+                        MAX_WINDOW_DAYS
+                    ))
+                }
+            };
+            let tz = crate::core::digest::DigestConfig::load(&pool)
+                .await
+                .timezone;
+            let starts_at = match start {
+                Some(s) => match parse_start(&s, &tz) {
+                    Some(t) => t,
+                    None => {
+                        return CommandResponse::Text(format!(
+                            "Invalid start '{}'. Use @HH:MM or @YYYY-MM-DDTHH:MM ({}).",
+                            s,
+                            tz.name()
+                        ))
+                    }
+                },
+                None => chrono::Utc::now().naive_utc(),
+            };
+            let ends_at = match starts_at.checked_add_signed(duration) {
+                Some(t) => t,
+                None => return CommandResponse::Text("Invalid start or duration.".to_string()),
+            };
+
+            let maintenance = MaintenanceManager::new(pool.clone());
+            let mut lines = Vec::new();
+            for alias in aliases {
+                let server = match manager.get_server(&alias).await {
+                    Ok(Some(server)) => server,
+                    Ok(None) => {
+                        lines.push(format!("Server '{}' not found.", alias));
+                        continue;
+                    }
+                    Err(e) => return CommandResponse::Text(format!("Database error: {}", e)),
+                };
+                match maintenance
+                    .schedule(&server.id, starts_at, ends_at, reason.as_deref())
+                    .await
+                {
+                    Ok(id) => lines.push(format!("#{} {} scheduled", id, alias)),
+                    Err(e) => return CommandResponse::Text(format!("Database error: {}", e)),
+                }
+            }
+
+            let fmt = |t: chrono::NaiveDateTime| {
+                use chrono::TimeZone;
+                chrono::Utc
+                    .from_utc_datetime(&t)
+                    .with_timezone(&tz)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            };
+            CommandResponse::Text(format!(
+                "🔧 Maintenance {} → {} ({}):\n{}\n\nNotifications about these servers are muted until it ends.",
+                fmt(starts_at),
+                fmt(ends_at),
+                tz.name(),
+                lines.join("\n")
+            ))
+        }
+
+        SystemCommand::EndMaintenance { aliases } => {
+            let maintenance = MaintenanceManager::new(pool.clone());
+            let mut lines = Vec::new();
+            for alias in aliases {
+                match manager.get_server(&alias).await {
+                    Ok(Some(server)) => match maintenance.end(&server.id).await {
+                        Ok(0) => lines.push(format!("{}: no maintenance window.", alias)),
+                        Ok(_) => lines.push(format!("{}: maintenance ended.", alias)),
+                        Err(e) => lines.push(format!("{}: database error: {}", alias, e)),
+                    },
+                    Ok(None) => lines.push(format!("Server '{}' not found.", alias)),
+                    Err(e) => lines.push(format!("{}: database error: {}", alias, e)),
+                }
+            }
+            CommandResponse::Text(lines.join("\n"))
+        }
+
+        SystemCommand::MaintenanceConfirm { enabled } => {
+            match MaintenanceManager::new(pool.clone())
+                .set_requires_confirmation(enabled)
+                .await
+            {
+                Ok(_) => CommandResponse::Text(format!(
+                    "Extra confirmation for commands under maintenance: {}",
+                    if enabled { "on" } else { "off" }
+                )),
+                Err(e) => CommandResponse::Text(format!("Failed to save config: {}", e)),
+            }
+        }
+
+        SystemCommand::Alerts { action, value } => {
+            use crate::core::alerts::{AlertManager, AlertRules};
+
+            if let (Some(action), Some(value)) = (action, value) {
+                let key = match action.as_str() {
+                    "disk" if value.parse::<f64>().is_ok() => "disk_threshold",
+                    "interval" if value.parse::<i64>().is_ok() => "interval_minutes",
+                    _ => {
+                        return CommandResponse::Text(
+                            "Usage: /alerts [disk <percent>|interval <minutes>]".to_string(),
+                        )
+                    }
+                };
+                return match crate::db::set_setting(&pool, "alerts", key, &value).await {
+                    Ok(_) => {
+                        CommandResponse::Text(format!("Alert rule {} set to {}.", action, value))
+                    }
+                    Err(e) => CommandResponse::Text(format!("Failed to save config: {}", e)),
+                };
+            }
+
+            let rules = AlertRules::load(&pool).await;
+            let header = format!(
+                "Health checks every {} min, disk threshold {:.0}%.",
+                rules.interval_minutes, rules.disk_threshold
+            );
+            match AlertManager::new(pool.clone()).list_open().await {
+                Ok(alerts) if alerts.is_empty() => {
+                    CommandResponse::Text(format!("No open alerts. {}", header))
+                }
+                Ok(alerts) => {
+                    let mut title = format!("<b>🚨 Open alerts</b>\n{}\n\n", header);
+                    let mut buttons = Vec::new();
+                    for alert in alerts {
+                        title.push_str(&format!(
+                            "#{} [{}] {} (since {} UTC)\n",
+                            alert.id,
+                            alert.status,
+                            escape_html(&alert.message),
+                            alert.fired_at
+                        ));
+                        if alert.status == "firing" {
+                            buttons.push((
+                                format!("✅ Acknowledge #{}", alert.id),
+                                format!("alert_ack:{}", alert.id),
+                            ));
+                        }
+                    }
+                    CommandResponse::Buttons { title, buttons }
+                }
+                Err(e) => CommandResponse::Text(format!("Database error: {}", e)),
+            }
+        }
+
+        SystemCommand::AckAlert { id } => {
+            match crate::core::alerts::AlertManager::new(pool.clone())
+                .acknowledge(id)
+                .await
+            {
+                Ok(Some(alert)) => CommandResponse::Text(format!(
+                    "Alert #{} acknowledged: {}",
+                    alert.id, alert.message
+                )),
+                Ok(None) => CommandResponse::Text(format!(
+                    "Alert #{} is not firing (already handled?).",
+                    id
+                )),
+                Err(e) => CommandResponse::Text(format!("Database error: {}", e)),
+            }
+        }
+
+        SystemCommand::Escalation { action, value } => {
+            let mut policy = crate::core::alerts::EscalationPolicy::load(&pool).await;
+            match (action.as_deref(), value) {
+                (None, _) => return CommandResponse::Text(policy.describe()),
+                (Some("after"), Some(v)) => match v.parse::<i64>() {
+                    Ok(minutes) if minutes > 0 => policy.ack_minutes = minutes,
+                    _ => {
+                        return CommandResponse::Text(
+                            "Invalid delay. Use a number of minutes.".to_string(),
+                        )
+                    }
+                },
+                (Some("chats"), Some(v)) if v == "off" => policy.chat_ids.clear(),
+                (Some("chats"), Some(v)) => {
+                    let ids: Result<Vec<i64>, _> =
+                        v.split(',').map(|id| id.trim().parse()).collect();
+                    match ids {
+                        Ok(ids) => policy.chat_ids = ids,
+                        Err(_) => {
+                            return CommandResponse::Text(
+                                "Invalid chat IDs. Use a comma separated list of numbers."
+                                    .to_string(),
+                            )
+                        }
+                    }
+                }
+                (Some("webhook"), Some(v)) if v == "off" => policy.webhook_url = None,
+                (Some("webhook"), Some(v)) if v.starts_with("http") => policy.webhook_url = Some(v),
+                _ => {
+                    return CommandResponse::Text(
+                        "Usage: /escalation [after <minutes>|chats <id,...>|off|webhook <url>|off]"
+                            .to_string(),
+                    )
+                }
+            }
+            match policy.save(&pool).await {
+                Ok(_) => CommandResponse::Text(format!("Saved.\n{}", policy.describe())),
+                Err(e) => CommandResponse::Text(format!("Failed to save config: {}", e)),
+            }
+        }
+
+        SystemCommand::Usage { action, args } => {
+            use crate::ai::usage::{self, Period};
+            const USAGE: &str = "Usage: /usage [day|month|cap <usd|off>|price <model> <in> <out>]";
+            let period = match (action.as_deref(), args.as_slice()) {
+                (None | Some("day") | Some("today"), []) => Period::Day,
+                (Some("month"), []) => Period::Month,
+                (Some("cap"), [value]) => {
+                    let cap = match value.as_str() {
+                        "off" => None,
+                        v => match v.trim_start_matches('$').parse::<f64>() {
+                            Ok(cap) if cap > 0.0 && cap.is_finite() => Some(cap),
+                            _ => {
+                                return CommandResponse::Text(
+                                    "Invalid cap. Use an amount in USD, e.g. /usage cap 2.50"
+                                        .to_string(),
+                                )
+                            }
+                        },
+                    };
+                    return match usage::set_daily_cap(&pool, cap).await {
+                        Ok(_) => CommandResponse::Text(match cap {
+                            Some(cap) => format!(
+                                "✅ Daily AI spend cap set to ${:.2}. Paid providers are skipped once it is reached.",
+                                cap
+                            ),
+                            None => "✅ Daily AI spend cap removed.".to_string(),
+                        }),
+                        Err(e) => CommandResponse::Text(format!("Failed to save config: {}", e)),
+                    };
+                }
+                (Some("price"), [model, input, output]) => {
+                    let (input, output) = match (input.parse::<f64>(), output.parse::<f64>()) {
+                        (Ok(i), Ok(o)) if i >= 0.0 && o >= 0.0 && i.is_finite() && o.is_finite() => {
+                            (i, o)
+                        }
+                        _ => {
+                            return CommandResponse::Text(
+                                "Invalid price. Use USD per million input and output tokens, e.g. /usage price gpt-4o 2.5 10"
+                                    .to_string(),
+                            )
+                        }
+                    };
+                    return match usage::set_price(&pool, model, input, output).await {
+                        Ok(_) => CommandResponse::Text(format!(
+                            "✅ Price of {} set to ${} in / ${} out per million tokens.",
+                            model, input, output
+                        )),
+                        Err(e) => CommandResponse::Text(format!("Failed to save config: {}", e)),
+                    };
+                }
+                _ => return CommandResponse::Text(USAGE.to_string()),
+            };
+            match usage::report(&pool, period).await {
+                Ok(html) => CommandResponse::Html(html),
+                Err(e) => CommandResponse::Text(format!("Database error: {}", e)),
+            }
+        }
+
+        SystemCommand::Redact {
+            action,
+            target,
+            text,
+        } => {
+            use crate::ai::redact::{self, Redactor};
+            const USAGE: &str = "Usage: /redact [add <name> <regex>|remove <name>|local <provider> on|off|test <text>]";
+
+            match (action.as_deref(), target, text) {
+                (None, _, _) => {
+                    let patterns = match redact::patterns(&pool).await {
+                        Ok(patterns) => patterns,
+                        Err(e) => return CommandResponse::Text(format!("Database error: {}", e)),
+                    };
+                    let local = redact::local_providers(&pool).await.unwrap_or_default();
+                    let mut html = format!(
+                        "🔒 <b>Redaction</b>\nSecrets are replaced with <code>[REDACTED:detector]</code> before any text is sent to an AI provider.\n\n<b>Built-in detectors</b>\n{}",
+                        redact::detectors().join(", ")
+                    );
+                    html.push_str("\n\n<b>Your patterns</b>");
+                    if patterns.is_empty() {
+                        html.push_str(
+                            "\nNone. Add one with /redact add &lt;name&gt; &lt;regex&gt;",
+                        );
+                    }
+                    for (name, pattern) in &patterns {
+                        html.push_str(&format!(
+                            "\n• {}: <code>{}</code>",
+                            escape_html(name),
+                            escape_html(pattern)
+                        ));
+                    }
+                    html.push_str(&format!(
+                        "\n\n<b>Local only</b> (sent unredacted)\n{}",
+                        if local.is_empty() {
+                            "None".to_string()
+                        } else {
+                            escape_html(&local.join(", "))
+                        }
+                    ));
+                    CommandResponse::Html(html)
+                }
+                (Some("add"), Some(name), Some(pattern)) => {
+                    match redact::set_pattern(&pool, &name, Some(&pattern)).await {
+                        Ok(()) => CommandResponse::Text(format!(
+                            "✅ Pattern {} added. Matches are redacted before text is sent to the AI; a (?P<secret>...) group limits the redaction to that part.",
+                            name
+                        )),
+                        Err(e) => CommandResponse::Text(format!("Failed to save pattern: {}", e)),
+                    }
+                }
+                (Some("remove"), Some(name), None) => {
+                    match redact::set_pattern(&pool, &name, None).await {
+                        Ok(()) => CommandResponse::Text(format!("✅ Pattern {} removed.", name)),
+                        Err(e) => CommandResponse::Text(format!("Failed to save pattern: {}", e)),
+                    }
+                }
+                (Some("local"), Some(provider), Some(value)) if value == "on" || value == "off" => {
+                    if !BUILTIN_PROVIDERS.contains(&provider.as_str())
+                        && ProviderProfile::load(&pool, &provider).await.is_none()
+                    {
+                        return CommandResponse::Text(format!("Unknown provider: {}", provider));
+                    }
+                    match redact::set_local(&pool, &provider, value == "on").await {
+                        Ok(()) if value == "on" => CommandResponse::Text(format!(
+                            "✅ {} is marked local only: text is sent to it without redaction.",
+                            provider
+                        )),
+                        Ok(()) => CommandResponse::Text(format!(
+                            "✅ Text sent to {} is redacted again.",
+                            provider
+                        )),
+                        Err(e) => CommandResponse::Text(format!("Failed to save config: {}", e)),
+                    }
+                }
+                (Some("test"), None, Some(text)) => {
+                    let (redacted, count) = Redactor::load(&pool).await.redact(&text);
+                    CommandResponse::Html(format!(
+                        "🔒 {} redaction(s):\n<pre>{}</pre>",
+                        count,
+                        escape_html(&redacted)
+                    ))
+                }
+                _ => CommandResponse::Text(USAGE.to_string()),
+            }
+        }
+
+        SystemCommand::Prompt {
+            action,
+            target,
+            text,
+        } => {
+            use crate::ai::prompts::{self, TEMPLATES};
+            const USAGE: &str = "Usage: /prompt [show|set|reset <name>[@provider] [text]] or /prompt notes <alias> [text|off]";
+
+            if action.as_deref() == Some("notes") {
+                let alias = match target {
+                    Some(alias) => alias,
+                    None => return CommandResponse::Text(USAGE.to_string()),
+                };
+                let notes = match text.as_deref() {
+                    None => {
+                        return CommandResponse::Text(match prompts::notes(&pool, &alias).await {
+                            Some(notes) => format!("📝 Notes about {}:\n{}", alias, notes),
+                            None => format!(
+                                "No notes about {}. Add some with /prompt notes {} <text>.",
+                                alias, alias
+                            ),
+                        })
+                    }
+                    Some("off") => "",
+                    Some(notes) => notes,
+                };
+                return match prompts::set_notes(&pool, &alias, notes).await {
+                    Ok(_) if notes.is_empty() => {
+                        CommandResponse::Text(format!("✅ Notes about {} removed.", alias))
+                    }
+                    Ok(_) => CommandResponse::Text(format!(
+                        "✅ Notes about {} saved. New sessions with it will include them.",
+                        alias
+                    )),
+                    Err(e) => CommandResponse::Text(format!("Failed to save config: {}", e)),
+                };
+            }
+
+            let (name, provider) = match target.as_deref() {
+                Some(target) => match target.split_once('@') {
+                    Some((name, provider)) => (name, Some(provider)),
+                    None => (target, None),
+                },
+                None if action.is_none() => ("", None),
+                None => return CommandResponse::Text(USAGE.to_string()),
+            };
+            let template = match (action.as_deref(), prompts::template(name)) {
+                (None, _) => None,
+                (Some(_), Some(template)) => Some(template),
+                (Some(_), None) => {
+                    return CommandResponse::Text(format!(
+                        "Unknown prompt '{}'. Use /prompt to list them.",
+                        name
+                    ))
+                }
+            };
+
+            match (action.as_deref(), template, text) {
+                (None, _, _) => {
+                    let mut html = "📝 <b>Prompt templates</b>\n".to_string();
+                    for template in TEMPLATES.iter() {
+                        let overrides = match prompts::overrides(&pool, template.name).await {
+                            Ok(keys) => keys,
+                            Err(e) => {
+                                return CommandResponse::Text(format!("Database error: {}", e))
+                            }
+                        };
+                        html.push_str(&format!(
+                            "\n<code>{}</code> — {}\nVariables: {}",
+                            template.name,
+                            template.description,
+                            template
+                                .variables
+                                .iter()
+                                .map(|v| format!("{{{{{}}}}}", v))
+                                .collect::<Vec<_>>()
+                                .join(" ")
+                        ));
+                        if !overrides.is_empty() {
+                            html.push_str(&format!(
+                                "\nOverridden: {}",
+                                escape_html(&overrides.join(", "))
+                            ));
+                        }
+                        html.push('\n');
+                    }
+                    html.push_str(
+                        "\nA <code>name@provider</code> override applies only while that provider (or profile) is active. Reset one with /prompt reset.",
+                    );
+                    CommandResponse::Html(html)
+                }
+                (Some("show"), Some(template), _) => {
+                    let active = ai_client.provider_name().await;
+                    let provider = provider.unwrap_or(&active);
+                    let text = prompts::text(&pool, template.name, provider).await;
+                    CommandResponse::Html(format!(
+                        "📝 <b>{}</b> for {}\n<pre>{}</pre>",
+                        template.name,
+                        escape_html(provider),
+                        escape_html(text.trim_end())
+                    ))
+                }
+                (Some("set"), Some(template), Some(text)) => {
+                    match prompts::set_override(&pool, template.name, provider, &text).await {
+                        Ok(_) => CommandResponse::Text(format!(
+                            "✅ Prompt {} overridden{}. It applies to new messages and sessions.",
+                            template.name,
+                            provider.map(|p| format!(" for {}", p)).unwrap_or_default()
+                        )),
+                        Err(e) => CommandResponse::Text(format!("Failed to save config: {}", e)),
+                    }
+                }
+                (Some("reset"), Some(template), None) => {
+                    match prompts::set_override(&pool, template.name, provider, "").await {
+                        Ok(_) => CommandResponse::Text(format!(
+                            "✅ Prompt {}{} reset.",
+                            template.name,
+                            provider.map(|p| format!(" for {}", p)).unwrap_or_default()
+                        )),
+                        Err(e) => CommandResponse::Text(format!("Failed to save config: {}", e)),
+                    }
+                }
+                _ => CommandResponse::Text(USAGE.to_string()),
+            }
+        }
+
         SystemCommand::CountTokens { text } => match ai_client.count_tokens(&text).await {
-            Ok(count) => CommandResponse::Text(format!("Estimated token count: {}", count)),
+            Ok(count) => CommandResponse::Text(format!(
+                "Token count: {} ({}, {})",
+                count.tokens,
+                if count.exact { "exact" } else { "estimated" },
+                ai_client.get_provider_info().await
+            )),
             Err(e) => CommandResponse::Text(format!("Failed to count tokens: {}", e)),
         },
 
+        SystemCommand::Context => {
+            let usage = session_manager.context_usage(chat_id).await;
+            let mut text = format!(
+                "🧠 <b>Context</b>\nModel: {}\nContext window: {} tokens\nHistory budget: {} tokens",
+                crate::core::session::escape_html(&usage.provider),
+                usage.window,
+                usage.budget
+            );
+            match usage.session {
+                Some(session) => {
+                    let used = session.system_tokens + session.history_tokens;
+                    text.push_str(&format!(
+                        "\n\nSession on <b>{}</b>\nMessages: {}\nSystem prompt and summaries: {} tokens\nConversation: {} tokens\nTotal: {} tokens ({}% of budget){}",
+                        crate::core::session::escape_html(&session.alias),
+                        session.messages,
+                        session.system_tokens,
+                        session.history_tokens,
+                        used,
+                        used * 100 / usage.budget.max(1),
+                        if session.exact { "" } else { ", estimated" }
+                    ));
+                    if session.summarised > 0 {
+                        text.push_str(&format!(
+                            "\n<i>{} older messages were summarised or dropped to stay within the budget.</i>",
+                            session.summarised
+                        ));
+                    }
+                }
+                None => text.push_str("\n\nNo active session. Start one with /ask."),
+            }
+            CommandResponse::Html(text)
+        }
+
         SystemCommand::Explain => {
             let explanation = include_str!("../../templates/messages/explain.html")
                 .trim()
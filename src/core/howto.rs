@@ -0,0 +1,248 @@
+use crate::ai::client::AiClient;
+use crate::ai::prompts::{self, Vars};
+use crate::ai::usage::CallContext;
+use crate::core::session::escape_html;
+use crate::db::DbPool;
+use crate::executor::validator;
+use crate::models::CommandResponse;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+/// Proposals kept for their Run button; older ones are forgotten.
+const MAX_PENDING: usize = 50;
+
+/// How much running a proposed command may change the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Risk {
+    ReadOnly,
+    Mutating,
+    Destructive,
+}
+
+impl Risk {
+    /// Reads the model's rating, tolerating `read_only`, `Read only` and the like.
+    fn parse(text: &str) -> Option<Risk> {
+        match text.trim().to_lowercase().replace(['_', ' '], "-").as_str() {
+            "read-only" | "readonly" => Some(Risk::ReadOnly),
+            "mutating" => Some(Risk::Mutating),
+            "destructive" => Some(Risk::Destructive),
+            _ => None,
+        }
+    }
+
+    /// The rating as shown to the user.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Risk::ReadOnly => "🟢 read-only",
+            Risk::Mutating => "🟠 mutating",
+            Risk::Destructive => "🔴 destructive",
+        }
+    }
+}
+
+/// A command proposed by `/howto`, waiting for its Run button.
+#[derive(Debug, Clone)]
+pub struct Proposal {
+    pub alias: String,
+    pub command: String,
+    pub risk: Risk,
+}
+
+/// The model's answer, as asked for by the `howto` template.
+#[derive(Debug, Deserialize)]
+struct Answer {
+    command: String,
+    #[serde(default)]
+    explanation: Vec<Step>,
+    #[serde(default)]
+    risk: String,
+    #[serde(default)]
+    notes: String,
+}
+
+/// One line or pipeline stage of the command and what it does.
+#[derive(Debug, Deserialize)]
+struct Step {
+    line: String,
+    meaning: String,
+}
+
+/// Pending proposals, by id.
+static PROPOSALS: LazyLock<Mutex<HashMap<u64, Proposal>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Proposal `id`, if it is still known.
+pub fn proposal(id: u64) -> Option<Proposal> {
+    PROPOSALS.lock().unwrap().get(&id).cloned()
+}
+
+/// Keeps a proposal for its Run button and returns its id.
+fn store(proposal: Proposal) -> u64 {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let mut proposals = PROPOSALS.lock().unwrap();
+    proposals.insert(id, proposal);
+    if proposals.len() > MAX_PENDING {
+        if let Some(oldest) = proposals.keys().min().copied() {
+            proposals.remove(&oldest);
+        }
+    }
+    id
+}
+
+/// Parses the model's answer, which may be wrapped in a code fence or in prose.
+fn parse_answer(reply: &str) -> Option<Answer> {
+    let start = reply.find('{')?;
+    let end = reply.rfind('}')?;
+    let answer: Answer = serde_json::from_str(reply.get(start..=end)?).ok()?;
+    (!answer.command.trim().is_empty()).then_some(answer)
+}
+
+/// The risk of `command` given the model's `rating`, with a note when it was raised.
+///
+/// The model's rating never goes below what the allowlist says; a missing or
+/// unknown rating counts as mutating.
+fn rate(rating: &str, command: &str) -> (Risk, &'static str) {
+    match Risk::parse(rating).unwrap_or(Risk::Mutating) {
+        Risk::ReadOnly if !validator::is_read_only(command) => (
+            Risk::Mutating,
+            " (rated read-only by the AI, but not on the read-only allowlist)",
+        ),
+        risk => (risk, ""),
+    }
+}
+
+/// Asks the AI for a command that does `task` on server `alias`, explained line by
+/// line and rated by risk.
+///
+/// Runs outside of any session, so the chat's session history is left alone.
+pub async fn propose(
+    pool: &DbPool,
+    ai_client: &AiClient,
+    chat_id: i64,
+    alias: &str,
+    task: &str,
+) -> CommandResponse {
+    let provider = ai_client.provider_name().await;
+    let vars = Vars::server(pool, alias).await.set("task", task);
+    let prompt = prompts::render(pool, "howto", &provider, &vars).await;
+    let reply = match ai_client
+        .ask(&prompt, &CallContext::chat(chat_id).purpose("howto"))
+        .await
+    {
+        Ok(reply) => reply,
+        Err(e) => return CommandResponse::Text(e.user_message()),
+    };
+    let answer = match parse_answer(&reply) {
+        Some(answer) => answer,
+        None => {
+            return CommandResponse::Text(format!(
+                "The AI did not answer with a command. Its reply:\n{}",
+                reply.trim()
+            ))
+        }
+    };
+
+    let command = answer.command.trim().to_string();
+    let (risk, rating_note) = rate(&answer.risk, &command);
+
+    let mut html = format!(
+        "🛠 <b>How to:</b> {}\nServer: <b>{}</b>\n\n<pre>{}</pre>\n\nRisk: <b>{}</b>{}",
+        escape_html(task),
+        escape_html(alias),
+        escape_html(&command),
+        risk.label(),
+        escape_html(rating_note)
+    );
+    if !answer.explanation.is_empty() {
+        html.push_str("\n\n<b>Explanation</b>");
+        for step in &answer.explanation {
+            html.push_str(&format!(
+                "\n• <code>{}</code> – {}",
+                escape_html(step.line.trim()),
+                escape_html(step.meaning.trim())
+            ));
+        }
+    }
+    if !answer.notes.trim().is_empty() {
+        html.push_str(&format!("\n\n<i>{}</i>", escape_html(answer.notes.trim())));
+    }
+
+    let id = store(Proposal {
+        alias: alias.to_string(),
+        command: command.clone(),
+        risk,
+    });
+    CommandResponse::Proposal {
+        title: html,
+        command,
+        run_data: format!("howto:{}:run", id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_answer, proposal, rate, store, Proposal, Risk, MAX_PENDING};
+
+    #[test]
+    fn parses_risk_ratings() {
+        for text in ["read-only", "read_only", "Read only", " READONLY "] {
+            assert_eq!(Risk::parse(text), Some(Risk::ReadOnly), "{:?}", text);
+        }
+        assert_eq!(Risk::parse("Mutating"), Some(Risk::Mutating));
+        assert_eq!(Risk::parse("destructive"), Some(Risk::Destructive));
+        assert_eq!(Risk::parse("harmless"), None);
+    }
+
+    #[test]
+    fn parses_answers_wrapped_in_fences_or_prose() {
+        let reply = "Here you go:\n```json\n{\"command\": \"df -h /\", \"explanation\": \
+                     [{\"line\": \"df -h /\", \"meaning\": \"root usage\"}], \"risk\": \"read-only\"}\n```";
+        let answer = parse_answer(reply).unwrap();
+        assert_eq!(answer.command, "df -h /");
+        assert_eq!(answer.explanation[0].meaning, "root usage");
+        assert_eq!(answer.risk, "read-only");
+        assert!(answer.notes.is_empty());
+    }
+
+    #[test]
+    fn rejects_answers_without_a_command() {
+        for reply in [
+            "I can't help with that.",
+            "{\"command\": \"  \"}",
+            "{\"risk\": 1}",
+        ] {
+            assert!(parse_answer(reply).is_none(), "{:?}", reply);
+        }
+    }
+
+    #[test]
+    fn never_rates_below_the_allowlist() {
+        assert_eq!(rate("read-only", "df -h"), (Risk::ReadOnly, ""));
+        let (risk, note) = rate("read-only", "rm -rf /tmp/cache");
+        assert_eq!(risk, Risk::Mutating);
+        assert!(note.contains("not on the read-only allowlist"));
+        assert_eq!(rate("", "df -h"), (Risk::Mutating, ""));
+        assert_eq!(rate("destructive", "df -h"), (Risk::Destructive, ""));
+    }
+
+    #[test]
+    fn forgets_the_oldest_proposals() {
+        let ids: Vec<u64> = (0..=MAX_PENDING)
+            .map(|i| {
+                store(Proposal {
+                    alias: "web1".to_string(),
+                    command: format!("echo {}", i),
+                    risk: Risk::ReadOnly,
+                })
+            })
+            .collect();
+        assert!(proposal(ids[0]).is_none());
+        assert_eq!(
+            proposal(ids[MAX_PENDING]).unwrap().command,
+            format!("echo {}", MAX_PENDING)
+        );
+    }
+}
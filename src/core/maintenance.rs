@@ -0,0 +1,313 @@
+use crate::db::{get_setting, set_setting, DbPool};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use sqlx::Row;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+
+/// Format used to store timestamps so they compare with SQLite's `datetime('now')`.
+const DB_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+/// Held commands kept for their Run anyway button; older ones are forgotten.
+const MAX_HELD: usize = 50;
+/// Longest maintenance window, in days.
+pub const MAX_WINDOW_DAYS: i64 = 30;
+
+/// An `/exec` held back by a maintenance window, waiting for its Run anyway button.
+#[derive(Debug, Clone)]
+pub struct HeldCommand {
+    pub alias: String,
+    pub cmd: String,
+    pub stream: bool,
+}
+
+/// Held commands, by id. Telegram caps callback data at 64 bytes, so the buttons
+/// only carry the id.
+static HELD: LazyLock<Mutex<HashMap<u64, HeldCommand>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Keeps a command for its Run anyway button and returns its id.
+pub fn hold(command: HeldCommand) -> u64 {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let mut held = HELD.lock().unwrap();
+    held.insert(id, command);
+    if held.len() > MAX_HELD {
+        if let Some(oldest) = held.keys().min().copied() {
+            held.remove(&oldest);
+        }
+    }
+    id
+}
+
+/// Removes held command `id` and returns it, if it is still known.
+pub fn take_held(id: u64) -> Option<HeldCommand> {
+    HELD.lock().unwrap().remove(&id)
+}
+
+/// A maintenance window for a single server.
+#[derive(Debug, Clone)]
+pub struct MaintenanceWindow {
+    pub id: i64,
+    pub alias: String,
+    /// Start time (UTC).
+    pub starts_at: NaiveDateTime,
+    /// End time (UTC).
+    pub ends_at: NaiveDateTime,
+    pub reason: Option<String>,
+}
+
+impl MaintenanceWindow {
+    /// Returns `true` if the window covers the current time.
+    pub fn is_active(&self) -> bool {
+        let now = Utc::now().naive_utc();
+        self.starts_at <= now && now < self.ends_at
+    }
+
+    /// One-line description in the given timezone.
+    pub fn describe(&self, tz: &Tz) -> String {
+        let fmt = |t: &NaiveDateTime| {
+            Utc.from_utc_datetime(t)
+                .with_timezone(tz)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        };
+        let status = if self.is_active() {
+            "🔧 active"
+        } else {
+            "🕒 scheduled"
+        };
+        let mut line = format!(
+            "#{} {} {} → {}: {}",
+            self.id,
+            status,
+            fmt(&self.starts_at),
+            fmt(&self.ends_at),
+            self.alias
+        );
+        if let Some(reason) = &self.reason {
+            line.push_str(&format!(" ({})", reason));
+        }
+        line
+    }
+}
+
+/// Manages maintenance windows stored in SQLite.
+#[derive(Clone)]
+pub struct MaintenanceManager {
+    pool: DbPool,
+}
+
+impl MaintenanceManager {
+    /// Creates a new `MaintenanceManager` with the given database pool.
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    /// Schedules a maintenance window for a server.
+    pub async fn schedule(
+        &self,
+        server_id: &str,
+        starts_at: NaiveDateTime,
+        ends_at: NaiveDateTime,
+        reason: Option<&str>,
+    ) -> Result<i64, sqlx::Error> {
+        // Windows starting right away are announced by the command reply itself
+        let started_now = starts_at <= Utc::now().naive_utc();
+        let result = sqlx::query(
+            "INSERT INTO maintenance_windows (server_id, starts_at, ends_at, reason, started_notified) VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(server_id)
+        .bind(starts_at.format(DB_FORMAT).to_string())
+        .bind(ends_at.format(DB_FORMAT).to_string())
+        .bind(reason)
+        .bind(started_now)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Ends the active window of a server now and cancels its scheduled ones.
+    ///
+    /// Returns the number of windows affected.
+    pub async fn end(&self, server_id: &str) -> Result<u64, sqlx::Error> {
+        let now = Utc::now().naive_utc().format(DB_FORMAT).to_string();
+        let cancelled = sqlx::query(
+            "DELETE FROM maintenance_windows WHERE server_id = ? AND starts_at > ? AND ended_notified = 0",
+        )
+        .bind(server_id)
+        .bind(&now)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        let ended = sqlx::query(
+            "UPDATE maintenance_windows SET ends_at = ? WHERE server_id = ? AND starts_at <= ? AND ends_at > ?",
+        )
+        .bind(&now)
+        .bind(server_id)
+        .bind(&now)
+        .bind(&now)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(cancelled + ended)
+    }
+
+    /// Lists active and upcoming windows, ordered by start time.
+    pub async fn list_upcoming(&self) -> Result<Vec<MaintenanceWindow>, sqlx::Error> {
+        self.query_windows("m.ends_at > datetime('now')").await
+    }
+
+    /// Lists the windows active right now.
+    pub async fn list_active(&self) -> Result<Vec<MaintenanceWindow>, sqlx::Error> {
+        self.query_windows("m.starts_at <= datetime('now') AND m.ends_at > datetime('now')")
+            .await
+    }
+
+    /// Returns the active window of a server, if it is under maintenance.
+    pub async fn active_for(&self, alias: &str) -> Option<MaintenanceWindow> {
+        self.list_active()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .find(|w| w.alias == alias)
+    }
+
+    /// Returns windows that started or ended since the last call, marking them as notified.
+    pub async fn take_transitions(
+        &self,
+    ) -> Result<(Vec<MaintenanceWindow>, Vec<MaintenanceWindow>), sqlx::Error> {
+        let started = self
+            .query_windows(
+                "m.started_notified = 0 AND m.starts_at <= datetime('now') AND m.ends_at > datetime('now')",
+            )
+            .await?;
+        let ended = self
+            .query_windows("m.ended_notified = 0 AND m.ends_at <= datetime('now')")
+            .await?;
+
+        for w in &started {
+            sqlx::query("UPDATE maintenance_windows SET started_notified = 1 WHERE id = ?")
+                .bind(w.id)
+                .execute(&self.pool)
+                .await?;
+        }
+        for w in &ended {
+            sqlx::query(
+                "UPDATE maintenance_windows SET started_notified = 1, ended_notified = 1 WHERE id = ?",
+            )
+            .bind(w.id)
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok((started, ended))
+    }
+
+    /// Whether commands on servers under maintenance need an extra confirmation.
+    pub async fn requires_confirmation(&self) -> bool {
+        get_setting(&self.pool, "maintenance", "confirm")
+            .await
+            .map(|v| v == "on")
+            .unwrap_or(true)
+    }
+
+    /// Enables or disables the extra confirmation.
+    pub async fn set_requires_confirmation(&self, enabled: bool) -> Result<(), sqlx::Error> {
+        let value = if enabled { "on" } else { "off" };
+        set_setting(&self.pool, "maintenance", "confirm", value).await
+    }
+
+    async fn query_windows(&self, filter: &str) -> Result<Vec<MaintenanceWindow>, sqlx::Error> {
+        let sql = format!(
+            "SELECT m.id, s.alias, m.starts_at, m.ends_at, m.reason FROM maintenance_windows m \
+             JOIN servers s ON s.id = m.server_id WHERE {} ORDER BY m.starts_at",
+            filter
+        );
+        let rows = sqlx::query(&sql).fetch_all(&self.pool).await?;
+
+        let windows = rows
+            .iter()
+            .filter_map(|row| {
+                let starts_at: String = row.get("starts_at");
+                let ends_at: String = row.get("ends_at");
+                Some(MaintenanceWindow {
+                    id: row.get("id"),
+                    alias: row.get("alias"),
+                    starts_at: NaiveDateTime::parse_from_str(&starts_at, DB_FORMAT).ok()?,
+                    ends_at: NaiveDateTime::parse_from_str(&ends_at, DB_FORMAT).ok()?,
+                    reason: row.get("reason"),
+                })
+            })
+            .collect();
+        Ok(windows)
+    }
+}
+
+/// Parses a duration such as `30m`, `2h` or `1d`, of at most [`MAX_WINDOW_DAYS`].
+pub fn parse_duration(text: &str) -> Option<Duration> {
+    let text = text.trim().to_lowercase();
+    let (idx, _) = text.char_indices().last()?;
+    let (number, unit) = text.split_at(idx);
+    let number: i64 = number.parse().ok().filter(|n| *n > 0)?;
+    let duration = match unit {
+        "m" => Duration::try_minutes(number),
+        "h" => Duration::try_hours(number),
+        "d" => Duration::try_days(number),
+        _ => None,
+    }?;
+    (duration <= Duration::days(MAX_WINDOW_DAYS)).then_some(duration)
+}
+
+/// Parses a start time (`HH:MM` or `YYYY-MM-DDTHH:MM`) in the given timezone into UTC.
+///
+/// A bare `HH:MM` refers to its next occurrence.
+pub fn parse_start(text: &str, tz: &Tz) -> Option<NaiveDateTime> {
+    let now = Utc::now().with_timezone(tz);
+    let local = if let Ok(dt) = NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M") {
+        dt
+    } else {
+        let time = NaiveTime::parse_from_str(text, "%H:%M").ok()?;
+        let today: NaiveDate = now.date_naive();
+        let candidate = today.and_time(time);
+        if candidate > now.naive_local() {
+            candidate
+        } else {
+            candidate + Duration::days(1)
+        }
+    };
+    tz.from_local_datetime(&local)
+        .earliest()
+        .map(|dt| dt.naive_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_duration, MAX_WINDOW_DAYS};
+    use chrono::Duration;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("30m"), Some(Duration::minutes(30)));
+        assert_eq!(parse_duration(" 2H "), Some(Duration::hours(2)));
+        assert_eq!(parse_duration("1d"), Some(Duration::days(1)));
+        for text in ["0m", "-1h", "2w", "h", ""] {
+            assert_eq!(parse_duration(text), None, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn rejects_durations_over_the_bound() {
+        assert_eq!(
+            parse_duration(&format!("{}d", MAX_WINDOW_DAYS)),
+            Some(Duration::days(MAX_WINDOW_DAYS))
+        );
+        for text in [
+            "31d",
+            "721h",
+            "9223372036854775807m",
+            "9223372036854775807d",
+        ] {
+            assert_eq!(parse_duration(text), None, "{:?}", text);
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use crate::core::discovery::DiscoveryReport;
+use crate::db::DbPool;
+use chrono::NaiveDateTime;
+use sqlx::Row;
+
+/// A metric that can be extracted from the `server_stats` history.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    /// 1-minute load average.
+    Cpu,
+    /// Memory used, as a percentage of total.
+    Memory,
+    /// Root partition usage, as a percentage.
+    Disk,
+}
+
+impl Metric {
+    /// Parses a metric name as typed by the user (`cpu`, `mem`, `disk`).
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "cpu" | "load" => Some(Metric::Cpu),
+            "mem" | "memory" | "ram" => Some(Metric::Memory),
+            "disk" => Some(Metric::Disk),
+            _ => None,
+        }
+    }
+
+    /// Human readable label, including the unit.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Metric::Cpu => "CPU load (1m avg)",
+            Metric::Memory => "Memory used (%)",
+            Metric::Disk => "Disk used on / (%)",
+        }
+    }
+
+    /// Whether the metric is a percentage (fixed 0-100 axis).
+    pub fn is_percentage(&self) -> bool {
+        !matches!(self, Metric::Cpu)
+    }
+
+    /// Extracts the numeric value from the text columns stored by discovery.
+    pub fn extract(&self, cpu_load: &str, memory_usage: &str, disk_usage: &str) -> Option<f64> {
+        match self {
+            Metric::Cpu => parse_load_avg(cpu_load),
+            Metric::Memory => parse_memory_percent(memory_usage),
+            Metric::Disk => parse_disk_percent(disk_usage),
+        }
+    }
+}
+
+/// A single point of a metric series.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub timestamp: NaiveDateTime,
+    pub value: f64,
+}
+
+/// Longest range a chart may cover, in hours (a year).
+pub const MAX_RANGE_HOURS: i64 = 366 * 24;
+
+/// Parses a time range such as `24h` or `7d` into a number of hours.
+///
+/// Ranges longer than [`MAX_RANGE_HOURS`] are rejected.
+pub fn parse_range_hours(range: &str) -> Option<i64> {
+    let range = range.trim().to_lowercase();
+    let hours = if let Some(hours) = range.strip_suffix('h') {
+        hours.parse::<i64>().ok()
+    } else if let Some(days) = range.strip_suffix('d') {
+        days.parse::<i64>().ok()?.checked_mul(24)
+    } else {
+        None
+    };
+    hours.filter(|h| (1..=MAX_RANGE_HOURS).contains(h))
+}
+
+/// Stores the resource figures of a discovery report in `server_stats`.
+pub async fn record_stats(
+    pool: &DbPool,
+    server_id: &str,
+    report: &DiscoveryReport,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO server_stats (server_id, cpu_load, memory_usage, disk_usage) VALUES (?, ?, ?, ?)",
+    )
+    .bind(server_id)
+    .bind(&report.resources.cpu_usage)
+    .bind(&report.resources.memory_usage)
+    .bind(&report.resources.disk_usage)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Loads the history of a metric for a server over the last `hours` hours.
+pub async fn load_history(
+    pool: &DbPool,
+    server_id: &str,
+    metric: Metric,
+    hours: i64,
+) -> Result<Vec<Sample>, sqlx::Error> {
+    let rows = sqlx::query(
+        "SELECT timestamp, cpu_load, memory_usage, disk_usage FROM server_stats \
+         WHERE server_id = ? AND timestamp >= datetime('now', ?) ORDER BY timestamp",
+    )
+    .bind(server_id)
+    .bind(format!("-{} hours", hours))
+    .fetch_all(pool)
+    .await?;
+
+    let samples = rows
+        .iter()
+        .filter_map(|row| {
+            let timestamp: String = row.try_get("timestamp").ok()?;
+            let timestamp = NaiveDateTime::parse_from_str(&timestamp, "%Y-%m-%d %H:%M:%S").ok()?;
+            let cpu: Option<String> = row.get("cpu_load");
+            let mem: Option<String> = row.get("memory_usage");
+            let disk: Option<String> = row.get("disk_usage");
+            let value = metric.extract(
+                cpu.as_deref().unwrap_or_default(),
+                mem.as_deref().unwrap_or_default(),
+                disk.as_deref().unwrap_or_default(),
+            )?;
+            Some(Sample { timestamp, value })
+        })
+        .collect();
+
+    Ok(samples)
+}
+
+/// Parses `Load Avg: 0.15 0.10 0.05` into the 1-minute value.
+fn parse_load_avg(text: &str) -> Option<f64> {
+    text.trim_start_matches("Load Avg:")
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// Parses `free -h` style `1.2Gi / 3.8Gi` into a used percentage.
+fn parse_memory_percent(text: &str) -> Option<f64> {
+    let (used, total) = text.split_once('/')?;
+    let used = parse_size(used)?;
+    let total = parse_size(total)?;
+    if total <= 0.0 {
+        return None;
+    }
+    Some(used / total * 100.0)
+}
+
+/// Parses `df -h` style `12G / 50G (24%)` into the percentage.
+fn parse_disk_percent(text: &str) -> Option<f64> {
+    let start = text.rfind('(')?;
+    let end = text[start..].find('%')? + start;
+    text[start + 1..end].trim().parse().ok()
+}
+
+/// Parses a human readable size (`512Mi`, `1.5G`, `900B`) into bytes.
+fn parse_size(text: &str) -> Option<f64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+        .unwrap_or(text.len());
+    let number: f64 = text[..split].replace(',', ".").parse().ok()?;
+    let multiplier = match text[split..].trim().chars().next() {
+        None | Some('B') => 1.0,
+        Some('K') | Some('k') => 1024.0,
+        Some('M') => 1024.0_f64.powi(2),
+        Some('G') => 1024.0_f64.powi(3),
+        Some('T') => 1024.0_f64.powi(4),
+        Some('P') => 1024.0_f64.powi(5),
+        _ => return None,
+    };
+    Some(number * multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_range_hours, Metric, MAX_RANGE_HOURS};
+
+    #[test]
+    fn parses_metric_names() {
+        assert_eq!(Metric::parse("CPU"), Some(Metric::Cpu));
+        assert_eq!(Metric::parse("ram"), Some(Metric::Memory));
+        assert_eq!(Metric::parse("disk"), Some(Metric::Disk));
+        assert_eq!(Metric::parse("net"), None);
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse_range_hours("24h"), Some(24));
+        assert_eq!(parse_range_hours(" 7D "), Some(168));
+        for range in ["0h", "-1d", "7w", "h", ""] {
+            assert_eq!(parse_range_hours(range), None, "{:?}", range);
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_ranges() {
+        assert_eq!(parse_range_hours("366d"), Some(MAX_RANGE_HOURS));
+        for range in [
+            "367d",
+            "8785h",
+            "9223372036854775807d",
+            "9223372036854775807h",
+        ] {
+            assert_eq!(parse_range_hours(range), None, "{:?}", range);
+        }
+    }
+
+    #[test]
+    fn extracts_values_stored_by_discovery() {
+        let extract = |metric: Metric, text: &str| metric.extract(text, text, text);
+        assert_eq!(extract(Metric::Cpu, "Load Avg: 0.15 0.10 0.05"), Some(0.15));
+        assert_eq!(extract(Metric::Cpu, "2.50 1.00 0.75"), Some(2.5));
+        assert_eq!(extract(Metric::Memory, "512Mi / 2.0Gi"), Some(25.0));
+        assert_eq!(extract(Metric::Memory, "1,5G / 3G"), Some(50.0));
+        assert_eq!(extract(Metric::Memory, "900B / 900B"), Some(100.0));
+        assert_eq!(extract(Metric::Disk, "12G / 50G (24%)"), Some(24.0));
+    }
+
+    #[test]
+    fn rejects_unparsable_values() {
+        let extract = |metric: Metric, text: &str| metric.extract(text, text, text);
+        for (metric, text) in [
+            (Metric::Cpu, "Unknown"),
+            (Metric::Cpu, ""),
+            (Metric::Memory, "Unknown"),
+            (Metric::Memory, "1Gi / 0Gi"),
+            (Metric::Memory, "1Xi / 2Gi"),
+            (Metric::Disk, "12G / 50G"),
+            (Metric::Disk, "(n/a%)"),
+        ] {
+            assert_eq!(extract(metric, text), None, "{:?} {:?}", metric, text);
+        }
+    }
+}
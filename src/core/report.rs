@@ -0,0 +1,299 @@
+use crate::ai::client::AiClient;
+use crate::ai::injection;
+use crate::ai::prompts::{self, Vars};
+use crate::ai::usage::CallContext;
+use crate::core::session::truncate_output;
+use crate::db::{get_setting, set_setting, DbPool};
+
+/// Settings scope of the server of each chat's latest session, by chat id.
+const SESSION_SCOPE: &str = "session_server";
+/// Longest message or command output quoted in the timeline, in bytes.
+const MAX_ENTRY_BYTES: usize = 2000;
+/// Longest timeline sent to the AI, in bytes.
+const MAX_TIMELINE_BYTES: usize = 60000;
+
+/// A post-mortem of a session, as stored in the `incident_reports` table.
+#[derive(Debug, Clone)]
+pub struct IncidentReport {
+    pub id: i64,
+    pub server_alias: String,
+    /// First and last message of the session, in UTC.
+    pub started_at: String,
+    pub ended_at: String,
+    /// The report as a Markdown document.
+    pub content: String,
+}
+
+type ReportRow = (i64, String, String, String, String);
+
+impl From<ReportRow> for IncidentReport {
+    fn from((id, server_alias, started_at, ended_at, content): ReportRow) -> Self {
+        IncidentReport {
+            id,
+            server_alias,
+            started_at,
+            ended_at,
+            content,
+        }
+    }
+}
+
+impl IncidentReport {
+    /// Name of the Markdown attachment, e.g. `incident-3-web1.md`.
+    pub fn file_name(&self) -> String {
+        let alias: String = self
+            .server_alias
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        format!("incident-{}-{}.md", self.id, alias)
+    }
+
+    /// One line describing the report, for captions and lists.
+    pub fn describe(&self) -> String {
+        format!(
+            "#{} {} ({} – {} UTC)",
+            self.id, self.server_alias, self.started_at, self.ended_at
+        )
+    }
+}
+
+/// Remembers which server the chat's latest session is about, for its report.
+pub async fn remember_session(pool: &DbPool, chat_id: i64, alias: &str) -> Result<(), sqlx::Error> {
+    set_setting(pool, SESSION_SCOPE, &chat_id.to_string(), alias).await
+}
+
+/// The record of the chat's latest session as `(timestamp, source, text)`, oldest
+/// first: its messages from `chat_history` and the commands logged in `audit_logs`
+/// for the chat meanwhile.
+///
+/// A session starts with its system prompt, so its messages are the ones after
+/// the chat's latest system message.
+async fn timeline(
+    pool: &DbPool,
+    chat_id: i64,
+) -> Result<Vec<(String, String, String)>, sqlx::Error> {
+    let start: Option<(i64, String)> = sqlx::query_as(
+        "SELECT id, timestamp FROM chat_history WHERE chat_id = ? AND role = 'system' ORDER BY id DESC LIMIT 1",
+    )
+    .bind(chat_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some((start_id, started_at)) = start else {
+        return Ok(Vec::new());
+    };
+
+    let messages: Vec<(String, String, String)> = sqlx::query_as(
+        "SELECT timestamp, role, content FROM chat_history WHERE chat_id = ? AND id > ? ORDER BY id",
+    )
+    .bind(chat_id)
+    .bind(start_id)
+    .fetch_all(pool)
+    .await?;
+    let ended_at = messages
+        .last()
+        .map(|m| m.0.clone())
+        .unwrap_or_else(|| started_at.clone());
+
+    // Commands from the chat carry its id in the logged text, investigation steps in user_id
+    let commands: Vec<(String, String, Option<String>)> = sqlx::query_as(
+        "SELECT timestamp, command, output FROM audit_logs
+         WHERE timestamp >= ? AND timestamp <= ? AND (user_id = ? OR command LIKE ?)
+         ORDER BY id",
+    )
+    .bind(&started_at)
+    .bind(&ended_at)
+    .bind(chat_id)
+    .bind(format!("%(User: {})", chat_id))
+    .fetch_all(pool)
+    .await?;
+
+    let mut entries: Vec<(String, String, String)> = messages
+        .into_iter()
+        .map(|(timestamp, role, content)| {
+            (timestamp, role, truncate_output(&content, MAX_ENTRY_BYTES))
+        })
+        .collect();
+    for (timestamp, command, output) in commands {
+        let text = match output.filter(|o| !o.trim().is_empty()) {
+            Some(output) => format!("{}\n{}", command, truncate_output(&output, MAX_ENTRY_BYTES)),
+            None => command,
+        };
+        entries.push((timestamp, "command".to_string(), text));
+    }
+    // Stable, so messages stay ahead of commands logged in the same second
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(entries)
+}
+
+/// Formats the timeline as one `[timestamp] source: text` entry per paragraph,
+/// keeping the last [`MAX_TIMELINE_BYTES`] of long sessions, where the conclusions are.
+fn format_timeline(entries: &[(String, String, String)]) -> String {
+    let record = entries
+        .iter()
+        .map(|(timestamp, source, text)| format!("[{}] {}: {}", timestamp, source, text))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    if record.len() <= MAX_TIMELINE_BYTES {
+        return record;
+    }
+    let mut start = record.len() - MAX_TIMELINE_BYTES;
+    while !record.is_char_boundary(start) {
+        start += 1;
+    }
+    format!("(earlier entries omitted)\n{}", &record[start..])
+}
+
+/// Has the AI write a post-mortem of the chat's latest session and stores it.
+pub async fn generate(
+    pool: &DbPool,
+    ai_client: &AiClient,
+    chat_id: i64,
+) -> Result<IncidentReport, String> {
+    let alias = get_setting(pool, SESSION_SCOPE, &chat_id.to_string())
+        .await
+        .filter(|a| !a.is_empty())
+        .ok_or("No session to report on. Start one with /ask first.")?;
+    let entries = timeline(pool, chat_id)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+    let (Some(first), Some(last)) = (entries.first(), entries.last()) else {
+        return Err("The last session has no messages to report on.".to_string());
+    };
+    let (started_at, ended_at) = (first.0.clone(), last.0.clone());
+
+    let (record, _) = injection::wrap("the session record", &format_timeline(&entries));
+
+    let provider = ai_client.provider_name().await;
+    let vars = Vars::server(pool, &alias)
+        .await
+        .set("started", started_at.as_str())
+        .set("ended", ended_at.as_str())
+        .set("timeline", record);
+    let prompt = prompts::render(pool, "incident_report", &provider, &vars).await;
+    let body = ai_client
+        .ask(&prompt, &CallContext::chat(chat_id).purpose("report"))
+        .await
+        .map_err(|e| e.user_message())?;
+
+    let content = format!(
+        "# Incident report: {}\n\n- Server: {}\n- Session: {} – {} UTC\n- Written by: {}\n\n{}\n",
+        alias,
+        alias,
+        started_at,
+        ended_at,
+        ai_client.get_provider_info().await,
+        body.trim()
+    );
+    let row: ReportRow = sqlx::query_as(
+        "INSERT INTO incident_reports (chat_id, server_alias, started_at, ended_at, content)
+         VALUES (?, ?, ?, ?, ?)
+         RETURNING id, server_alias, started_at, ended_at, content",
+    )
+    .bind(chat_id)
+    .bind(&alias)
+    .bind(&started_at)
+    .bind(&ended_at)
+    .bind(&content)
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Failed to save the report: {}", e))?;
+    Ok(row.into())
+}
+
+/// The chat's latest reports, newest first.
+pub async fn list(pool: &DbPool, chat_id: i64) -> Result<Vec<IncidentReport>, sqlx::Error> {
+    let rows: Vec<ReportRow> = sqlx::query_as(
+        "SELECT id, server_alias, started_at, ended_at, content FROM incident_reports
+         WHERE chat_id = ? ORDER BY id DESC LIMIT 10",
+    )
+    .bind(chat_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(IncidentReport::from).collect())
+}
+
+/// Report `id` of the chat, if it exists.
+pub async fn get(
+    pool: &DbPool,
+    chat_id: i64,
+    id: i64,
+) -> Result<Option<IncidentReport>, sqlx::Error> {
+    let row: Option<ReportRow> = sqlx::query_as(
+        "SELECT id, server_alias, started_at, ended_at, content FROM incident_reports
+         WHERE chat_id = ? AND id = ?",
+    )
+    .bind(chat_id)
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(IncidentReport::from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_timeline, IncidentReport, MAX_TIMELINE_BYTES};
+
+    fn entry(timestamp: &str, source: &str, text: &str) -> (String, String, String) {
+        (timestamp.to_string(), source.to_string(), text.to_string())
+    }
+
+    #[test]
+    fn names_reports_after_their_server() {
+        let report = IncidentReport {
+            id: 3,
+            server_alias: "web 1/prod".to_string(),
+            started_at: "2026-10-18 09:00:00".to_string(),
+            ended_at: "2026-10-18 09:30:00".to_string(),
+            content: String::new(),
+        };
+        assert_eq!(report.file_name(), "incident-3-web_1_prod.md");
+        assert_eq!(
+            report.describe(),
+            "#3 web 1/prod (2026-10-18 09:00:00 – 2026-10-18 09:30:00 UTC)"
+        );
+    }
+
+    #[test]
+    fn formats_the_timeline_in_order() {
+        let entries = [
+            entry("2026-10-18 09:00:00", "user", "nginx is down"),
+            entry(
+                "2026-10-18 09:00:05",
+                "command",
+                "systemctl status nginx\nfailed",
+            ),
+        ];
+        assert_eq!(
+            format_timeline(&entries),
+            "[2026-10-18 09:00:00] user: nginx is down\n\n\
+             [2026-10-18 09:00:05] command: systemctl status nginx\nfailed"
+        );
+    }
+
+    #[test]
+    fn keeps_the_end_of_long_timelines() {
+        let entries = [
+            entry(
+                "2026-10-18 09:00:00",
+                "user",
+                &"é".repeat(MAX_TIMELINE_BYTES),
+            ),
+            entry(
+                "2026-10-18 10:00:00",
+                "assistant",
+                "Fixed by restarting nginx",
+            ),
+        ];
+        let record = format_timeline(&entries);
+        assert!(record.starts_with("(earlier entries omitted)\n"));
+        assert!(record.ends_with("assistant: Fixed by restarting nginx"));
+        assert!(record.len() <= MAX_TIMELINE_BYTES + "(earlier entries omitted)\n".len());
+    }
+}
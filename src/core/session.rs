@@ -1,20 +1,107 @@
 use crate::ai::client::AiClient;
-use crate::ai::models::ChatMessage;
+use crate::ai::injection;
+use crate::ai::models::{ChatMessage, ChatReply, TokenCount};
+use crate::ai::prompts::{self, Vars};
+use crate::ai::tools::{specs, Tool};
+use crate::ai::usage::CallContext;
+use crate::core::agent::STEP_TIMEOUT;
 use crate::core::server_manager::ServerManager;
 use crate::executor::ssh::SshExecutor;
-use crate::models::CommandResponse;
+use crate::models::{CommandResponse, ManagedServer, StreamEvent};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+/// Maximum number of consecutive rounds of read-only tool calls per user message.
+const MAX_TOOL_ROUNDS: usize = 5;
+/// Tokens of the context window kept free for the model's reply.
+const REPLY_RESERVE: usize = 4096;
+/// Upper bound on the history sent per turn, even for models with huge windows.
+const MAX_CONTEXT_BUDGET: usize = 100_000;
+/// Most recent messages that are never folded into a summary.
+const KEEP_RECENT: usize = 6;
+/// Longest command or tool output kept in the history, in bytes.
+const MAX_OUTPUT_BYTES: usize = 8000;
 
 #[derive(Debug, Clone)]
 pub struct Session {
+    /// Identifies the session in the usage records.
+    pub id: String,
     pub server_alias: String,
     pub history: Vec<ChatMessage>,
+    /// Number of earlier messages replaced by a summary to fit the context window.
+    pub summarised: usize,
+    /// What looked like instructions in the outputs the AI has not answered yet.
+    pub suspicious: Option<String>,
+}
+
+/// Commands proposed together by the AI, approved as a batch from one message.
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub id: u64,
+    pub alias: String,
+    pub steps: Vec<PlanStep>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlanStep {
+    pub command: String,
+    /// Steps start selected; the user may untick some before running the plan.
+    pub selected: bool,
+}
+
+impl Plan {
+    /// One toggle button per step, then "Run selected" and "Skip".
+    pub fn buttons(&self) -> Vec<(String, String)> {
+        let mut buttons: Vec<(String, String)> = self
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| {
+                let mark = if step.selected { "☑️" } else { "⬜" };
+                (
+                    format!("{} {}. {}", mark, i + 1, step.command),
+                    format!("plan:{}:t{}", self.id, i),
+                )
+            })
+            .collect();
+        let selected = self.steps.iter().filter(|s| s.selected).count();
+        buttons.push((
+            format!("▶️ Run selected ({}/{})", selected, self.steps.len()),
+            format!("plan:{}:run", self.id),
+        ));
+        buttons.push(("❌ Skip".to_string(), format!("plan:{}:skip", self.id)));
+        buttons
+    }
+}
+
+static NEXT_PLAN_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Token usage of a session against the context window of the current model.
+pub struct ContextUsage {
+    pub provider: String,
+    pub window: usize,
+    pub budget: usize,
+    /// Present while a session is active.
+    pub session: Option<SessionUsage>,
+}
+
+pub struct SessionUsage {
+    pub alias: String,
+    pub messages: usize,
+    pub summarised: usize,
+    pub system_tokens: usize,
+    pub history_tokens: usize,
+    /// Whether the counts come from the model's own tokenizer.
+    pub exact: bool,
 }
 
 #[derive(Clone)]
 pub struct SessionManager {
     sessions: Arc<Mutex<HashMap<i64, Session>>>,
+    /// The plan waiting for approval in each chat; a newer plan replaces it.
+    plans: Arc<Mutex<HashMap<i64, Plan>>>,
     ai_client: Arc<AiClient>,
     pool: crate::db::DbPool,
 }
@@ -23,23 +110,28 @@ impl SessionManager {
     pub async fn new(pool: crate::db::DbPool) -> Self {
         Self {
             sessions: Arc::new(Mutex::new(HashMap::new())),
+            plans: Arc::new(Mutex::new(HashMap::new())),
             ai_client: Arc::new(AiClient::new(pool.clone()).await),
             pool,
         }
     }
 
     pub async fn start_session(&self, chat_id: i64, alias: String) {
-        let system_prompt = format!(
-            include_str!("../../templates/prompts/server_assistant.html"),
-            alias
-        );
+        let vars = Vars::server(&self.pool, &alias).await;
+        let system_prompt = self.render_prompt("server_assistant", &vars).await;
 
         let session = Session {
-            server_alias: alias,
+            id: uuid::Uuid::new_v4().to_string(),
+            server_alias: alias.clone(),
             history: vec![ChatMessage::new("system", &system_prompt)],
+            summarised: 0,
+            suspicious: None,
         };
 
         self.sessions.lock().unwrap().insert(chat_id, session);
+        if let Err(e) = crate::core::report::remember_session(&self.pool, chat_id, &alias).await {
+            eprintln!("Failed to save the session's server: {}", e);
+        }
 
         // Persist system message
         self.add_message(chat_id, "system", &system_prompt).await;
@@ -81,87 +173,359 @@ impl SessionManager {
     }
 
     pub async fn process_user_input(&self, chat_id: i64, input: &str) -> CommandResponse {
+        self.respond(chat_id, input, None).await
+    }
+
+    /// Like `process_user_input`, but streams the reply into the chat as it is generated.
+    pub fn process_user_input_streaming(&self, chat_id: i64, input: String) -> CommandResponse {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let manager = self.clone();
+        tokio::spawn(async move {
+            let response = manager.respond(chat_id, &input, Some(&tx)).await;
+            let _ = tx.send(StreamEvent::Done(response));
+        });
+        CommandResponse::AiStream(Arc::new(tokio::sync::Mutex::new(rx)))
+    }
+
+    async fn respond(
+        &self,
+        chat_id: i64,
+        input: &str,
+        events: Option<&UnboundedSender<StreamEvent>>,
+    ) -> CommandResponse {
         // Add user message
         self.add_message(chat_id, "user", input).await;
 
-        // Get history
-        // Get history and inject reminder into the last user message
-        let history = {
-            let guard = self.sessions.lock().unwrap();
-            if let Some(session) = guard.get(&chat_id) {
-                let mut history = session.history.clone();
-
-                // Inject reminder directly into the last user message for maximum adherence
-                if let Some(last_msg) = history.last_mut() {
-                    if last_msg.role == "user" {
-                        last_msg
-                            .content
-                            .push_str(include_str!("../../templates/prompts/ssh_reminder.txt"));
+        let alias = match self.get_alias(chat_id) {
+            Some(a) => a,
+            None => return CommandResponse::Text("No active session.".to_string()),
+        };
+        let tools = specs(&alias);
+        let context = self.call_context(chat_id);
+        let mut auto_ran: Vec<String> = Vec::new();
+        let mut fallback_notice: Option<String> = None;
+        let mut redacted = 0;
+        let vars = Vars::server(&self.pool, &alias).await;
+        let reminder = self.render_prompt("ssh_reminder", &vars).await;
+
+        // Read-only tools run right away and their output goes back to the model,
+        // until it answers or proposes a command that needs approval
+        for round in 0..MAX_TOOL_ROUNDS {
+            self.fit_context(chat_id, &vars).await;
+            let history = match self.history_with_reminder(chat_id, &reminder) {
+                Some(h) => h,
+                None => return CommandResponse::Text("No active session.".to_string()),
+            };
+
+            // Call AI
+            let reply = match events {
+                Some(tx) => {
+                    if round > 0 {
+                        let _ = tx.send(StreamEvent::Reset);
                     }
+                    let on_delta = |text: &str| {
+                        let _ = tx.send(StreamEvent::Delta(text.to_string()));
+                    };
+                    self.ai_client
+                        .chat_stream(&history, &tools, &on_delta, &context)
+                        .await
                 }
-                history
+                None => {
+                    self.ai_client
+                        .chat_with_tools(&history, &tools, &context)
+                        .await
+                }
+            };
+            let reply = match reply {
+                Ok((reply, notice)) => {
+                    fallback_notice = notice.or(fallback_notice);
+                    redacted += reply.redacted;
+                    reply
+                }
+                Err(e) => return CommandResponse::Text(e.user_message()),
+            };
+            // Commands proposed right after suspicious output may have been planted there
+            let injection = self.take_suspicious(chat_id);
+
+            // Some models still answer with the text protocol even when offered tools
+            let reply = if reply.tool_calls.is_empty() {
+                ChatReply::from_text(&reply.content)
             } else {
-                return CommandResponse::Text("No active session.".to_string());
+                reply
+            };
+            let (calls, invalid): (Vec<_>, Vec<_>) = reply
+                .tool_calls
+                .iter()
+                .map(Tool::from_call)
+                .partition(Result::is_ok);
+            let (read_only, commands): (Vec<Tool>, Vec<Tool>) = calls
+                .into_iter()
+                .filter_map(Result::ok)
+                .partition(Tool::is_read_only);
+            // The flag is only cleared once shown with a command approval; the planted
+            // text stays in context until then
+            if commands.is_empty() {
+                self.flag_suspicious(chat_id, injection.clone());
+            }
+
+            // Keep the tool calls in the history so the model remembers them
+            let mut recorded = reply.content.clone();
+            for tool in &commands {
+                recorded.push_str(&format!("\nRUN: {}", tool.describe()));
+            }
+            for tool in &read_only {
+                recorded.push_str(&format!("\nCALL: {}", tool.describe()));
+            }
+            self.add_message(chat_id, "assistant", recorded.trim())
+                .await;
+
+            for error in invalid.into_iter().filter_map(Result::err) {
+                self.add_message(chat_id, "user", &format!("Tool error: {}", error))
+                    .await;
+            }
+            if !read_only.is_empty() {
+                let manager = ServerManager::new(self.pool.clone());
+                let server = match manager.get_server(&alias).await {
+                    Ok(Some(server)) => server,
+                    Ok(None) => return CommandResponse::Text("Server not found.".to_string()),
+                    Err(e) => return CommandResponse::Text(format!("DB Error: {}", e)),
+                };
+                for tool in &read_only {
+                    let output = truncate_output(&run_tool(tool, &server).await, MAX_OUTPUT_BYTES);
+                    self.add_untrusted(chat_id, &tool.describe(), &output).await;
+                    auto_ran.push(tool.describe());
+                }
+            }
+
+            let mut ran_note = match &fallback_notice {
+                Some(notice) => format!("<i>↪️ {}</i>\n\n", escape_html(notice)),
+                None => String::new(),
+            };
+            if !auto_ran.is_empty() {
+                ran_note.push_str(&format!(
+                    "<i>🔎 Ran: {}</i>\n\n",
+                    escape_html(&auto_ran.join(", "))
+                ));
+            }
+            if let Some(note) = crate::ai::redact::note(redacted) {
+                ran_note.push_str(&format!("<i>{}</i>\n\n", note));
+            }
+
+            if !commands.is_empty() {
+                if let Some(found) = injection {
+                    ran_note.push_str(&format!("{}\n\n", injection::warning(&escape_html(&found))));
+                }
+                let plan =
+                    (commands.len() > 1).then(|| self.store_plan(chat_id, &alias, &commands));
+                return approval_request(&alias, &reply.content, &commands, &ran_note, plan);
+            }
+            if read_only.is_empty() && reply.tool_calls.is_empty() {
+                return CommandResponse::Html(format!(
+                    "{}{}",
+                    ran_note,
+                    markdown_to_telegram_html(&reply.content)
+                ));
             }
+        }
+
+        CommandResponse::Text(format!(
+            "Stopped after {} rounds of tool calls. Ask a follow-up question to continue.",
+            MAX_TOOL_ROUNDS
+        ))
+    }
+
+    /// Attributes AI calls to the chat and its current session.
+    fn call_context(&self, chat_id: i64) -> CallContext {
+        CallContext {
+            chat_id: Some(chat_id),
+            session_id: self
+                .sessions
+                .lock()
+                .unwrap()
+                .get(&chat_id)
+                .map(|s| s.id.clone()),
+            purpose: Some("chat"),
+        }
+    }
+
+    /// Renders prompt template `name` for the active provider.
+    async fn render_prompt(&self, name: &str, vars: &Vars) -> String {
+        let provider = self.ai_client.provider_name().await;
+        prompts::render(&self.pool, name, &provider, vars).await
+    }
+
+    /// Returns the session history with the SSH reminder appended to the last user message.
+    fn history_with_reminder(&self, chat_id: i64, reminder: &str) -> Option<Vec<ChatMessage>> {
+        let guard = self.sessions.lock().unwrap();
+        let mut history = guard.get(&chat_id)?.history.clone();
+
+        // Inject reminder directly into the last user message for maximum adherence
+        if let Some(last_msg) = history.last_mut() {
+            if last_msg.role == "user" {
+                last_msg.content.push_str("\n\n");
+                last_msg.content.push_str(reminder.trim_start());
+            }
+        }
+        Some(history)
+    }
+
+    /// Keeps the history within the token budget of the current model.
+    ///
+    /// Older messages between the system prompt and the most recent ones are replaced
+    /// by a summary written by the model. If that fails, or the recent messages alone
+    /// are still too large, the oldest messages are dropped.
+    async fn fit_context(&self, chat_id: i64, vars: &Vars) {
+        let budget = context_budget(self.ai_client.context_window().await);
+        let history = match self.sessions.lock().unwrap().get(&chat_id) {
+            Some(session) => session.history.clone(),
+            None => return,
         };
+        // One call over the whole history, since loading a tokenizer isn't free
+        let text: String = history.iter().map(|m| m.content.as_str()).collect();
+        let used = match self.ai_client.count_tokens(&text).await {
+            Ok(count) => count.tokens,
+            Err(_) => text.len() / 4,
+        };
+        if used + 4 * history.len() <= budget {
+            return;
+        }
 
-        // Call AI
-        match self.ai_client.chat(&history).await {
-            Ok(response) => {
-                // Add AI response to history
-                self.add_message(chat_id, "assistant", &response).await;
-
-                // We handle cases where the AI provides explanation before the command.
-                if let Some(idx) = response.find("RUN:") {
-                    let (message_part, cmd_part) = response.split_at(idx);
-                    let cmd_raw = cmd_part.trim_start_matches("RUN:").trim();
-                    // Strip HTML tags from the command (AI sometimes wraps in <code> etc.)
-                    let cmd = cmd_raw
-                        .replace("<code>", "")
-                        .replace("</code>", "")
-                        .replace("<b>", "")
-                        .replace("</b>", "")
-                        .replace("<i>", "")
-                        .replace("</i>", "")
-                        .trim()
-                        .to_string();
-
-                    // Only process checks if a command actually exists
-                    if !cmd.is_empty() {
-                        use base64::prelude::*;
-                        let encoded_cmd = BASE64_STANDARD.encode(&cmd);
-
-                        // Determine the message to show above the buttons
-                        let title = if message_part.trim().is_empty() {
-                            format!("AI suggests running: <code>{}</code>", escape_html(&cmd))
-                        } else {
-                            format!(
-                                "{}\n\nSuggested command: <code>{}</code>",
-                                markdown_to_telegram_html(message_part.trim()),
-                                escape_html(&cmd)
-                            )
-                        };
-
-                        CommandResponse::InteractiveList {
-                            title,
-                            options: vec!["✅ Run".to_string(), "❌ Skip".to_string()],
-                            callback_prefix: format!("tool_run:{}:", encoded_cmd),
-                        }
-                    } else {
-                        CommandResponse::Html(markdown_to_telegram_html(&response))
-                    }
-                } else {
-                    CommandResponse::Html(markdown_to_telegram_html(&response))
+        let end = history.len().saturating_sub(KEEP_RECENT);
+        if end > 1 {
+            let older = &history[1..end];
+            let transcript = older
+                .iter()
+                .map(|m| format!("{}: {}", m.role, m.content))
+                .collect::<Vec<_>>()
+                .join("\n\n");
+            // The transcript must fit in a single request itself (about 3 bytes per token)
+            let transcript = truncate_output(&transcript, budget.saturating_mul(3) / 2);
+            let vars = vars.clone().set("transcript", transcript);
+            let prompt = self.render_prompt("summarise_history", &vars).await;
+            let note = match self
+                .ai_client
+                .ask(&prompt, &self.call_context(chat_id).purpose("summary"))
+                .await
+            {
+                Ok(summary) if !summary.trim().is_empty() => format!(
+                    "Summary of the {} earlier messages of this conversation:\n{}",
+                    older.len(),
+                    summary.trim()
+                ),
+                Ok(_) | Err(_) => format!(
+                    "{} earlier messages were removed to fit the context window.",
+                    older.len()
+                ),
+            };
+
+            let mut guard = self.sessions.lock().unwrap();
+            if let Some(session) = guard.get_mut(&chat_id) {
+                if session.history.len() >= end {
+                    session
+                        .history
+                        .splice(1..end, [ChatMessage::new("system", note)]);
+                    session.summarised += end - 1;
                 }
             }
-            Err(e) => CommandResponse::Text(format!("AI Error: {}", e)),
         }
+
+        // Recent messages alone may still be too large: drop the oldest of them
+        let history = match self.sessions.lock().unwrap().get(&chat_id) {
+            Some(session) => session.history.clone(),
+            None => return,
+        };
+        let counts = count_messages(&history);
+        let mut total: usize = counts.iter().sum();
+        let mut dropped = 0;
+        while total > budget && 2 + dropped < history.len() {
+            total -= counts[1 + dropped];
+            dropped += 1;
+        }
+        if dropped > 0 {
+            let mut guard = self.sessions.lock().unwrap();
+            if let Some(session) = guard.get_mut(&chat_id) {
+                if session.history.len() > dropped + 1 {
+                    session.history.drain(1..1 + dropped);
+                    session.summarised += dropped;
+                }
+            }
+        }
+    }
+
+    /// Reports how much of the context window the session in `chat_id` uses.
+    pub async fn context_usage(&self, chat_id: i64) -> ContextUsage {
+        let window = self.ai_client.context_window().await;
+        let session = self.sessions.lock().unwrap().get(&chat_id).cloned();
+
+        let session = match session {
+            Some(session) => {
+                // Two calls rather than one per message, as some providers count remotely
+                let count = |text: String| async move {
+                    match self.ai_client.count_tokens(&text).await {
+                        Ok(count) => count,
+                        Err(_) => TokenCount::estimate(&text),
+                    }
+                };
+                let overhead = 4 * session.history.len();
+                let system = count(
+                    session
+                        .history
+                        .iter()
+                        .filter(|m| m.role == "system")
+                        .map(|m| m.content.as_str())
+                        .collect(),
+                )
+                .await;
+                let total =
+                    count(session.history.iter().map(|m| m.content.as_str()).collect()).await;
+                Some(SessionUsage {
+                    alias: session.server_alias,
+                    messages: session.history.len(),
+                    summarised: session.summarised,
+                    system_tokens: system.tokens,
+                    history_tokens: (total.tokens + overhead).saturating_sub(system.tokens),
+                    exact: system.exact && total.exact,
+                })
+            }
+            None => None,
+        };
+
+        ContextUsage {
+            provider: self.ai_client.get_provider_info().await,
+            window,
+            budget: context_budget(window),
+            session,
+        }
+    }
+
+    /// Adds output read from the server to the history as an untrusted block, and
+    /// remembers whether it looked like an attempt to instruct the AI.
+    async fn add_untrusted(&self, chat_id: i64, source: &str, output: &str) {
+        let (content, suspicious) = injection::wrap(source, output);
+        self.flag_suspicious(chat_id, suspicious);
+        self.add_message(chat_id, "user", &content).await;
+    }
+
+    /// Records instruction-like text found in output the AI is about to read.
+    fn flag_suspicious(&self, chat_id: i64, found: Option<String>) {
+        if let Some(session) = self.sessions.lock().unwrap().get_mut(&chat_id) {
+            session.suspicious = session.suspicious.take().or(found);
+        }
+    }
+
+    /// Clears and returns what was flagged since commands were last proposed.
+    fn take_suspicious(&self, chat_id: i64) -> Option<String> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .get_mut(&chat_id)
+            .and_then(|s| s.suspicious.take())
     }
 
     // Manual tool output injection
-    pub async fn add_tool_output(&self, chat_id: i64, output: &str) {
-        let content = format!("Command Output:\n{}", output);
-        self.add_message(chat_id, "user", &content).await; // Treat tool output as user message for simplicity (context)
+    pub async fn add_tool_output(&self, chat_id: i64, command: &str, output: &str) {
+        let output = truncate_output(output, MAX_OUTPUT_BYTES);
+        self.add_untrusted(chat_id, command, &output).await;
     }
 
     pub async fn execute_tool_command(&self, chat_id: i64, cmd: &str) -> CommandResponse {
@@ -180,12 +544,101 @@ impl SessionManager {
             Err(e) => format!("DB Error: {}", e),
         };
 
-        self.add_tool_output(chat_id, &output).await;
+        self.add_tool_output(chat_id, cmd, &output).await;
 
         self.process_user_input(chat_id, "Command executed. Analyze results.")
             .await
     }
 
+    /// Keeps the commands proposed in one reply as the chat's pending plan.
+    fn store_plan(&self, chat_id: i64, alias: &str, commands: &[Tool]) -> Plan {
+        let plan = Plan {
+            id: NEXT_PLAN_ID.fetch_add(1, Ordering::Relaxed),
+            alias: alias.to_string(),
+            steps: commands
+                .iter()
+                .filter_map(|tool| match tool {
+                    Tool::RunCommand(c) => Some(PlanStep {
+                        command: c.command.clone(),
+                        selected: true,
+                    }),
+                    _ => None,
+                })
+                .collect(),
+        };
+        self.plans.lock().unwrap().insert(chat_id, plan.clone());
+        plan
+    }
+
+    /// Returns the pending plan `id` of the chat, if it is still pending.
+    pub fn pending_plan(&self, chat_id: i64, id: u64) -> Option<Plan> {
+        self.plans
+            .lock()
+            .unwrap()
+            .get(&chat_id)
+            .filter(|p| p.id == id)
+            .cloned()
+    }
+
+    /// Selects or unselects a step of the pending plan and returns the updated plan.
+    pub fn toggle_plan_step(&self, chat_id: i64, id: u64, index: usize) -> Option<Plan> {
+        let mut plans = self.plans.lock().unwrap();
+        let plan = plans.get_mut(&chat_id).filter(|p| p.id == id)?;
+        let step = plan.steps.get_mut(index)?;
+        step.selected = !step.selected;
+        Some(plan.clone())
+    }
+
+    /// Removes the pending plan `id` of the chat, returning it if it was still pending.
+    pub fn take_plan(&self, chat_id: i64, id: u64) -> Option<Plan> {
+        let mut plans = self.plans.lock().unwrap();
+        if plans.get(&chat_id).is_some_and(|p| p.id == id) {
+            plans.remove(&chat_id)
+        } else {
+            None
+        }
+    }
+
+    /// Runs the selected steps of `plan` in order and hands all their outputs
+    /// back to the AI in a single turn.
+    pub async fn execute_plan(&self, chat_id: i64, plan: Plan) -> CommandResponse {
+        let manager = ServerManager::new(self.pool.clone());
+        let server = match manager.get_server(&plan.alias).await {
+            Ok(Some(server)) => server,
+            Ok(None) => return CommandResponse::Text("Server not found.".to_string()),
+            Err(e) => return CommandResponse::Text(format!("DB Error: {}", e)),
+        };
+
+        let selected = plan.steps.iter().filter(|s| s.selected).count().max(1);
+        // Each output gets an equal share of the space a single output may take
+        let share = (MAX_OUTPUT_BYTES * 2 / selected).min(MAX_OUTPUT_BYTES);
+        let mut content = "Plan output:".to_string();
+        let mut suspicious = None;
+        for (i, step) in plan.steps.iter().enumerate() {
+            if !step.selected {
+                content.push_str(&format!(
+                    "\n\nStep {}: {}\n(skipped by the user)",
+                    i + 1,
+                    step.command
+                ));
+                continue;
+            }
+            let output = match SshExecutor::execute(&server, &step.command) {
+                Ok(out) if out.trim().is_empty() => "(no output)".to_string(),
+                Ok(out) => out,
+                Err(e) => format!("Error: {}", e),
+            };
+            let (wrapped, found) = injection::wrap(&step.command, &truncate_output(&output, share));
+            suspicious = suspicious.or(found);
+            content.push_str(&format!("\n\nStep {}: {}", i + 1, wrapped));
+        }
+        self.flag_suspicious(chat_id, suspicious);
+        self.add_message(chat_id, "user", &content).await;
+
+        self.process_user_input(chat_id, "Plan executed. Analyze the results of all steps.")
+            .await
+    }
+
     pub async fn reload_ai_config(&self) {
         if let Err(e) = self.ai_client.reload_config().await {
             eprintln!("Failed to reload AI config: {}", e);
@@ -193,8 +646,146 @@ impl SessionManager {
     }
 }
 
+/// Estimated tokens of each message, including a small per-message overhead.
+///
+/// Counted locally: the counts only decide which messages to drop, and remote
+/// tokenizers would cost a request per message.
+fn count_messages(messages: &[ChatMessage]) -> Vec<usize> {
+    messages
+        .iter()
+        .map(|m| TokenCount::estimate(&m.content).tokens + 4)
+        .collect()
+}
+
+/// Tokens of history that may be sent to a model with the given context window.
+fn context_budget(window: usize) -> usize {
+    window
+        .saturating_sub(REPLY_RESERVE.min(window / 4))
+        .min(MAX_CONTEXT_BUDGET)
+}
+
+/// Runs a read-only tool off the async runtime, giving up after [`STEP_TIMEOUT`].
+///
+/// A hung call is abandoned; its SSH session ends on its own read timeout.
+async fn run_tool(tool: &Tool, server: &ManagedServer) -> String {
+    let (tool, server) = (tool.clone(), server.clone());
+    let handle = tokio::task::spawn_blocking(move || tool.execute(&server, Some(STEP_TIMEOUT)));
+    match tokio::time::timeout(STEP_TIMEOUT, handle).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => format!("Error: tool call failed: {}", e),
+        Err(_) => format!("Error: timed out after {} seconds", STEP_TIMEOUT.as_secs()),
+    }
+}
+
+/// Shortens `output` to about `max_bytes`, keeping its first and last lines.
+///
+/// The end of an output usually holds the errors, so it gets two thirds of the space.
+/// A note in the middle tells the model how much was left out.
+pub fn truncate_output(output: &str, max_bytes: usize) -> String {
+    if output.len() <= max_bytes {
+        return output.to_string();
+    }
+
+    let mut head_end = max_bytes / 3;
+    while !output.is_char_boundary(head_end) {
+        head_end -= 1;
+    }
+    let head_end = output[..head_end].rfind('\n').map_or(head_end, |i| i + 1);
+
+    let mut tail_start = output.len() - (max_bytes - max_bytes / 3);
+    while !output.is_char_boundary(tail_start) {
+        tail_start += 1;
+    }
+    let tail_start = output[tail_start..]
+        .find('\n')
+        .filter(|&i| tail_start + i + 1 < output.len())
+        .map_or(tail_start, |i| tail_start + i + 1);
+
+    let omitted = &output[head_end..tail_start];
+    format!(
+        "{}[… {} lines ({} bytes) omitted …]\n{}",
+        &output[..head_end],
+        omitted.lines().count(),
+        omitted.len(),
+        &output[tail_start..]
+    )
+}
+
+/// Builds the message asking the user to approve the commands proposed by the AI.
+///
+/// Several commands form a plan: the steps are numbered and can be unticked
+/// before running them together.
+fn approval_request(
+    alias: &str,
+    content: &str,
+    commands: &[Tool],
+    ran_note: &str,
+    plan: Option<Plan>,
+) -> CommandResponse {
+    use base64::prelude::*;
+
+    let heading = if plan.is_some() {
+        "Suggested plan:"
+    } else {
+        "Suggested:"
+    };
+    let mut title = if content.trim().is_empty() {
+        format!("{}AI suggests running:", ran_note)
+    } else {
+        format!(
+            "{}{}\n\n{}",
+            ran_note,
+            markdown_to_telegram_html(content.trim()),
+            heading
+        )
+    };
+    for (i, tool) in commands.iter().enumerate() {
+        let Tool::RunCommand(c) = tool else { continue };
+        let bullet = if plan.is_some() {
+            format!("{}.", i + 1)
+        } else {
+            "•".to_string()
+        };
+        title.push_str(&format!(
+            "\n{} <code>{}</code>",
+            bullet,
+            escape_html(&c.command)
+        ));
+        if let Some(rationale) = c.rationale.as_deref().filter(|r| !r.is_empty()) {
+            title.push_str(&format!(" — <i>{}</i>", escape_html(rationale)));
+        }
+        if let Some(server) = c.server.as_deref().filter(|s| *s != alias) {
+            title.push_str(&format!(
+                " (asked for {}, runs on {})",
+                escape_html(server),
+                escape_html(alias)
+            ));
+        }
+    }
+
+    match plan {
+        Some(plan) => {
+            title.push_str(
+                "\n\n<i>Untick the steps you don't want, then run the rest in order.</i>",
+            );
+            CommandResponse::Buttons {
+                title,
+                buttons: plan.buttons(),
+            }
+        }
+        None => {
+            let encoded_cmd = BASE64_STANDARD.encode(commands[0].describe());
+            CommandResponse::InteractiveList {
+                title,
+                options: vec!["✅ Run".to_string(), "❌ Skip".to_string()],
+                callback_prefix: format!("tool_run:{}:", encoded_cmd),
+            }
+        }
+    }
+}
+
 /// Escapes HTML special characters so Telegram never rejects the message.
-fn escape_html(input: &str) -> String {
+pub fn escape_html(input: &str) -> String {
     input
         .replace('&', "&amp;")
         .replace('<', "&lt;")
@@ -203,7 +794,7 @@ fn escape_html(input: &str) -> String {
 
 /// Converts Markdown formatting to Telegram-compatible HTML.
 /// First escapes all HTML special chars, then converts Markdown patterns.
-fn markdown_to_telegram_html(input: &str) -> String {
+pub fn markdown_to_telegram_html(input: &str) -> String {
     // Strip tables first to avoid breaking HTML limits or showing raw tags
     let pre_processed = input
         .replace("<table>", "")
@@ -258,3 +849,87 @@ fn markdown_to_telegram_html(input: &str) -> String {
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{approval_request, Plan, PlanStep};
+    use crate::ai::tools::{RunCommand, Tool};
+    use crate::models::CommandResponse;
+
+    fn run(command: &str, rationale: Option<&str>, server: Option<&str>) -> Tool {
+        Tool::RunCommand(RunCommand {
+            server: server.map(str::to_string),
+            command: command.to_string(),
+            rationale: rationale.map(str::to_string),
+        })
+    }
+
+    fn plan(selected: [bool; 2]) -> Plan {
+        Plan {
+            id: 7,
+            alias: "web1".to_string(),
+            steps: ["df -h", "du -sh /var/log"]
+                .iter()
+                .zip(selected)
+                .map(|(command, selected)| PlanStep {
+                    command: command.to_string(),
+                    selected,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn plan_buttons_toggle_steps_and_count_the_selection() {
+        let expected = |first: &str, second: &str, count: &str| {
+            vec![
+                (format!("{} 1. df -h", first), "plan:7:t0".to_string()),
+                (
+                    format!("{} 2. du -sh /var/log", second),
+                    "plan:7:t1".to_string(),
+                ),
+                (
+                    format!("▶️ Run selected ({})", count),
+                    "plan:7:run".to_string(),
+                ),
+                ("❌ Skip".to_string(), "plan:7:skip".to_string()),
+            ]
+        };
+        assert_eq!(plan([true, true]).buttons(), expected("☑️", "☑️", "2/2"));
+        assert_eq!(plan([true, false]).buttons(), expected("☑️", "⬜", "1/2"));
+    }
+
+    #[test]
+    fn numbers_the_steps_of_a_plan() {
+        let commands = [
+            run("df -h", Some("Check the disks"), None),
+            run("du -sh /var/<x>", None, Some("db1")),
+        ];
+        let response = approval_request("web1", "Two checks", &commands, "", Some(plan([true; 2])));
+        let CommandResponse::Buttons { title, buttons } = response else {
+            panic!("a plan is proposed with buttons");
+        };
+        assert!(title.starts_with("Two checks\n\nSuggested plan:\n"));
+        assert!(title.contains("\n1. <code>df -h</code> — <i>Check the disks</i>"));
+        assert!(
+            title.contains("\n2. <code>du -sh /var/&lt;x&gt;</code> (asked for db1, runs on web1)")
+        );
+        assert_eq!(buttons.len(), 4);
+    }
+
+    #[test]
+    fn proposes_a_single_command_with_run_and_skip() {
+        let response = approval_request("web1", "", &[run("uptime", None, None)], "", None);
+        let CommandResponse::InteractiveList {
+            title,
+            options,
+            callback_prefix,
+        } = response
+        else {
+            panic!("a single command is proposed as a list");
+        };
+        assert_eq!(title, "AI suggests running:\n• <code>uptime</code>");
+        assert_eq!(options, ["✅ Run", "❌ Skip"]);
+        assert_eq!(callback_prefix, "tool_run:dXB0aW1l:");
+    }
+}
@@ -67,6 +67,66 @@ impl Database {
                 content TEXT NOT NULL,
                 timestamp DATETIME DEFAULT CURRENT_TIMESTAMP
             );
+
+            CREATE TABLE IF NOT EXISTS settings (
+                scope TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (scope, key)
+            );
+
+            CREATE TABLE IF NOT EXISTS maintenance_windows (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                server_id TEXT NOT NULL,
+                starts_at DATETIME NOT NULL,
+                ends_at DATETIME NOT NULL,
+                reason TEXT,
+                started_notified INTEGER NOT NULL DEFAULT 0,
+                ended_notified INTEGER NOT NULL DEFAULT 0,
+                FOREIGN KEY(server_id) REFERENCES servers(id)
+            );
+
+            CREATE TABLE IF NOT EXISTS alerts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                alert_key TEXT NOT NULL,
+                server_alias TEXT NOT NULL,
+                message TEXT NOT NULL,
+                status TEXT NOT NULL DEFAULT 'firing',
+                level INTEGER NOT NULL DEFAULT 0,
+                fired_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                last_notified_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                acknowledged_at DATETIME,
+                resolved_at DATETIME
+            );
+
+            CREATE TABLE IF NOT EXISTS ai_usage (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                chat_id INTEGER,
+                session_id TEXT,
+                provider TEXT NOT NULL,
+                model TEXT NOT NULL,
+                prompt_tokens INTEGER NOT NULL,
+                completion_tokens INTEGER NOT NULL,
+                estimated INTEGER NOT NULL DEFAULT 0,
+                cost REAL NOT NULL DEFAULT 0
+            );
+
+            CREATE TABLE IF NOT EXISTS digests (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                snapshot TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS incident_reports (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
+                chat_id INTEGER NOT NULL,
+                server_alias TEXT NOT NULL,
+                started_at DATETIME NOT NULL,
+                ended_at DATETIME NOT NULL,
+                content TEXT NOT NULL
+            );
             "#,
         )
         .execute(&pool)
@@ -75,3 +135,30 @@ impl Database {
         Ok(pool)
     }
 }
+
+/// Reads a value from the generic `settings` table.
+pub async fn get_setting(pool: &DbPool, scope: &str, key: &str) -> Option<String> {
+    sqlx::query_as::<_, (String,)>("SELECT value FROM settings WHERE scope = ? AND key = ?")
+        .bind(scope)
+        .bind(key)
+        .fetch_optional(pool)
+        .await
+        .unwrap_or(None)
+        .map(|r| r.0)
+}
+
+/// Writes a value to the generic `settings` table, replacing any previous one.
+pub async fn set_setting(
+    pool: &DbPool,
+    scope: &str,
+    key: &str,
+    value: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT OR REPLACE INTO settings (scope, key, value) VALUES (?, ?, ?)")
+        .bind(scope)
+        .bind(key)
+        .bind(value)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
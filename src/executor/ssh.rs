@@ -1,8 +1,10 @@
 use crate::models::ManagedServer;
-use ssh2::Session;
-use std::io::Read;
+use ssh2::{ExtendedData, Session};
+use std::io::{ErrorKind, Read};
 use std::net::TcpStream;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 /// A utility struct for executing SSH commands.
 pub struct SshExecutor;
@@ -28,6 +30,132 @@ impl SshExecutor {
     /// * `Ok(String)` - The command's stdout.
     /// * `Err(String)` - An error message describing failure steps or non-zero exit code.
     pub fn execute(server: &ManagedServer, command: &str) -> Result<String, String> {
+        Self::execute_with_limits(server, command, None, None)
+    }
+
+    /// Like `execute`, but any blocking SSH call (e.g. a read of output from a
+    /// command that prints nothing) fails after `timeout` instead of hanging.
+    ///
+    /// With `max_output`, a command that prints more bytes than that is closed and
+    /// an error is returned, as its exit status is then unknown.
+    pub fn execute_with_limits(
+        server: &ManagedServer,
+        command: &str,
+        timeout: Option<Duration>,
+        max_output: Option<u64>,
+    ) -> Result<String, String> {
+        let limit = max_output.unwrap_or(u64::MAX);
+        let sess = Self::connect(server, timeout)?;
+
+        // Create Channel and Execute Command
+        let mut channel = sess
+            .channel_session()
+            .map_err(|e| format!("Failed to create channel: {}", e))?;
+        channel
+            .exec(command)
+            .map_err(|e| format!("Failed to execute command: {}", e))?;
+
+        // Read Output
+        let mut stdout = Vec::new();
+        (&mut channel)
+            .take(limit)
+            .read_to_end(&mut stdout)
+            .map_err(|e| format!("Failed to read stdout: {}", e))?;
+        if stdout.len() as u64 >= limit {
+            let _ = channel.close();
+            return Err(format!(
+                "Output exceeded {} bytes, so the command was stopped.\nStdout (truncated): {}",
+                limit,
+                String::from_utf8_lossy(&stdout)
+            ));
+        }
+        let stdout = String::from_utf8_lossy(&stdout).into_owned();
+
+        // Also try to read stderr
+        let mut stderr = Vec::new();
+        let _ = channel.stderr().take(limit).read_to_end(&mut stderr);
+        let stderr = String::from_utf8_lossy(&stderr).into_owned();
+
+        channel
+            .wait_close()
+            .map_err(|e| format!("Failed to close channel: {}", e))?;
+
+        // Check exit status
+        let exit_status = channel.exit_status().unwrap_or(-1);
+        if exit_status != 0 {
+            return Err(format!(
+                "Command exited with status {}.\nStdout: {}\nStderr: {}",
+                exit_status, stdout, stderr
+            ));
+        }
+
+        if stdout.is_empty() && !stderr.is_empty() {
+            Ok(format!("(stderr): {}", stderr))
+        } else {
+            Ok(stdout)
+        }
+    }
+
+    /// Executes a command and hands its output to `on_output` as it arrives.
+    ///
+    /// Stdout and stderr are merged. Setting `stop` closes the channel, which ends
+    /// commands that never finish on their own such as `tail -f`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Some(i32))` - The exit status once the command finished.
+    /// * `Ok(None)` - The stream was stopped before the command finished.
+    /// * `Err(String)` - An error message describing the failure.
+    pub fn stream(
+        server: &ManagedServer,
+        command: &str,
+        stop: &AtomicBool,
+        mut on_output: impl FnMut(&[u8]),
+    ) -> Result<Option<i32>, String> {
+        let sess = Self::connect(server, None)?;
+
+        let mut channel = sess
+            .channel_session()
+            .map_err(|e| format!("Failed to create channel: {}", e))?;
+        channel
+            .handle_extended_data(ExtendedData::Merge)
+            .map_err(|e| format!("Failed to merge stderr: {}", e))?;
+        channel
+            .exec(command)
+            .map_err(|e| format!("Failed to execute command: {}", e))?;
+
+        // Poll without blocking so the stop flag is checked regularly
+        sess.set_blocking(false);
+        let mut buf = [0u8; 4096];
+        loop {
+            if stop.load(Ordering::Relaxed) {
+                sess.set_blocking(true);
+                let _ = channel.close();
+                return Ok(None);
+            }
+            match channel.read(&mut buf) {
+                Ok(0) if channel.eof() => break,
+                Ok(0) => std::thread::sleep(Duration::from_millis(200)),
+                Ok(n) => on_output(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    std::thread::sleep(Duration::from_millis(200))
+                }
+                Err(e) => return Err(format!("Failed to read output: {}", e)),
+            }
+        }
+
+        sess.set_blocking(true);
+        channel
+            .wait_close()
+            .map_err(|e| format!("Failed to close channel: {}", e))?;
+        Ok(Some(channel.exit_status().unwrap_or(-1)))
+    }
+
+    /// Opens an authenticated SSH session to a server.
+    ///
+    /// Tries the SSH agent, then the local `id_rsa` key, then the password.
+    /// Blocking calls on the session fail after `timeout`, if given.
+    fn connect(server: &ManagedServer, timeout: Option<Duration>) -> Result<Session, String> {
         //Establish TCP connection
         let address = format!("{}:{}", server.ip_address, server.port);
         let tcp = TcpStream::connect(&address)
@@ -37,6 +165,9 @@ impl SshExecutor {
         let mut sess =
             Session::new().map_err(|e| format!("Failed to create SSH session: {}", e))?;
         sess.set_tcp_stream(tcp);
+        if let Some(timeout) = timeout {
+            sess.set_timeout(timeout.as_millis() as u32);
+        }
         sess.handshake()
             .map_err(|e| format!("SSH handshake failed: {}", e))?;
         println!("SSH: Handshake successful.");
@@ -73,44 +204,6 @@ impl SshExecutor {
             );
         }
 
-        // Create Channel and Execute Command
-        let mut channel = sess
-            .channel_session()
-            .map_err(|e| format!("Failed to create channel: {}", e))?;
-        channel
-            .exec(command)
-            .map_err(|e| format!("Failed to execute command: {}", e))?;
-
-        // Read Output
-        let mut stdout = String::new();
-        channel
-            .read_to_string(&mut stdout)
-            .map_err(|e| format!("Failed to read stdout: {}", e))?;
-
-        // Also try to read stderr
-        let mut stderr = String::new();
-        channel
-            .stderr()
-            .read_to_string(&mut stderr)
-            .unwrap_or_default();
-
-        channel
-            .wait_close()
-            .map_err(|e| format!("Failed to close channel: {}", e))?;
-
-        // Check exit status
-        let exit_status = channel.exit_status().unwrap_or(-1);
-        if exit_status != 0 {
-            return Err(format!(
-                "Command exited with status {}.\nStdout: {}\nStderr: {}",
-                exit_status, stdout, stderr
-            ));
-        }
-
-        if stdout.is_empty() && !stderr.is_empty() {
-            Ok(format!("(stderr): {}", stderr))
-        } else {
-            Ok(stdout)
-        }
+        Ok(sess)
     }
 }
@@ -0,0 +1,714 @@
+/// Programs that only read state. Those that can also change it are checked
+/// further in `segment_is_read_only`.
+const READ_ONLY_PROGRAMS: [&str; 57] = [
+    "cat",
+    "head",
+    "tail",
+    "grep",
+    "egrep",
+    "fgrep",
+    "zgrep",
+    "zcat",
+    "ls",
+    "stat",
+    "file",
+    "wc",
+    "sort",
+    "uniq",
+    "cut",
+    "tr",
+    "column",
+    "df",
+    "du",
+    "free",
+    "uptime",
+    "uname",
+    "hostname",
+    "whoami",
+    "id",
+    "w",
+    "who",
+    "last",
+    "ps",
+    "pgrep",
+    "pidof",
+    "lsof",
+    "ss",
+    "netstat",
+    "lsblk",
+    "findmnt",
+    "nproc",
+    "lscpu",
+    "vmstat",
+    "iostat",
+    "date",
+    "echo",
+    "getent",
+    "dig",
+    "nslookup",
+    "host",
+    "dmesg",
+    "journalctl",
+    "systemctl",
+    "ip",
+    "docker",
+    "find",
+    "dpkg",
+    "rpm",
+    "apt",
+    "ping",
+    "timedatectl",
+];
+
+/// Files that hold credentials, relative to `/`.
+const SENSITIVE_FILES: [&str; 8] = [
+    "etc/shadow",
+    "etc/shadow-",
+    "etc/gshadow",
+    "etc/gshadow-",
+    "etc/sudoers",
+    "etc/security/opasswd",
+    "etc/master.passwd",
+    "etc/ssh/sshd_config",
+];
+
+/// Directories whose files hold credentials, wherever they are.
+const SENSITIVE_DIRS: [&str; 9] = [
+    ".ssh",
+    ".gnupg",
+    ".aws",
+    ".azure",
+    ".kube",
+    ".docker",
+    ".password-store",
+    "sudoers.d",
+    "private",
+];
+
+/// Names of files that hold credentials, wherever they are.
+const SENSITIVE_NAMES: [&str; 14] = [
+    ".pgpass",
+    ".netrc",
+    ".my.cnf",
+    ".git-credentials",
+    ".htpasswd",
+    ".vault-token",
+    "credentials",
+    "credentials.json",
+    "wp-config.php",
+    "environ",
+    ".bash_history",
+    ".zsh_history",
+    ".mysql_history",
+    ".psql_history",
+];
+
+/// Extensions of key stores and private keys.
+const SENSITIVE_EXTENSIONS: [&str; 7] =
+    [".pem", ".key", ".p12", ".pfx", ".jks", ".keystore", ".kdbx"];
+
+/// Whether `path` may hold secrets (password hashes, private keys, `.env` files and
+/// the like), so reading it needs the user's approval.
+///
+/// `..` is resolved first; relative paths are matched by their components.
+pub fn is_sensitive_path(path: &str) -> bool {
+    let components = components(path);
+    let joined = format!("/{}", components.join("/"));
+    let name = components.last().copied().unwrap_or_default();
+
+    SENSITIVE_FILES
+        .iter()
+        .any(|f| joined.ends_with(&format!("/{}", f)))
+        || components.iter().any(|c| SENSITIVE_DIRS.contains(c))
+        || SENSITIVE_NAMES.contains(&name)
+        || name == ".env"
+        || name.starts_with(".env.")
+        || name.ends_with(".env")
+        // SSH keys such as id_rsa or ssh_host_ed25519_key, but not their public halves
+        || ((name.starts_with("id_") || name.starts_with("ssh_host_")) && !name.ends_with(".pub"))
+        || SENSITIVE_EXTENSIONS.iter().any(|ext| name.ends_with(ext))
+}
+
+/// Whether `path` is a device such as `/dev/zero`, or `/proc/kcore`, whose reads
+/// may never end.
+///
+/// Relative paths climbing with `..` may end up anywhere and count as devices.
+pub fn is_device_path(path: &str) -> bool {
+    if !path.starts_with('/') {
+        return path.split('/').any(|c| c == "..");
+    }
+    let components = components(path);
+    components.first() == Some(&"dev") || components == ["proc", "kcore"]
+}
+
+/// Splits `path` into its components, resolving `.` and `..`.
+fn components(path: &str) -> Vec<&str> {
+    let mut components: Vec<&str> = Vec::new();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            other => components.push(other),
+        }
+    }
+    components
+}
+
+/// Whether `command` only reads state and may run without asking the user.
+///
+/// This is a conservative allowlist: every part of a pipeline must be a known
+/// read-only program, and anything the shell could use to write or chain other
+/// commands (redirections, `;`, `&&`, substitutions) makes the command unsafe.
+/// `sudo` and variable assignments are never allowed, nor are files that may hold
+/// secrets (see `is_sensitive_path`) or devices.
+pub fn is_read_only(command: &str) -> bool {
+    let command = command.trim();
+    if command.is_empty()
+        || command.contains([';', '&', '>', '<', '`', '$', '(', ')', '\\', '\n', '\r'])
+    {
+        return false;
+    }
+    // `||` leaves an empty segment and is refused with it
+    command.split('|').enumerate().all(|(index, segment)| {
+        let words: Vec<&str> = segment.split_whitespace().collect();
+        !words.is_empty() && segment_is_read_only(&words, index > 0)
+    })
+}
+
+/// Checks one command of a pipeline; `piped` is set when its stdin is the
+/// previous command's output.
+fn segment_is_read_only(words: &[&str], piped: bool) -> bool {
+    let program = words[0].rsplit('/').next().unwrap_or(words[0]);
+    let args = &words[1..];
+    if !READ_ONLY_PROGRAMS.contains(&program) {
+        return false;
+    }
+    // A glob or brace pattern would only be matched against the secrets list after
+    // the shell expanded it, so only quoted words may hold one
+    if args
+        .iter()
+        .any(|a| !is_quoted(a) && a.contains(['*', '?', '[', ']', '{', '}']))
+    {
+        return false;
+    }
+    // Also catches `--file=/etc/shadow` and quoted paths
+    if args.iter().any(|a| {
+        let value = a.rsplit('=').next().unwrap_or(a).replace(['\'', '"'], "");
+        is_sensitive_path(&value) || is_device_path(&value)
+    }) {
+        return false;
+    }
+    // Without a file these read stdin, which never ends over SSH
+    if !piped {
+        let files = match program {
+            "tr" => 0,
+            "grep" | "egrep" | "fgrep" | "zgrep" => {
+                let count = operands(args, GREP_VALUE_FLAGS).len();
+                let given = args.iter().any(|a| {
+                    a.starts_with("-e")
+                        || a.starts_with("-f")
+                        || a.starts_with("--regexp")
+                        || a.starts_with("--file")
+                });
+                if given {
+                    count
+                } else {
+                    count.saturating_sub(1)
+                }
+            }
+            "sort" => operands(args, SORT_VALUE_FLAGS).len(),
+            "head" | "tail" => operands(args, &["-n", "-c", "--lines", "--bytes"]).len(),
+            "cut" => operands(args, &["-d", "-f", "-b", "-c", "--delimiter", "--fields"]).len(),
+            "cat" | "zcat" | "wc" | "uniq" | "column" => operands(args, &[]).len(),
+            _ => 1,
+        };
+        if files == 0 {
+            return false;
+        }
+    }
+    let has = |flags: &[&str]| args.iter().any(|a| flags.contains(a));
+    let first_operand = args.iter().find(|a| !a.starts_with('-')).copied();
+
+    match program {
+        // Following output never ends, so the SSH call would hang
+        "tail" | "journalctl" if follows(args) => false,
+        "journalctl" => !args.iter().any(|a| {
+            a.starts_with("--vacuum")
+                || a.starts_with("--setup-keys")
+                || a.starts_with("--update-catalog")
+                || ["--rotate", "--flush", "--sync", "--relinquish-var"].contains(a)
+        }),
+        // Unit files and `Environment=` may hold secrets, so `show` needs named properties
+        "systemctl" if first_operand == Some("show") => {
+            args.iter()
+                .any(|a| *a == "-p" || a.starts_with("--property"))
+                && !args.iter().any(|a| a.contains("Environment"))
+        }
+        "systemctl" => matches!(
+            first_operand,
+            Some(
+                "status"
+                    | "is-active"
+                    | "is-enabled"
+                    | "is-failed"
+                    | "list-units"
+                    | "list-unit-files"
+                    | "list-timers"
+                    | "list-sockets"
+                    | "list-dependencies"
+            )
+        ),
+        "docker" => match first_operand {
+            Some("logs") => !follows(args),
+            Some("stats") => has(&["--no-stream"]),
+            // `inspect` prints the containers' environment, secrets included
+            Some("ps" | "images" | "top" | "version" | "info" | "port") => true,
+            _ => false,
+        },
+        "ip" => {
+            matches!(
+                first_operand,
+                Some("a" | "addr" | "address" | "r" | "route" | "link" | "l" | "neigh" | "rule")
+            ) && !has(&[
+                "add", "del", "delete", "set", "flush", "change", "replace", "append", "prepend",
+            ])
+        }
+        // Recursing reads every file below, secrets included
+        "grep" | "egrep" | "fgrep" | "zgrep" => {
+            // `-d recurse` too
+            !short_flag(args, &['r', 'R', 'd'])
+                && !args.iter().any(|a| {
+                    a.starts_with("--recursive")
+                        || a.starts_with("--dereference-recursive")
+                        || a.starts_with("--directories")
+                })
+        }
+        // The shadow databases hold password hashes
+        "getent" => !args
+            .iter()
+            .any(|a| ["shadow", "gshadow"].contains(&a.replace(['\'', '"'], "").as_str())),
+        "find" => !has(&[
+            "-delete", "-exec", "-execdir", "-ok", "-okdir", "-fprint", "-fprint0", "-fprintf",
+            "-fls",
+        ]),
+        "dmesg" => {
+            // `-w`/`-W` follow the kernel log; `-f` is the facility filter here
+            !short_flag(args, &['c', 'C', 'D', 'E', 'n', 'w', 'W'])
+                && !args.iter().any(|a| {
+                    a.starts_with("--clear")
+                        || a.starts_with("--follow")
+                        || a.starts_with("--read-clear")
+                        || a.starts_with("--console")
+                })
+        }
+        "dpkg" => has(&[
+            "-l",
+            "-L",
+            "-s",
+            "-S",
+            "--list",
+            "--listfiles",
+            "--status",
+            "--search",
+        ]),
+        "rpm" => args.first().is_some_and(|a| a.starts_with("-q")),
+        "apt" => matches!(first_operand, Some("list" | "policy" | "show")),
+        "ping" => has(&["-c"]),
+        // Both also set the time or name when given an operand
+        "date" => {
+            !short_flag(args, &['s'])
+                && !args.iter().any(|a| a.starts_with("--set"))
+                && args.iter().all(|a| a.starts_with(['-', '+']))
+        }
+        "hostname" => args.iter().all(|a| {
+            [
+                "-f",
+                "-s",
+                "-d",
+                "-i",
+                "-I",
+                "-A",
+                "--fqdn",
+                "--short",
+                "--domain",
+                "--ip-address",
+                "--all-ip-addresses",
+                "--all-fqdns",
+            ]
+            .contains(a)
+        }),
+        "timedatectl" => matches!(first_operand, None | Some("status" | "show")),
+        // `-o` may be attached to its file or grouped (`-o/etc/x`, `-uo x`)
+        "sort" => {
+            !short_flag(args, &['o'])
+                && !args
+                    .iter()
+                    .any(|a| a.starts_with("--output") || a.starts_with("--compress-program"))
+        }
+        // An interval operand repeats the report forever, or for `count` rounds
+        "vmstat" | "iostat" => !args.iter().any(|a| a.parse::<f64>().is_ok()),
+        // BSD `e` and the `environ` column print the environment of every process
+        "ps" => {
+            !args.iter().any(|a| a.contains("environ"))
+                && !operands(args, PS_VALUE_FLAGS)
+                    .iter()
+                    .any(|a| a.chars().all(|c| c.is_ascii_alphabetic()) && a.contains('e'))
+        }
+        "ss" => !short_flag(args, &['K']) && !args.iter().any(|a| a.starts_with("--kill")),
+        // A second operand is an output file
+        "uniq" => args.iter().filter(|a| !a.starts_with('-')).count() <= 1,
+        _ => true,
+    }
+}
+
+/// Short and long `grep` flags whose value is the next word.
+const GREP_VALUE_FLAGS: &[&str] = &[
+    "-A",
+    "-B",
+    "-C",
+    "-m",
+    "-e",
+    "-f",
+    "-d",
+    "-D",
+    "--regexp",
+    "--file",
+    "--max-count",
+];
+
+/// Short and long `sort` flags whose value is the next word.
+const SORT_VALUE_FLAGS: &[&str] = &["-k", "-t", "-S", "-T", "--key", "--field-separator"];
+
+/// Short and long `ps` flags whose value is the next word.
+const PS_VALUE_FLAGS: &[&str] = &[
+    "-o", "-O", "-p", "-q", "-u", "-U", "-g", "-G", "-C", "-t", "--format", "--pid", "--user",
+    "--User", "--group", "--Group", "--sort",
+];
+
+/// The operands among `args`, skipping flags and the values of `value_flags`.
+fn operands<'a>(args: &[&'a str], value_flags: &[&str]) -> Vec<&'a str> {
+    let mut operands = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if value_flags.contains(arg) {
+            args.next();
+        } else if !arg.starts_with('-') {
+            operands.push(*arg);
+        }
+    }
+    operands
+}
+
+/// Whether the arguments ask to follow the output (`-f`, `-F`, `-fu`, `--follow`).
+fn follows(args: &[&str]) -> bool {
+    short_flag(args, &['f', 'F']) || args.iter().any(|a| a.starts_with("--follow"))
+}
+
+/// Whether `word` is wrapped in a single pair of quotes, so the shell doesn't
+/// expand it.
+fn is_quoted(word: &str) -> bool {
+    [b'\'', b'"'].into_iter().any(|quote| {
+        word.len() >= 2
+            && word.as_bytes()[0] == quote
+            && word.as_bytes()[word.len() - 1] == quote
+            && !word[1..word.len() - 1].contains(['\'', '"'])
+    })
+}
+
+/// Whether one of the short flags is given, alone or in a group such as `-xef`.
+fn short_flag(args: &[&str], flags: &[char]) -> bool {
+    args.iter()
+        .any(|a| a.starts_with('-') && !a.starts_with("--") && a.contains(flags))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_read_only, is_sensitive_path};
+
+    #[test]
+    fn accepts_read_only_commands() {
+        for command in [
+            "uptime",
+            "df -h",
+            "ps aux | grep nginx | head -5",
+            "tail -n 50 /var/log/syslog",
+            "journalctl -u nginx -n 100 --no-pager",
+            "systemctl status nginx",
+            "docker logs --tail 20 web",
+            "docker stats --no-stream",
+            "ip addr show",
+            "find /var/log -name '*.gz'",
+            "dmesg -T",
+            "dmesg --level=err,warn",
+            "dmesg -f kern",
+            "vmstat",
+            "vmstat -s",
+            "vmstat -S M",
+            "iostat -x",
+            "iostat -p sda",
+            "sort -u /etc/passwd",
+            "sort -k2 -n file",
+            "sort -t: -k3 /etc/group",
+            "ss -tlnp",
+            "ss -s",
+            "uniq -c file",
+            "date +%s",
+            "hostname -f",
+            "ping -c 3 example.com",
+        ] {
+            assert!(is_read_only(command), "should accept {:?}", command);
+        }
+    }
+
+    #[test]
+    fn rejects_commands_that_change_state() {
+        for command in [
+            "",
+            "rm -rf /tmp/x",
+            "sudo cat /etc/shadow",
+            "cat /etc/passwd > /tmp/x",
+            "uptime; reboot",
+            "uptime && reboot",
+            "false || reboot",
+            "echo $(reboot)",
+            "systemctl restart nginx",
+            "docker rm web",
+            "ip route add default via 10.0.0.1",
+            "find / -delete",
+            "find / -exec rm {} +",
+            "journalctl --vacuum-size=1M",
+            "dmesg -c",
+            "dmesg --clear",
+            "date -s 2020-01-01",
+            "hostname newname",
+            "uniq in out",
+            "apt install nginx",
+        ] {
+            assert!(!is_read_only(command), "should reject {:?}", command);
+        }
+    }
+
+    #[test]
+    fn rejects_commands_that_never_end() {
+        for command in [
+            "tail -f /var/log/syslog",
+            "tail -F /var/log/syslog",
+            "journalctl -fu nginx",
+            "journalctl --follow",
+            "docker logs -f web",
+            "docker stats",
+            "ping example.com",
+            "vmstat 1",
+            "vmstat 1 5",
+            "vmstat -w 2",
+            "iostat 1",
+            "iostat -x 5 3",
+            "dmesg -w",
+            "dmesg -W",
+            "dmesg -Tw",
+            "dmesg --follow",
+            "dmesg --follow-new",
+        ] {
+            assert!(!is_read_only(command), "should reject {:?}", command);
+        }
+    }
+
+    #[test]
+    fn rejects_sort_writing_files() {
+        for command in [
+            "sort -o /etc/x file",
+            "sort -o/etc/x file",
+            "sort -uo /etc/x file",
+            "sort -uo/etc/x file",
+            "sort --output=/etc/x file",
+            "sort --output /etc/x file",
+            "sort --compress-program=sh file",
+            "sort --compress-program sh file",
+        ] {
+            assert!(!is_read_only(command), "should reject {:?}", command);
+        }
+    }
+
+    #[test]
+    fn flags_sensitive_paths() {
+        for path in [
+            "/etc/shadow",
+            "/etc/gshadow",
+            "/etc/../etc/./shadow",
+            "/etc/sudoers",
+            "/etc/sudoers.d/admins",
+            "/root/.ssh/id_rsa",
+            "/home/deploy/.ssh/authorized_keys",
+            "/etc/ssh/ssh_host_ed25519_key",
+            "/etc/ssl/private/site.key",
+            "/srv/app/.env",
+            "/srv/app/.env.production",
+            "/srv/app/prod.env",
+            "/home/deploy/.aws/credentials",
+            "/root/.pgpass",
+            "/proc/1/environ",
+            "/etc/letsencrypt/live/site/privkey.pem",
+            ".env",
+        ] {
+            assert!(is_sensitive_path(path), "should flag {:?}", path);
+        }
+        for path in [
+            "/var/log/syslog",
+            "/etc/nginx/nginx.conf",
+            "/etc/passwd",
+            "/etc/ssh/ssh_host_ed25519_key.pub",
+            "/home/deploy/.ssh/../app/README.md",
+            "/",
+        ] {
+            assert!(!is_sensitive_path(path), "should not flag {:?}", path);
+        }
+    }
+
+    #[test]
+    fn rejects_reading_sensitive_files() {
+        for command in [
+            "cat /etc/shadow",
+            "tail -n 5 '/root/.ssh/id_rsa'",
+            "grep KEY /srv/app/.env",
+            "head --lines=5 /etc/ssl/private/site.key",
+            "cat /proc/1/environ",
+        ] {
+            assert!(!is_read_only(command), "should reject {:?}", command);
+        }
+    }
+
+    #[test]
+    fn rejects_reading_devices() {
+        for command in [
+            "cat /dev/zero",
+            "cat /dev/urandom",
+            "head -c 10 /dev/urandom",
+            "cat /tmp/../dev/zero",
+            "cat '/dev/zero'",
+            "wc --files0-from=/dev/stdin",
+            "cat ../../dev/zero",
+            "head -c 10 /proc/kcore",
+        ] {
+            assert!(!is_read_only(command), "should reject {:?}", command);
+        }
+    }
+
+    #[test]
+    fn rejects_reading_stdin_without_a_file() {
+        for command in [
+            "cat",
+            "cat -n",
+            "cat -",
+            "grep error",
+            "grep -A 3 error",
+            "grep -e error",
+            "sort",
+            "sort -k 2 -t :",
+            "tr a-z A-Z",
+            "head -n 5",
+            "wc -l",
+        ] {
+            assert!(!is_read_only(command), "should reject {:?}", command);
+        }
+        for command in [
+            "cat /var/log/syslog",
+            "grep error /var/log/syslog",
+            "grep -A 3 error /var/log/syslog",
+            "grep -e error /var/log/syslog",
+            "sort -k 2 -t : /etc/group",
+            "ps aux | sort | tr a-z A-Z | grep -e nginx",
+            "head -n 5 /var/log/syslog",
+        ] {
+            assert!(is_read_only(command), "should accept {:?}", command);
+        }
+    }
+
+    #[test]
+    fn rejects_journalctl_changing_the_system() {
+        for command in [
+            "journalctl --setup-keys",
+            "journalctl --setup-keys --interval=10s",
+            "journalctl --update-catalog",
+        ] {
+            assert!(!is_read_only(command), "should reject {:?}", command);
+        }
+    }
+
+    #[test]
+    fn rejects_globs_matching_sensitive_files() {
+        for command in [
+            "cat /etc/sha*",
+            "tail /etc/ss?/ssh_host_*_key",
+            "cat /etc/shado[w]",
+            "cat /etc/{shadow,passwd}",
+            "cat /etc/sha'dow'",
+            "cat \"/etc/sha\"*",
+        ] {
+            assert!(!is_read_only(command), "should reject {:?}", command);
+        }
+        for command in [
+            "grep 'err.*' /var/log/syslog",
+            "find /var/log -name \"*.gz\"",
+        ] {
+            assert!(is_read_only(command), "should accept {:?}", command);
+        }
+    }
+
+    #[test]
+    fn rejects_commands_printing_secrets() {
+        for command in [
+            "getent shadow",
+            "getent gshadow root",
+            "getent 'shadow'",
+            "grep -r pass /etc",
+            "grep -R pass /etc",
+            "grep -ri pass /etc",
+            "grep --recursive pass /etc",
+            "grep -d recurse pass /etc",
+            "docker inspect web",
+            "systemctl cat nginx",
+            "systemctl show nginx",
+            "systemctl show nginx -p Environment",
+        ] {
+            assert!(!is_read_only(command), "should reject {:?}", command);
+        }
+        for command in [
+            "getent passwd deploy",
+            "systemctl show nginx -p ActiveState,SubState",
+        ] {
+            assert!(is_read_only(command), "should accept {:?}", command);
+        }
+    }
+
+    #[test]
+    fn rejects_ss_killing_sockets() {
+        for command in ["ss -K dst 10.0.0.1", "ss -tK", "ss --kill dst 10.0.0.1"] {
+            assert!(!is_read_only(command), "should reject {:?}", command);
+        }
+    }
+
+    #[test]
+    fn rejects_ps_printing_environments() {
+        for command in [
+            "ps e",
+            "ps eww",
+            "ps auxe",
+            "ps -A e",
+            "ps -eo pid,environ",
+            "ps --format pid,environ",
+            "ps -o environ -p 1",
+        ] {
+            assert!(!is_read_only(command), "should reject {:?}", command);
+        }
+        for command in [
+            "ps aux",
+            "ps -ef",
+            "ps -eo pid,comm --sort=-%cpu",
+            "ps -u www-data",
+        ] {
+            assert!(is_read_only(command), "should accept {:?}", command);
+        }
+    }
+}
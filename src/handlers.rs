@@ -1 +1,4 @@
+pub mod scheduler;
+pub mod stream;
 pub mod telegram;
+pub mod webhook;
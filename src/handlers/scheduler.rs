@@ -0,0 +1,224 @@
+use crate::ai::client::AiClient;
+use crate::core::alerts::{
+    Alert, AlertManager, AlertRules, EscalationPolicy, CHECK_TIMEOUT, MAX_LEVEL,
+};
+use crate::core::digest::DigestConfig;
+use crate::core::maintenance::MaintenanceManager;
+use crate::core::server_manager::ServerManager;
+use crate::core::session::escape_html;
+use crate::db::{get_setting, set_setting, DbPool};
+use crate::handlers::telegram::handle_command_response;
+use crate::models::CommandResponse;
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use std::time::Duration;
+use teloxide::prelude::*;
+
+/// How often the background jobs wake up to check whether something is due.
+const TICK: Duration = Duration::from_secs(60);
+
+/// Spawns the background jobs that run alongside the bot dispatcher.
+pub fn spawn_jobs(bot: Bot, pool: DbPool, admin_id: i64) {
+    tokio::spawn(digest_loop(bot.clone(), pool.clone(), admin_id));
+    tokio::spawn(maintenance_loop(bot.clone(), pool.clone(), admin_id));
+    tokio::spawn(alert_loop(bot, pool, admin_id));
+}
+
+/// Posts the fleet digest to the admin chat according to `DigestConfig`.
+async fn digest_loop(bot: Bot, pool: DbPool, admin_id: i64) {
+    let mut interval = tokio::time::interval(TICK);
+    loop {
+        interval.tick().await;
+
+        let config = DigestConfig::load(&pool).await;
+        let last_run = get_setting(&pool, "digest", "last_run")
+            .await
+            .and_then(|d| NaiveDate::parse_from_str(&d, "%Y-%m-%d").ok());
+
+        let scheduled = match config.due(Utc::now(), last_run) {
+            Some(date) => date,
+            None => continue,
+        };
+
+        // Mark as done first so a slow or failing run isn't retried every tick
+        let _ = set_setting(&pool, "digest", "last_run", &scheduled.to_string()).await;
+
+        println!("Scheduler: Running {} digest", config.schedule);
+        let ai_client = AiClient::new(pool.clone()).await;
+        let response = match crate::core::digest::run(&pool, &ai_client, config.ai_summary).await {
+            Ok(report) => CommandResponse::Html(report),
+            Err(e) => CommandResponse::Text(format!("Scheduled digest failed: {}", e)),
+        };
+
+        if let Err(e) = handle_command_response(&bot, ChatId(admin_id), response).await {
+            eprintln!("Scheduler: Failed to send digest: {}", e);
+        }
+    }
+}
+
+/// Announces maintenance windows as they start and end.
+async fn maintenance_loop(bot: Bot, pool: DbPool, admin_id: i64) {
+    let maintenance = MaintenanceManager::new(pool.clone());
+    let mut interval = tokio::time::interval(TICK);
+    loop {
+        interval.tick().await;
+
+        let (started, ended) = match maintenance.take_transitions().await {
+            Ok(transitions) => transitions,
+            Err(e) => {
+                eprintln!("Scheduler: Failed to check maintenance windows: {}", e);
+                continue;
+            }
+        };
+
+        let tz = DigestConfig::load(&pool).await.timezone;
+        let mut lines = Vec::new();
+        for w in started {
+            lines.push(format!("🔧 Maintenance started — {}", w.describe(&tz)));
+        }
+        for w in ended {
+            lines.push(format!(
+                "✅ Maintenance ended for {}. Notifications resumed.",
+                w.alias
+            ));
+        }
+        if lines.is_empty() {
+            continue;
+        }
+
+        let response = CommandResponse::Text(lines.join("\n"));
+        if let Err(e) = handle_command_response(&bot, ChatId(admin_id), response).await {
+            eprintln!("Scheduler: Failed to send maintenance notice: {}", e);
+        }
+    }
+}
+
+/// Runs the periodic health checks, fires and resolves alerts, and escalates
+/// alerts that stay unacknowledged according to `EscalationPolicy`.
+async fn alert_loop(bot: Bot, pool: DbPool, admin_id: i64) {
+    let alerts = AlertManager::new(pool.clone());
+    let maintenance = MaintenanceManager::new(pool.clone());
+    let mut last_check: Option<NaiveDateTime> = None;
+    let mut interval = tokio::time::interval(TICK);
+    loop {
+        interval.tick().await;
+
+        let rules = AlertRules::load(&pool).await;
+        let now = Utc::now().naive_utc();
+        let check_due = rules.interval_minutes > 0
+            && last_check
+                .map(|t| (now - t).num_minutes() >= rules.interval_minutes)
+                .unwrap_or(true);
+
+        // Servers under maintenance are neither checked nor escalated
+        let muted: Vec<String> = maintenance
+            .list_active()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|w| w.alias)
+            .collect();
+
+        if check_due {
+            last_check = Some(now);
+            let servers = ServerManager::new(pool.clone())
+                .list_servers()
+                .await
+                .unwrap_or_default();
+
+            for (alias, server) in servers {
+                if muted.contains(&alias) {
+                    continue;
+                }
+                // A hung server is skipped; its SSH calls end on their own read timeout
+                let check = tokio::task::spawn_blocking({
+                    let (rules, alias) = (rules.clone(), alias.clone());
+                    move || rules.check(&alias, &server)
+                });
+                let problems = match tokio::time::timeout(CHECK_TIMEOUT, check).await {
+                    Ok(Ok(problems)) => problems,
+                    Ok(Err(e)) => {
+                        eprintln!("Scheduler: Health check of {} failed: {}", alias, e);
+                        continue;
+                    }
+                    Err(_) => {
+                        eprintln!("Scheduler: Health check of {} timed out", alias);
+                        continue;
+                    }
+                };
+                let keys: Vec<String> = problems.iter().map(|(k, _)| k.clone()).collect();
+
+                for (key, message) in problems {
+                    if let Ok(Some(alert)) = alerts.fire(&key, &alias, &message).await {
+                        notify_alert(&bot, ChatId(admin_id), &alert, "🚨 Alert").await;
+                    }
+                }
+                for alert in alerts
+                    .resolve_cleared(&alias, &keys)
+                    .await
+                    .unwrap_or_default()
+                {
+                    let text = format!("✅ Resolved #{}: {}", alert.id, alert.message);
+                    let _ = handle_command_response(
+                        &bot,
+                        ChatId(admin_id),
+                        CommandResponse::Text(text),
+                    )
+                    .await;
+                }
+            }
+        }
+
+        let policy = EscalationPolicy::load(&pool).await;
+        let escalations = match alerts.take_escalations(policy.ack_minutes, &muted).await {
+            Ok(e) => e,
+            Err(e) => {
+                eprintln!("Scheduler: Failed to check escalations: {}", e);
+                continue;
+            }
+        };
+
+        for alert in escalations {
+            if alert.level < MAX_LEVEL {
+                notify_alert(&bot, ChatId(admin_id), &alert, "⏰ Still unacknowledged").await;
+                continue;
+            }
+
+            // Final step: widen the audience
+            notify_alert(&bot, ChatId(admin_id), &alert, "📣 Escalated").await;
+            for chat in &policy.chat_ids {
+                notify_alert(&bot, ChatId(*chat), &alert, "📣 Escalated").await;
+            }
+            if let Some(url) = &policy.webhook_url {
+                if let Err(e) = crate::handlers::webhook::post_alert(url, &alert).await {
+                    eprintln!(
+                        "Scheduler: Failed to post alert #{} to webhook: {}",
+                        alert.id, e
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Sends an alert with an "Acknowledge" button.
+async fn notify_alert(bot: &Bot, chat_id: ChatId, alert: &Alert, heading: &str) {
+    let response = CommandResponse::Buttons {
+        title: format!(
+            "<b>{} #{}</b>\n{}\nFiring since {} UTC",
+            heading,
+            alert.id,
+            escape_html(&alert.message),
+            alert.fired_at
+        ),
+        buttons: vec![(
+            "✅ Acknowledge".to_string(),
+            format!("alert_ack:{}", alert.id),
+        )],
+    };
+    if let Err(e) = handle_command_response(bot, chat_id, response).await {
+        eprintln!(
+            "Scheduler: Failed to notify chat {} about alert #{}: {}",
+            chat_id, alert.id, e
+        );
+    }
+}
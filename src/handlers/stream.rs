@@ -0,0 +1,319 @@
+use crate::core::session::escape_html;
+use crate::executor::ssh::SshExecutor;
+use crate::handlers::telegram::{handle_command_response, send_long_message};
+use crate::models::{AgentEvent, CommandResponse, ManagedServer, StreamEvent};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+use teloxide::prelude::*;
+use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, MessageId, ParseMode};
+use tokio::sync::mpsc::{self, UnboundedReceiver};
+
+/// Minimum delay between two edits of the live message (Telegram rate limits edits).
+const EDIT_INTERVAL: Duration = Duration::from_secs(3);
+/// Streams are stopped automatically after this long.
+const MAX_DURATION: Duration = Duration::from_secs(30 * 60);
+/// Bytes of output kept for display, leaving room for the header within Telegram's limit.
+const MAX_DISPLAY: usize = 3500;
+
+/// Minimum delay between two edits of a streamed AI reply.
+const AI_EDIT_INTERVAL: Duration = Duration::from_millis(1500);
+/// Longest text Telegram accepts in a single message, with some margin.
+const MAX_MESSAGE: usize = 4000;
+
+/// Stop flags of the running streams, by stream id.
+static STREAMS: LazyLock<Mutex<HashMap<u64, Arc<AtomicBool>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Requests a running stream to stop. Returns `false` if it already ended.
+pub fn stop(id: u64) -> bool {
+    match STREAMS.lock().unwrap().get(&id) {
+        Some(flag) => {
+            flag.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Runs `command` on `server` and keeps a single message updated with its latest output.
+///
+/// The message carries a "Stop" button that closes the SSH channel.
+pub async fn run(bot: Bot, chat_id: ChatId, server: ManagedServer, command: String, title: String) {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let flag = Arc::new(AtomicBool::new(false));
+    STREAMS.lock().unwrap().insert(id, flag.clone());
+
+    let header = format!("📡 <b>{}</b>", escape_html(&title));
+    let message = match bot
+        .send_message(chat_id, format!("{}\n<i>Waiting for output…</i>", header))
+        .parse_mode(ParseMode::Html)
+        .reply_markup(stop_keyboard(id))
+        .await
+    {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("Stream: Failed to send message: {}", e);
+            STREAMS.lock().unwrap().remove(&id);
+            return;
+        }
+    };
+
+    let (tx, mut rx) = mpsc::unbounded_channel::<Vec<u8>>();
+    let stop_flag = flag.clone();
+    let mut task = tokio::task::spawn_blocking(move || {
+        SshExecutor::stream(&server, &command, &stop_flag, |chunk| {
+            let _ = tx.send(chunk.to_vec());
+        })
+    });
+
+    let deadline = tokio::time::Instant::now() + MAX_DURATION;
+    let mut ticker = tokio::time::interval(EDIT_INTERVAL);
+    let mut output: Vec<u8> = Vec::new();
+    let mut dirty = false;
+
+    let result = loop {
+        tokio::select! {
+            Some(chunk) = rx.recv() => {
+                output.extend_from_slice(&chunk);
+                if output.len() > MAX_DISPLAY {
+                    output.drain(..output.len() - MAX_DISPLAY);
+                }
+                dirty = true;
+            }
+            _ = ticker.tick() => {
+                if tokio::time::Instant::now() >= deadline {
+                    flag.store(true, Ordering::Relaxed);
+                }
+                if dirty {
+                    dirty = false;
+                    edit(&bot, chat_id, message.id, &header, &output, None, Some(id)).await;
+                }
+            }
+            joined = &mut task => {
+                break joined.unwrap_or_else(|e| Err(format!("Stream task failed: {}", e)));
+            }
+        }
+    };
+    STREAMS.lock().unwrap().remove(&id);
+
+    // Pick up output sent right before the channel closed
+    while let Ok(chunk) = rx.try_recv() {
+        output.extend_from_slice(&chunk);
+    }
+    if output.len() > MAX_DISPLAY {
+        output.drain(..output.len() - MAX_DISPLAY);
+    }
+
+    let status = match result {
+        Ok(Some(0)) => "✅ Finished".to_string(),
+        Ok(Some(code)) => format!("⚠️ Exited with status {}", code),
+        Ok(None) => "⏹ Stopped".to_string(),
+        Err(e) => format!("❌ {}", e),
+    };
+    edit(
+        &bot,
+        chat_id,
+        message.id,
+        &header,
+        &output,
+        Some(&status),
+        None,
+    )
+    .await;
+}
+
+/// Re-renders the live message. Errors such as "message is not modified" are ignored.
+async fn edit(
+    bot: &Bot,
+    chat_id: ChatId,
+    message_id: MessageId,
+    header: &str,
+    output: &[u8],
+    status: Option<&str>,
+    stream_id: Option<u64>,
+) {
+    // Only whole lines are shown, the first one may have been cut by trimming
+    let text = String::from_utf8_lossy(output);
+    let text = match text.split_once('\n') {
+        Some((_, rest)) if output.len() >= MAX_DISPLAY => rest,
+        _ => &text,
+    };
+    let body = if text.trim().is_empty() {
+        "<i>No output yet.</i>".to_string()
+    } else {
+        format!("<pre>{}</pre>", escape_html(text.trim_end()))
+    };
+
+    let mut html = format!("{}\n{}", header, body);
+    if let Some(status) = status {
+        html.push_str(&format!("\n{}", escape_html(status)));
+    }
+
+    let mut req = bot
+        .edit_message_text(chat_id, message_id, html)
+        .parse_mode(ParseMode::Html);
+    if let Some(id) = stream_id {
+        req = req.reply_markup(stop_keyboard(id));
+    }
+    let _ = req.await;
+}
+
+fn stop_keyboard(id: u64) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::callback(
+        "⏹ Stop",
+        format!("stream_stop:{}", id),
+    )]])
+}
+
+/// Renders an AI reply into a single message, editing it as text arrives.
+///
+/// Edits are plain text and rate limited; the final response replaces the message
+/// with its HTML, or is sent as a new message when it isn't plain text (e.g. buttons).
+pub async fn render_ai(
+    bot: &Bot,
+    chat_id: ChatId,
+    events: Arc<tokio::sync::Mutex<UnboundedReceiver<StreamEvent>>>,
+) -> ResponseResult<()> {
+    let mut events = events.lock().await;
+    let message = bot.send_message(chat_id, "🤖 Thinking…").await?;
+
+    let mut ticker = tokio::time::interval(AI_EDIT_INTERVAL);
+    let mut text = String::new();
+    let mut dirty = false;
+
+    let response = loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Some(StreamEvent::Delta(delta)) => {
+                    text.push_str(&delta);
+                    dirty = true;
+                }
+                Some(StreamEvent::Reset) => {
+                    text.clear();
+                    dirty = true;
+                }
+                Some(StreamEvent::Done(response)) => break response,
+                None => break CommandResponse::Text("AI Error: the reply was interrupted.".to_string()),
+            },
+            _ = ticker.tick() => {
+                if dirty {
+                    dirty = false;
+                    let shown = if text.trim().is_empty() {
+                        "🤖 Thinking…".to_string()
+                    } else {
+                        format!("{} ▌", tail(&text))
+                    };
+                    let _ = bot.edit_message_text(chat_id, message.id, shown).await;
+                }
+            }
+        }
+    };
+
+    let (body, parse_mode) = match &response {
+        CommandResponse::Html(html) => (html.clone(), Some(ParseMode::Html)),
+        CommandResponse::Text(text) => (text.clone(), None),
+        _ => (String::new(), None),
+    };
+    if !body.trim().is_empty() && body.len() <= MAX_MESSAGE {
+        let mut req = bot.edit_message_text(chat_id, message.id, body);
+        if let Some(mode) = parse_mode {
+            req = req.parse_mode(mode);
+        }
+        if req.await.is_ok() {
+            return Ok(());
+        }
+    }
+
+    let _ = bot.delete_message(chat_id, message.id).await;
+    Box::pin(handle_command_response(bot, chat_id, response)).await
+}
+
+/// The end of `text` that fits in a message, cut on a character boundary.
+fn tail(text: &str) -> &str {
+    let limit = MAX_MESSAGE - 100;
+    if text.len() <= limit {
+        return text;
+    }
+    let mut start = text.len() - limit;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    &text[start..]
+}
+
+/// Shows an investigation: one message with its progress, edited as it goes and
+/// carrying a "Stop" button, a message with buttons for each step that needs
+/// approval, and the findings report at the end.
+pub async fn render_agent(
+    bot: &Bot,
+    chat_id: ChatId,
+    id: u64,
+    events: Arc<tokio::sync::Mutex<UnboundedReceiver<AgentEvent>>>,
+) -> ResponseResult<()> {
+    let mut events = events.lock().await;
+    let message = bot
+        .send_message(chat_id, "🕵️ Starting the investigation…")
+        .reply_markup(agent_keyboard(id, false))
+        .await?;
+
+    let mut ticker = tokio::time::interval(AI_EDIT_INTERVAL);
+    let mut progress: Option<String> = None;
+
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Some(AgentEvent::Progress(html)) => progress = Some(html),
+                Some(AgentEvent::Approval(html)) => {
+                    bot.send_message(chat_id, html)
+                        .parse_mode(ParseMode::Html)
+                        .reply_markup(agent_keyboard(id, true))
+                        .await?;
+                }
+                Some(AgentEvent::Done(report)) => {
+                    // The last progress has no buttons left
+                    if let Some(html) = progress.take() {
+                        let _ = bot
+                            .edit_message_text(chat_id, message.id, html)
+                            .parse_mode(ParseMode::Html)
+                            .await;
+                    }
+                    // Not through handle_command_response, which spawns this task
+                    return send_long_message(bot, chat_id, report, Some(ParseMode::Html)).await;
+                }
+                None => return Ok(()),
+            },
+            _ = ticker.tick() => {
+                if let Some(html) = progress.take() {
+                    let _ = bot
+                        .edit_message_text(chat_id, message.id, html)
+                        .parse_mode(ParseMode::Html)
+                        .reply_markup(agent_keyboard(id, false))
+                        .await;
+                }
+            }
+        }
+    }
+}
+
+/// "Stop" for the progress message, "Run"/"Skip"/"Stop" for an approval request.
+fn agent_keyboard(id: u64, approval: bool) -> InlineKeyboardMarkup {
+    let mut row = Vec::new();
+    if approval {
+        row.push(InlineKeyboardButton::callback(
+            "✅ Run",
+            format!("agent:{}:run", id),
+        ));
+        row.push(InlineKeyboardButton::callback(
+            "⏭ Skip",
+            format!("agent:{}:skip", id),
+        ));
+    }
+    row.push(InlineKeyboardButton::callback(
+        "⏹ Stop",
+        format!("agent:{}:stop", id),
+    ));
+    InlineKeyboardMarkup::new(vec![row])
+}
@@ -5,7 +5,12 @@ use crate::models::CommandResponse;
 use base64::prelude::*;
 use std::env;
 use teloxide::prelude::*;
-use teloxide::types::{InlineKeyboardButton, InlineKeyboardMarkup, ParseMode};
+use teloxide::types::{
+    CopyTextButton, InlineKeyboardButton, InlineKeyboardMarkup, InputFile, ParseMode,
+};
+
+/// Longest text Telegram accepts in a callback answer, in characters.
+const MAX_CALLBACK_TEXT: usize = 200;
 
 pub async fn start_bot(pool: crate::db::DbPool, session_manager: SessionManager) {
     let bot = Bot::from_env();
@@ -20,6 +25,8 @@ pub async fn start_bot(pool: crate::db::DbPool, session_manager: SessionManager)
         admin_id
     );
 
+    crate::handlers::scheduler::spawn_jobs(bot.clone(), pool.clone(), admin_id);
+
     let handler = dptree::entry()
         .branch(Update::filter_message().endpoint(message_handler))
         .branch(Update::filter_callback_query().endpoint(callback_handler));
@@ -93,12 +100,29 @@ async fn callback_handler(
     q: CallbackQuery,
     pool: crate::db::DbPool,
     session_manager: SessionManager,
+    admin_id: i64,
 ) -> ResponseResult<()> {
     let data = match q.data.clone() {
         Some(d) => d,
         None => return Ok(()),
     };
 
+    // Security check: callback data can be forged, and escalated alerts reach other
+    // chats whose members may only acknowledge them
+    if i64::try_from(q.from.id.0) != Ok(admin_id) {
+        let in_escalation_chat = match &q.message {
+            Some(msg) => crate::core::alerts::EscalationPolicy::load(&pool)
+                .await
+                .chat_ids
+                .contains(&msg.chat().id.0),
+            None => false,
+        };
+        if !(in_escalation_chat && data.starts_with("alert_ack:")) {
+            bot.answer_callback_query(q.id).text("Not allowed").await?;
+            return Ok(());
+        }
+    }
+
     if let Some(model) = data.strip_prefix("set_model:") {
         handle_set_model(bot, q, pool, session_manager, model).await
     } else if let Some(alias) = data.strip_prefix("menu_server:") {
@@ -112,7 +136,19 @@ async fn callback_handler(
     } else if let Some(alias) = data.strip_prefix("act_remove:") {
         handle_action_remove(bot, q, pool, session_manager, alias).await
     } else if let Some(rest) = data.strip_prefix("tool_run:") {
-        handle_tool_run(bot, q, session_manager, rest).await
+        handle_tool_run(bot, q, pool, session_manager, rest).await
+    } else if let Some(id) = data.strip_prefix("alert_ack:") {
+        handle_alert_ack(bot, q, pool, session_manager, id).await
+    } else if let Some(rest) = data.strip_prefix("exec_force:") {
+        handle_exec_force(bot, q, pool, session_manager, rest).await
+    } else if let Some(id) = data.strip_prefix("stream_stop:") {
+        handle_stream_stop(bot, q, id).await
+    } else if let Some(rest) = data.strip_prefix("plan:") {
+        handle_plan(bot, q, pool, session_manager, rest).await
+    } else if let Some(rest) = data.strip_prefix("agent:") {
+        handle_agent_decision(bot, q, rest).await
+    } else if let Some(rest) = data.strip_prefix("howto:") {
+        handle_howto(bot, q, pool, session_manager, rest).await
     } else {
         Ok(())
     }
@@ -127,7 +163,9 @@ async fn handle_set_model(
     session_manager: SessionManager,
     model: &str,
 ) -> ResponseResult<()> {
-    use crate::ai::config::{GeminiConfig, GlobalConfig, OllamaConfig, OpenAiConfig};
+    use crate::ai::config::{
+        AnthropicConfig, GeminiConfig, GlobalConfig, OllamaConfig, OpenAiConfig, ProviderProfile,
+    };
 
     let global_config = GlobalConfig::load(&pool).await;
     // Use same provider detection logic as AiClient::new
@@ -149,11 +187,27 @@ async fn handle_set_model(
             config.model = model.to_string();
             config.save(&pool).await
         }
-        _ => {
+        "anthropic" => {
+            let mut config = AnthropicConfig::load(&pool).await;
+            config.model = model.to_string();
+            config.save(&pool).await
+        }
+        "ollama" => {
             let mut config = OllamaConfig::load(&pool).await;
             config.model = model.to_string();
             config.save(&pool).await
         }
+        profile_name => match ProviderProfile::load(&pool, profile_name).await {
+            Some(mut profile) => {
+                profile.model = model.to_string();
+                profile.save(&pool).await
+            }
+            None => {
+                let mut config = OllamaConfig::load(&pool).await;
+                config.model = model.to_string();
+                config.save(&pool).await
+            }
+        },
     };
 
     let result_msg = match result {
@@ -291,9 +345,320 @@ async fn handle_action_remove(
     handle_command_response(&bot, chat_id, response).await
 }
 
+async fn handle_alert_ack(
+    bot: Bot,
+    q: CallbackQuery,
+    pool: crate::db::DbPool,
+    session_manager: SessionManager,
+    id: &str,
+) -> ResponseResult<()> {
+    let chat_id = match q.message {
+        Some(ref msg) => msg.chat().id,
+        None => return Ok(()),
+    };
+    let id = match id.parse() {
+        Ok(id) => id,
+        Err(_) => return Ok(()),
+    };
+
+    bot.answer_callback_query(q.id).await?;
+    let command = SystemCommand::AckAlert { id };
+    let response = dispatcher::dispatch(chat_id.0, command, pool, session_manager).await;
+    handle_command_response(&bot, chat_id, response).await
+}
+
+async fn handle_exec_force(
+    bot: Bot,
+    q: CallbackQuery,
+    pool: crate::db::DbPool,
+    session_manager: SessionManager,
+    rest: &str,
+) -> ResponseResult<()> {
+    let chat_id = match q.message {
+        Some(ref msg) => msg.chat().id,
+        None => return Ok(()),
+    };
+    let (id, action) = match rest.split_once(':') {
+        Some((id, action)) => match id.parse::<u64>() {
+            Ok(id) => (id, action),
+            Err(_) => return Ok(()),
+        },
+        None => return Ok(()),
+    };
+
+    let held = match crate::core::maintenance::take_held(id) {
+        Some(held) if action == "run" => held,
+        Some(_) => {
+            bot.answer_callback_query(q.id).text("Cancelled").await?;
+            return Ok(());
+        }
+        None => {
+            bot.answer_callback_query(q.id)
+                .text("This command has expired, send it again with /exec")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    bot.answer_callback_query(q.id)
+        .text("Running anyway…")
+        .await?;
+    let command = SystemCommand::Exec {
+        alias: held.alias,
+        cmd: held.cmd,
+        force: true,
+        stream: held.stream,
+    };
+    let response = dispatcher::dispatch(chat_id.0, command, pool, session_manager).await;
+    handle_command_response(&bot, chat_id, response).await
+}
+
+async fn handle_stream_stop(bot: Bot, q: CallbackQuery, id: &str) -> ResponseResult<()> {
+    let stopped = id
+        .parse()
+        .map(crate::handlers::stream::stop)
+        .unwrap_or(false);
+    let text = if stopped {
+        "Stopping…"
+    } else {
+        "Already finished"
+    };
+    bot.answer_callback_query(q.id).text(text).await?;
+    Ok(())
+}
+
+async fn handle_agent_decision(bot: Bot, q: CallbackQuery, rest: &str) -> ResponseResult<()> {
+    use crate::core::agent::{decide, Decision};
+
+    let (id, decision) = match rest.split_once(':') {
+        Some((id, "run")) => (id, Decision::Run),
+        Some((id, "skip")) => (id, Decision::Skip),
+        Some((id, "stop")) => (id, Decision::Stop),
+        _ => return Ok(()),
+    };
+    let accepted = id.parse().map(|id| decide(id, decision)).unwrap_or(false);
+    let text = match (accepted, decision) {
+        (false, _) => "Nothing to decide anymore",
+        (true, Decision::Run) => "Running…",
+        (true, Decision::Skip) => "Skipped",
+        (true, Decision::Stop) => "Stopping…",
+    };
+    bot.answer_callback_query(q.id).text(text).await?;
+
+    // Approval prompts are answered once
+    if accepted && decision != Decision::Stop {
+        if let Some(msg) = q.message {
+            let _ = bot.edit_message_reply_markup(msg.chat().id, msg.id()).await;
+        }
+    }
+    Ok(())
+}
+
+/// `Running: <command>`, shortened to fit a callback answer.
+fn running_text(command: &str) -> String {
+    let text = format!("Running: {}", command);
+    if text.chars().count() <= MAX_CALLBACK_TEXT {
+        return text;
+    }
+    let mut short: String = text.chars().take(MAX_CALLBACK_TEXT - 1).collect();
+    short.push('…');
+    short
+}
+
+async fn handle_howto(
+    bot: Bot,
+    q: CallbackQuery,
+    pool: crate::db::DbPool,
+    session_manager: SessionManager,
+    rest: &str,
+) -> ResponseResult<()> {
+    use crate::core::howto::{proposal, Risk};
+
+    let msg = match q.message {
+        Some(ref msg) => msg,
+        None => return Ok(()),
+    };
+    let chat_id = msg.chat().id;
+    let (id, action) = match rest.split_once(':') {
+        Some((id, action)) => match id.parse::<u64>() {
+            Ok(id) => (id, action),
+            Err(_) => return Ok(()),
+        },
+        None => return Ok(()),
+    };
+    let proposal = match proposal(id) {
+        Some(proposal) => proposal,
+        None => {
+            bot.answer_callback_query(q.id)
+                .text("This proposal has expired, ask again with /howto")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    match action {
+        "cancel" => {
+            bot.answer_callback_query(q.id).text("Cancelled").await?;
+            let _ = bot.edit_message_reply_markup(chat_id, msg.id()).await;
+            Ok(())
+        }
+        // Destructive commands need a second tap
+        "run" if proposal.risk == Risk::Destructive => {
+            bot.answer_callback_query(q.id).await?;
+            let response = CommandResponse::Buttons {
+                title: format!(
+                    "🔴 <code>{}</code> is rated destructive. Run it on <b>{}</b> anyway?",
+                    crate::core::session::escape_html(&proposal.command),
+                    crate::core::session::escape_html(&proposal.alias)
+                ),
+                buttons: vec![
+                    ("⚠️ Run anyway".to_string(), format!("howto:{}:confirm", id)),
+                    ("❌ Cancel".to_string(), format!("howto:{}:cancel", id)),
+                ],
+            };
+            handle_command_response(&bot, chat_id, response).await
+        }
+        "run" | "confirm" => {
+            bot.answer_callback_query(q.id)
+                .text(running_text(&proposal.command))
+                .await?;
+            if action == "confirm" {
+                let _ = bot.edit_message_reply_markup(chat_id, msg.id()).await;
+            }
+            // Runs like /exec, so the output stays out of any session
+            let command = SystemCommand::Exec {
+                alias: proposal.alias,
+                cmd: proposal.command,
+                force: false,
+                stream: false,
+            };
+            let response = dispatcher::dispatch(chat_id.0, command, pool, session_manager).await;
+            handle_command_response(&bot, chat_id, response).await
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn handle_plan(
+    bot: Bot,
+    q: CallbackQuery,
+    pool: crate::db::DbPool,
+    session_manager: SessionManager,
+    rest: &str,
+) -> ResponseResult<()> {
+    let msg = match q.message {
+        Some(ref msg) => msg,
+        None => return Ok(()),
+    };
+    let chat_id = msg.chat().id;
+    let (id, action) = match rest.split_once(':') {
+        Some((id, action)) => match id.parse::<u64>() {
+            Ok(id) => (id, action),
+            Err(_) => return Ok(()),
+        },
+        None => return Ok(()),
+    };
+    let plan = match session_manager.pending_plan(chat_id.0, id) {
+        Some(plan) => plan,
+        None => {
+            bot.answer_callback_query(q.id)
+                .text("This plan is no longer pending")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    if let Some(index) = action.strip_prefix('t').and_then(|i| i.parse().ok()) {
+        bot.answer_callback_query(q.id).await?;
+        if let Some(plan) = session_manager.toggle_plan_step(chat_id.0, id, index) {
+            let _ = bot
+                .edit_message_reply_markup(chat_id, msg.id())
+                .reply_markup(keyboard(plan.buttons()))
+                .await;
+        }
+        return Ok(());
+    }
+
+    if action == "skip" {
+        session_manager.take_plan(chat_id.0, id);
+        bot.answer_callback_query(q.id).text("Cancelled").await?;
+        let _ = bot.edit_message_reply_markup(chat_id, msg.id()).await;
+        bot.send_message(chat_id, "Plan skipped.").await?;
+        session_manager
+            .add_message(chat_id.0, "user", "I skipped the proposed plan.")
+            .await;
+        return Ok(());
+    }
+
+    let selected: Vec<&str> = plan
+        .steps
+        .iter()
+        .filter(|s| s.selected)
+        .map(|s| s.command.as_str())
+        .collect();
+    if selected.is_empty() {
+        bot.answer_callback_query(q.id)
+            .text("Select at least one step, or Skip")
+            .await?;
+        return Ok(());
+    }
+
+    // Servers under maintenance need a second tap on "Confirm"
+    if action == "run" {
+        let maintenance = crate::core::maintenance::MaintenanceManager::new(pool);
+        if maintenance.requires_confirmation().await {
+            if let Some(window) = maintenance.active_for(&plan.alias).await {
+                bot.answer_callback_query(q.id).await?;
+                let response = CommandResponse::Buttons {
+                    title: format!(
+                        "🔧 <b>{}</b> is under maintenance until {} UTC. Confirm running {} plan steps?",
+                        crate::core::session::escape_html(&plan.alias),
+                        window.ends_at.format("%Y-%m-%d %H:%M"),
+                        selected.len()
+                    ),
+                    buttons: vec![
+                        ("Confirm".to_string(), format!("plan:{}:confirm", id)),
+                        ("❌ Skip".to_string(), format!("plan:{}:skip", id)),
+                    ],
+                };
+                return handle_command_response(&bot, chat_id, response).await;
+            }
+        }
+    } else if action != "confirm" {
+        return Ok(());
+    }
+
+    let plan = match session_manager.take_plan(chat_id.0, id) {
+        Some(plan) => plan,
+        None => return Ok(()),
+    };
+    bot.answer_callback_query(q.id)
+        .text(format!("Running {} steps", selected.len()))
+        .await?;
+    let _ = bot.edit_message_reply_markup(chat_id, msg.id()).await;
+    bot.send_message(
+        chat_id,
+        format!(
+            "⏳ Executing {} steps in order:\n{}",
+            selected.len(),
+            selected
+                .iter()
+                .enumerate()
+                .map(|(i, c)| format!("{}. {}", i + 1, c))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ),
+    )
+    .await?;
+
+    let response = session_manager.execute_plan(chat_id.0, plan).await;
+    handle_command_response(&bot, chat_id, response).await
+}
+
 async fn handle_tool_run(
     bot: Bot,
     q: CallbackQuery,
+    pool: crate::db::DbPool,
     session_manager: SessionManager,
     rest: &str,
 ) -> ResponseResult<()> {
@@ -334,6 +699,32 @@ async fn handle_tool_run(
         }
     };
 
+    // Servers under maintenance need a second tap on "Confirm"
+    if action == "✅ Run" {
+        let maintenance = crate::core::maintenance::MaintenanceManager::new(pool);
+        let chat_id = q.message.as_ref().map(|m| m.chat().id);
+        if let Some((chat_id, alias)) =
+            chat_id.and_then(|id| session_manager.get_alias(id.0).map(|a| (id, a)))
+        {
+            if maintenance.requires_confirmation().await {
+                if let Some(window) = maintenance.active_for(&alias).await {
+                    bot.answer_callback_query(q.id.clone()).await?;
+                    let response = CommandResponse::InteractiveList {
+                        title: format!(
+                            "🔧 <b>{}</b> is under maintenance until {} UTC. Confirm running <code>{}</code>?",
+                            crate::core::session::escape_html(&alias),
+                            window.ends_at.format("%Y-%m-%d %H:%M"),
+                            crate::core::session::escape_html(&cmd)
+                        ),
+                        options: vec!["Confirm".to_string(), "❌ Skip".to_string()],
+                        callback_prefix: format!("tool_run:{}:", encoded),
+                    };
+                    return handle_command_response(&bot, chat_id, response).await;
+                }
+            }
+        }
+    }
+
     // Execute Command
     bot.answer_callback_query(q.id)
         .text(format!("Running: {}", cmd))
@@ -353,7 +744,7 @@ async fn handle_tool_run(
 
 // --- Response Helpers ---
 
-async fn handle_command_response(
+pub async fn handle_command_response(
     bot: &Bot,
     chat_id: ChatId,
     response: CommandResponse,
@@ -398,11 +789,86 @@ async fn handle_command_response(
                 .reply_markup(keyboard)
                 .await?;
         }
+        CommandResponse::Buttons { title, buttons } => {
+            bot.send_message(chat_id, title)
+                .parse_mode(ParseMode::Html)
+                .reply_markup(keyboard(buttons))
+                .await?;
+        }
+        CommandResponse::Proposal {
+            title,
+            command,
+            run_data,
+        } => {
+            let mut row = vec![InlineKeyboardButton::callback("▶️ Run", run_data)];
+            // Telegram copies at most 256 characters
+            if command.chars().count() <= 256 {
+                row.push(InlineKeyboardButton::copy_text_button(
+                    "📋 Copy",
+                    CopyTextButton { text: command },
+                ));
+            }
+            bot.send_message(chat_id, title)
+                .parse_mode(ParseMode::Html)
+                .reply_markup(InlineKeyboardMarkup::new(vec![row]))
+                .await?;
+        }
+        CommandResponse::Photo { data, caption } => {
+            bot.send_photo(chat_id, InputFile::memory(data).file_name("chart.png"))
+                .caption(caption)
+                .await?;
+        }
+        CommandResponse::Document {
+            file_name,
+            data,
+            caption,
+        } => {
+            bot.send_document(chat_id, InputFile::memory(data).file_name(file_name))
+                .caption(caption)
+                .await?;
+        }
+        CommandResponse::AiStream(events) => {
+            crate::handlers::stream::render_ai(bot, chat_id, events).await?;
+        }
+        CommandResponse::Stream {
+            server,
+            command,
+            title,
+        } => {
+            // Run in the background so updates (like the Stop button) keep being handled
+            tokio::spawn(crate::handlers::stream::run(
+                bot.clone(),
+                chat_id,
+                server,
+                command,
+                title,
+            ));
+        }
+        CommandResponse::Agent { id, events } => {
+            // Runs in the background so the approval buttons keep being handled
+            let bot = bot.clone();
+            tokio::spawn(async move {
+                if let Err(e) =
+                    crate::handlers::stream::render_agent(&bot, chat_id, id, events).await
+                {
+                    eprintln!("Investigate: Failed to send progress: {}", e);
+                }
+            });
+        }
     }
     Ok(())
 }
 
-async fn send_long_message(
+/// One button per row, given as `(label, callback data)`.
+fn keyboard(buttons: Vec<(String, String)>) -> InlineKeyboardMarkup {
+    let rows: Vec<Vec<InlineKeyboardButton>> = buttons
+        .into_iter()
+        .map(|(label, data)| vec![InlineKeyboardButton::callback(label, data)])
+        .collect();
+    InlineKeyboardMarkup::new(rows)
+}
+
+pub async fn send_long_message(
     bot: &Bot,
     chat_id: ChatId,
     text: String,
@@ -0,0 +1,28 @@
+use crate::core::alerts::Alert;
+use reqwest::Client;
+use serde_json::json;
+use std::time::Duration;
+
+/// Posts an escalated alert to an outgoing webhook as JSON.
+///
+/// The payload carries a plain `text` field (understood by Slack/Mattermost style
+/// incoming webhooks) alongside the full alert for custom receivers.
+pub async fn post_alert(url: &str, alert: &Alert) -> Result<(), String> {
+    let body = json!({
+        "text": format!("🚨 [PocketSentinel] Unacknowledged alert #{}: {}", alert.id, alert.message),
+        "alert": alert,
+    });
+
+    let res = Client::new()
+        .post(url)
+        .timeout(Duration::from_secs(10))
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| format!("Request failed: {}", e))?;
+
+    if !res.status().is_success() {
+        return Err(format!("Webhook Error: {}", res.status()));
+    }
+    Ok(())
+}
@@ -1,4 +1,6 @@
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::mpsc::UnboundedReceiver;
 
 /// Represents a server managed by the PocketSentinel agent.
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -19,7 +21,6 @@ pub struct ManagedServer {
 
 /// Defines the status of a specific task within the agent's workflow.
 #[derive(Debug, Serialize, Deserialize, Clone)]
-#[allow(dead_code)]
 pub enum TaskStatus {
     Pending,
     Executing,
@@ -29,7 +30,6 @@ pub enum TaskStatus {
 
 /// The core instruction generated by the AI reasoning layer.
 #[derive(Debug, Serialize, Deserialize, Clone)]
-#[allow(dead_code)]
 pub struct AgentTask {
     pub task_id: String,
     pub server_id: String,
@@ -49,4 +49,62 @@ pub enum CommandResponse {
         options: Vec<String>,
         callback_prefix: String,
     },
+    /// An HTML message with one button per row, given as `(label, callback data)`.
+    Buttons {
+        title: String,
+        buttons: Vec<(String, String)>,
+    },
+    /// An HTML message proposing a command, with a button to run it (sending
+    /// `run_data`) and one that copies it.
+    Proposal {
+        title: String,
+        command: String,
+        run_data: String,
+    },
+    /// A PNG image sent as a photo with a plain-text caption.
+    Photo {
+        data: Vec<u8>,
+        caption: String,
+    },
+    /// A file sent as a document with a plain-text caption.
+    Document {
+        file_name: String,
+        data: Vec<u8>,
+        caption: String,
+    },
+    /// An AI reply that is rendered into one message as it is generated.
+    AiStream(Arc<tokio::sync::Mutex<UnboundedReceiver<StreamEvent>>>),
+    /// A long-running command whose output is streamed into a single, regularly edited message.
+    Stream {
+        server: ManagedServer,
+        command: String,
+        title: String,
+    },
+    /// A running investigation, shown as a live progress message with approval prompts.
+    Agent {
+        id: u64,
+        events: Arc<tokio::sync::Mutex<UnboundedReceiver<AgentEvent>>>,
+    },
+}
+
+/// Progress of an AI reply streamed into the chat.
+#[derive(Debug)]
+pub enum StreamEvent {
+    /// A piece of text generated by the model.
+    Delta(String),
+    /// The text so far was replaced by tool calls; start over.
+    Reset,
+    /// The final response, replacing the streamed text.
+    Done(CommandResponse),
+}
+
+/// Progress of an investigation run by the agent.
+#[derive(Debug)]
+pub enum AgentEvent {
+    /// The current state of the investigation, as HTML.
+    Progress(String),
+    /// A step that changes the server waits for the user, described as HTML.
+    Approval(String),
+    /// The final findings report, as HTML.
+    Done(String),
 }
@@ -21,11 +21,33 @@ pub enum SystemCommand {
     /// Lists all configured servers.
     ListServers,
     /// Executes a shell command on a specific server.
-    Exec { alias: String, cmd: String },
+    ///
+    /// `force` skips the maintenance window confirmation, `stream` shows the output live.
+    Exec {
+        alias: String,
+        cmd: String,
+        force: bool,
+        stream: bool,
+    },
+    /// Follows a log file or a systemd unit's journal.
+    Tail { alias: String, target: String },
     /// Asks the AI a question.
     Ask { question: String },
     /// Sets the active AI provider.
     SetProvider { provider: Option<String> },
+    /// Creates or replaces a named provider profile.
+    AddProviderProfile {
+        name: String,
+        kind: String,
+        base_url: String,
+        model: String,
+        api_key: Option<String>,
+        headers: Vec<String>,
+    },
+    /// Deletes a named provider profile.
+    RemoveProviderProfile { name: String },
+    /// Sets the providers tried in order when the current one fails (empty to disable).
+    SetFallbacks { providers: Vec<String> },
     /// Sets the API key for a specific AI provider.
     SetApiKey { provider: String, key: String },
     /// Configures the API key interactively.
@@ -35,18 +57,84 @@ pub enum SystemCommand {
         model: String,
         base_url: Option<String>,
     },
+    /// Shows or sets the generation parameters of a provider, optionally for one purpose.
+    ConfigAi {
+        provider: Option<String>,
+        args: Vec<String>,
+    },
     /// Lists available AI models from the current provider.
     ListAiModels,
     /// Shows information about the current AI provider.
     AiInfo,
     /// Runs a discovery process on a server to gather system info.
     Discover { alias: String },
-    /// Counts the estimated tokens in the provided text.
+    /// Renders a metric history chart for one or more servers.
+    Chart {
+        aliases: Vec<String>,
+        metric: String,
+        range: String,
+    },
+    /// Runs the fleet digest now or changes its schedule.
+    Digest {
+        action: Option<String>,
+        value: Option<String>,
+    },
+    /// Lists active and scheduled maintenance windows.
+    ListMaintenance,
+    /// Puts servers under maintenance for a duration, optionally starting later.
+    StartMaintenance {
+        aliases: Vec<String>,
+        duration: String,
+        start: Option<String>,
+        reason: Option<String>,
+    },
+    /// Ends (or cancels) the maintenance windows of servers.
+    EndMaintenance { aliases: Vec<String> },
+    /// Enables or disables the extra confirmation for commands under maintenance.
+    MaintenanceConfirm { enabled: bool },
+    /// Lists open alerts or changes the health check rules.
+    Alerts {
+        action: Option<String>,
+        value: Option<String>,
+    },
+    /// Acknowledges a firing alert, stopping its escalation.
+    AckAlert { id: i64 },
+    /// Shows or changes the alert escalation policy.
+    Escalation {
+        action: Option<String>,
+        value: Option<String>,
+    },
+    /// Shows AI token usage and cost, or changes the daily cap and model prices.
+    Usage {
+        action: Option<String>,
+        args: Vec<String>,
+    },
+    /// Shows or changes the redaction of secrets sent to AI providers, or tests it on a text.
+    Redact {
+        action: Option<String>,
+        target: Option<String>,
+        /// The rest of the input with its line breaks kept.
+        text: Option<String>,
+    },
+    /// Lists, shows, overrides or resets the AI prompt templates, or edits server notes.
+    Prompt {
+        action: Option<String>,
+        target: Option<String>,
+        /// The rest of the input with its line breaks kept.
+        text: Option<String>,
+    },
+    /// Counts the tokens in the provided text, flagging estimates.
     CountTokens { text: String },
+    /// Shows how much of the model's context window the current session uses.
+    Context,
     /// Provides a comprehensive explanation of the software and its architecture.
     Explain,
-    /// Starts an interactive troubleshooting session with the AI.
-    Investigate { alias: String },
+    /// Lets the AI investigate a goal on a server on its own, step by step.
+    Investigate { alias: String, goal: String },
+    /// Asks the AI for an explained, risk-rated command that does a task on a server.
+    HowTo { alias: String, task: String },
+    /// Writes a post-mortem of the last session, or lists and resends stored ones.
+    Report { action: Option<String> },
     /// Ends the current interactive session.
     EndSession,
     /// Represents an unrecognized or invalid command.
@@ -89,6 +177,31 @@ impl SystemCommand {
             }
 
             ["/provider"] | ["/set_provider"] => SystemCommand::SetProvider { provider: None },
+            ["/provider", "add", name, kind, base_url, model, rest @ ..] => {
+                SystemCommand::AddProviderProfile {
+                    name: name.to_string(),
+                    kind: kind.to_lowercase(),
+                    base_url: base_url.to_string(),
+                    model: model.to_string(),
+                    api_key: rest.first().filter(|k| **k != "-").map(|k| k.to_string()),
+                    headers: rest.iter().skip(1).map(|h| h.to_string()).collect(),
+                }
+            }
+            ["/provider", "remove", name] => SystemCommand::RemoveProviderProfile {
+                name: name.to_string(),
+            },
+            ["/provider", "fallback"] => SystemCommand::SetProvider { provider: None },
+            ["/provider", "fallback", "off"] => SystemCommand::SetFallbacks {
+                providers: Vec::new(),
+            },
+            ["/provider", "fallback", names @ ..] => SystemCommand::SetFallbacks {
+                providers: names
+                    .iter()
+                    .flat_map(|n| n.split(','))
+                    .filter(|n| !n.is_empty())
+                    .map(|n| n.to_string())
+                    .collect(),
+            },
             ["/provider", name] | ["/set_provider", name] => SystemCommand::SetProvider {
                 provider: Some(name.to_string()),
             },
@@ -112,6 +225,16 @@ impl SystemCommand {
                 base_url: Some(base_url.to_string()),
             },
 
+            // /config_ai [provider [purpose] <param> <value...|off>]
+            ["/config_ai"] => SystemCommand::ConfigAi {
+                provider: None,
+                args: Vec::new(),
+            },
+            ["/config_ai", provider, args @ ..] => SystemCommand::ConfigAi {
+                provider: Some(provider.to_string()),
+                args: args.iter().map(|a| a.to_string()).collect(),
+            },
+
             ["/models"] | ["/ai_models"] => SystemCommand::ListAiModels,
 
             ["/current_model"] | ["/ai_info"] => SystemCommand::AiInfo,
@@ -120,23 +243,183 @@ impl SystemCommand {
                 alias: alias.to_string(),
             },
 
+            // /chart <alias[,alias...]> <cpu|mem|disk> [24h|7d]
+            ["/chart", aliases, metric] => SystemCommand::Chart {
+                aliases: aliases.split(',').map(|a| a.to_string()).collect(),
+                metric: metric.to_string(),
+                range: "24h".to_string(),
+            },
+            ["/chart", aliases, metric, range] => SystemCommand::Chart {
+                aliases: aliases.split(',').map(|a| a.to_string()).collect(),
+                metric: metric.to_string(),
+                range: range.to_string(),
+            },
+
+            // /digest [status|daily|weekly [day]|off|time HH:MM|tz <zone>|quiet HH:MM-HH:MM|ai on|off]
+            ["/digest"] => SystemCommand::Digest {
+                action: None,
+                value: None,
+            },
+            ["/digest", action] => SystemCommand::Digest {
+                action: Some(action.to_string()),
+                value: None,
+            },
+            ["/digest", action, value] => SystemCommand::Digest {
+                action: Some(action.to_string()),
+                value: Some(value.to_string()),
+            },
+
+            ["/maintenance"] => SystemCommand::ListMaintenance,
+            ["/maintenance", "confirm", "on"] => {
+                SystemCommand::MaintenanceConfirm { enabled: true }
+            }
+            ["/maintenance", "confirm", "off"] => {
+                SystemCommand::MaintenanceConfirm { enabled: false }
+            }
+            ["/maintenance", "end", aliases @ ..] if !aliases.is_empty() => {
+                SystemCommand::EndMaintenance {
+                    aliases: aliases.iter().map(|a| a.to_string()).collect(),
+                }
+            }
+            // /maintenance <alias...> <duration> [@start] [reason]
+            ["/maintenance", rest @ ..] => {
+                // Checked loosely so an oversized duration gets its own error
+                let is_duration = |p: &&str| {
+                    p.strip_suffix(['m', 'h', 'd', 'M', 'H', 'D'])
+                        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+                };
+                match rest.iter().position(is_duration) {
+                    Some(idx) if idx > 0 => {
+                        let mut tail = &rest[idx + 1..];
+                        let start = match tail.first() {
+                            Some(s) if s.starts_with('@') => {
+                                tail = &tail[1..];
+                                Some(s.trim_start_matches('@').to_string())
+                            }
+                            _ => None,
+                        };
+                        SystemCommand::StartMaintenance {
+                            aliases: rest[..idx].iter().map(|a| a.to_string()).collect(),
+                            duration: rest[idx].to_string(),
+                            start,
+                            reason: if tail.is_empty() {
+                                None
+                            } else {
+                                Some(tail.join(" "))
+                            },
+                        }
+                    }
+                    _ => SystemCommand::Unknown,
+                }
+            }
+
+            ["/alerts"] => SystemCommand::Alerts {
+                action: None,
+                value: None,
+            },
+            ["/alerts", action, value] => SystemCommand::Alerts {
+                action: Some(action.to_string()),
+                value: Some(value.to_string()),
+            },
+            ["/ack", id] => match id.trim_start_matches('#').parse() {
+                Ok(id) => SystemCommand::AckAlert { id },
+                Err(_) => SystemCommand::Unknown,
+            },
+            ["/escalation"] => SystemCommand::Escalation {
+                action: None,
+                value: None,
+            },
+            ["/escalation", action, value] => SystemCommand::Escalation {
+                action: Some(action.to_string()),
+                value: Some(value.to_string()),
+            },
+
+            ["/usage"] => SystemCommand::Usage {
+                action: None,
+                args: Vec::new(),
+            },
+            ["/usage", action, args @ ..] => SystemCommand::Usage {
+                action: Some(action.to_string()),
+                args: args.iter().map(|a| a.to_string()).collect(),
+            },
+
+            ["/prompt"] => SystemCommand::Prompt {
+                action: None,
+                target: None,
+                text: None,
+            },
+            ["/prompt", action] => SystemCommand::Prompt {
+                action: Some(action.to_string()),
+                target: None,
+                text: None,
+            },
+            ["/prompt", action, target, ..] => SystemCommand::Prompt {
+                action: Some(action.to_string()),
+                target: Some(target.to_string()),
+                text: Some(raw_tail(input, 3)).filter(|t| !t.is_empty()),
+            },
+
+            ["/redact"] => SystemCommand::Redact {
+                action: None,
+                target: None,
+                text: None,
+            },
+            ["/redact", "test", ..] => SystemCommand::Redact {
+                action: Some("test".to_string()),
+                target: None,
+                text: Some(raw_tail(input, 2)).filter(|t| !t.is_empty()),
+            },
+            ["/redact", action, target, ..] => SystemCommand::Redact {
+                action: Some(action.to_string()),
+                target: Some(target.to_string()),
+                text: Some(raw_tail(input, 3)).filter(|t| !t.is_empty()),
+            },
+
+            ["/exec", "-f", alias, _, ..] => {
+                let cmd = parts[3..].join(" ");
+                SystemCommand::Exec {
+                    alias: alias.to_string(),
+                    cmd,
+                    force: false,
+                    stream: true,
+                }
+            }
             ["/exec", alias, ..] => {
                 let cmd = parts[2..].join(" ");
                 SystemCommand::Exec {
                     alias: alias.to_string(),
                     cmd,
+                    force: false,
+                    stream: false,
                 }
             }
+            ["/tail", alias, target] => SystemCommand::Tail {
+                alias: alias.to_string(),
+                target: target.to_string(),
+            },
 
             ["/tokens", ..] | ["/count_tokens", ..] => {
                 let text = parts[1..].join(" ");
                 SystemCommand::CountTokens { text }
             }
 
+            ["/context"] => SystemCommand::Context,
+
             ["/explain"] | ["/about"] => SystemCommand::Explain,
 
-            ["/investigate", alias] => SystemCommand::Investigate {
+            ["/investigate", alias, goal @ ..] => SystemCommand::Investigate {
+                alias: alias.to_string(),
+                goal: goal.join(" "),
+            },
+
+            ["/howto", alias, task @ ..] if !task.is_empty() => SystemCommand::HowTo {
                 alias: alias.to_string(),
+                task: task.join(" "),
+            },
+
+            ["/report"] => SystemCommand::Report { action: None },
+            ["/report", action] => SystemCommand::Report {
+                action: Some(action.to_string()),
             },
 
             ["/exit"] | ["/stop"] | ["/end"] | ["/quit"] => SystemCommand::EndSession,
@@ -156,17 +439,105 @@ impl SystemCommand {
             ("/add <alias> <host> <user>", "Add a new server"),
             ("/remove <alias>", "Remove a server by alias"),
             ("/exec <alias> <cmd>", "Execute a shell command on a server"),
+            (
+                "/exec -f <alias> <cmd>",
+                "Execute a command and stream its output live",
+            ),
+            (
+                "/tail <alias> <file|unit>",
+                "Follow a log file or a systemd unit's journal",
+            ),
             ("/ask <question>", "Ask the AI a question"),
+            (
+                "/investigate <alias> [goal]",
+                "Let the AI investigate a problem on a server step by step",
+            ),
+            (
+                "/howto <alias> <task>",
+                "Get an explained, risk-rated command for a task on a server",
+            ),
+            (
+                "/report [list|<id>]",
+                "Write a post-mortem of the last session, or list and resend saved ones",
+            ),
             (
                 "/provider [name]",
-                "Show or set current AI provider (ollama, openai, gemini)",
+                "Show or set current AI provider (ollama, openai, gemini, anthropic or a profile)",
+            ),
+            (
+                "/provider add <name> <openai|ollama|anthropic> <base_url> <model> [api_key|-] [Header:Value...]",
+                "Add a named provider profile",
+            ),
+            ("/provider remove <name>", "Delete a provider profile"),
+            (
+                "/provider fallback <name...>|off",
+                "Set the providers tried when the current one fails",
             ),
             ("/config_key", "Set API Key interactively for a provider"),
+            (
+                "/config_ai [provider [purpose] <param> <value|off>]",
+                "Show or set temperature, max_tokens, top_p, stop, num_ctx and keep_alive",
+            ),
             ("/models", "List available AI models"),
             ("/current_model", "Show current AI provider and model"),
             ("/discover <alias>", "Analyze a server's state"),
-            ("/tokens <text>", "Count estimated tokens in text"),
+            (
+                "/chart <alias[,alias...]|all> <cpu|mem|disk> [24h|7d]",
+                "Chart metric history from discoveries",
+            ),
+            (
+                "/digest [status|daily|weekly [day]|off|time|tz|quiet|ai]",
+                "Run the fleet digest now or configure its schedule",
+            ),
+            (
+                "/maintenance [<alias...> <30m|2h|1d> [@HH:MM] [reason]]",
+                "List or schedule maintenance windows",
+            ),
+            ("/maintenance end <alias...>", "End maintenance early"),
+            (
+                "/maintenance confirm on|off",
+                "Require extra confirmation for commands under maintenance",
+            ),
+            (
+                "/alerts [disk <percent>|interval <minutes>]",
+                "List open alerts or change health check rules",
+            ),
+            ("/ack <id>", "Acknowledge an alert"),
+            (
+                "/escalation [after <minutes>|chats <id,...>|off|webhook <url>|off]",
+                "Show or change the alert escalation policy",
+            ),
+            (
+                "/usage [day|month|cap <usd|off>|price <model> <in> <out>]",
+                "Show AI token usage and cost, or set the daily cap and model prices",
+            ),
+            (
+                "/prompt [show|set|reset <name>[@provider] [text]]",
+                "List, show or override the AI prompt templates",
+            ),
+            (
+                "/redact [add <name> <regex>|remove <name>|local <provider> on|off|test <text>]",
+                "Show or configure the redaction of secrets sent to the AI",
+            ),
+            (
+                "/prompt notes <alias> [text|off]",
+                "Show or set notes about a server given to the AI",
+            ),
+            ("/tokens <text>", "Count tokens in text (exact or estimated)"),
+            ("/context", "Show token usage of the current AI session"),
             ("/explain", "Explain how this software works"),
         ]
     }
 }
+
+/// The input after its first `skip` words, keeping line breaks (unlike `split_whitespace`).
+fn raw_tail(input: &str, skip: usize) -> String {
+    let mut rest = input.trim_start();
+    for _ in 0..skip {
+        rest = match rest.find(char::is_whitespace) {
+            Some(end) => rest[end..].trim_start(),
+            None => "",
+        };
+    }
+    rest.trim_end().to_string()
+}